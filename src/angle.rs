@@ -0,0 +1,165 @@
+// Angle units for the trig functions. `mode deg` switches what sin/cos/tan
+// expect and what asin/acos/atan return; suffixes like `30deg` always win.
+
+use std::f64::consts::PI;
+use std::fmt;
+
+use crate::decimal::{self, Decimal, DecimalContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AngleMode {
+    #[default]
+    Rad,
+    Deg,
+    Grad,
+}
+
+impl AngleMode {
+    pub fn parse(name: &str) -> Option<AngleMode> {
+        match name {
+            "rad" => Some(AngleMode::Rad),
+            "deg" => Some(AngleMode::Deg),
+            "grad" => Some(AngleMode::Grad),
+            _ => None,
+        }
+    }
+
+    // How many of this unit make a half turn
+    fn half_turn(self) -> f64 {
+        match self {
+            AngleMode::Rad => PI,
+            AngleMode::Deg => 180.0,
+            AngleMode::Grad => 200.0,
+        }
+    }
+
+    fn half_turn_decimal(self, ctx: DecimalContext) -> Decimal {
+        match self {
+            AngleMode::Rad => decimal::pi(ctx),
+            AngleMode::Deg => Decimal::from(180),
+            AngleMode::Grad => Decimal::from(200),
+        }
+    }
+
+    pub fn to_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleMode::Rad => angle,
+            _ => angle * PI / self.half_turn(),
+        }
+    }
+
+    // The opposite of `to_radians`: an angle in radians, expressed in this unit
+    pub fn express(self, radians: f64) -> f64 {
+        match self {
+            AngleMode::Rad => radians,
+            _ => radians * self.half_turn() / PI,
+        }
+    }
+
+    // Re-expresses an angle given in `from` units in this unit
+    pub fn convert(self, angle: f64, from: AngleMode) -> f64 {
+        if self == from {
+            angle
+        } else {
+            self.express(from.to_radians(angle))
+        }
+    }
+
+    // `convert` for decimal mode, computed with guard digits so that
+    // e.g. 90deg in radians is pi/2 to every digit of the precision
+    pub fn convert_decimal(self, angle: &Decimal, from: AngleMode, ctx: DecimalContext) -> Decimal {
+        if self == from {
+            return angle.clone();
+        }
+        let work = ctx.extended(decimal::GUARD_DIGITS);
+        angle
+            .mul(&self.half_turn_decimal(work), work)
+            .div(&from.half_turn_decimal(work), work)
+            .round(ctx)
+    }
+}
+
+impl fmt::Display for AngleMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AngleMode::Rad => "rad",
+            AngleMode::Deg => "deg",
+            AngleMode::Grad => "grad",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Context;
+    use crate::show;
+    use crate::value::NumberMode;
+
+    fn in_mode(angle: AngleMode) -> Context {
+        let mut ctx = Context::new();
+        ctx.settings.angle = angle;
+        ctx
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(AngleMode::Deg.to_radians(180.0), PI);
+        assert_eq!(AngleMode::Grad.express(PI), 200.0);
+        assert_eq!(AngleMode::Deg.convert(100.0, AngleMode::Grad), 90.0);
+        assert_eq!(AngleMode::Rad.convert(1.0, AngleMode::Rad), 1.0);
+        assert_eq!(AngleMode::parse("grad"), Some(AngleMode::Grad));
+        assert_eq!(AngleMode::parse("degrees"), None);
+    }
+
+    #[test]
+    fn each_mode() {
+        assert_eq!(show(&in_mode(AngleMode::Rad), "cos(pi)"), "-1");
+        assert_eq!(show(&in_mode(AngleMode::Deg), "sin(90)"), "1");
+        assert_eq!(show(&in_mode(AngleMode::Deg), "cos(60)"), "0.5");
+        assert_eq!(show(&in_mode(AngleMode::Grad), "sin(100)"), "1");
+        assert_eq!(show(&in_mode(AngleMode::Grad), "tan(50)"), "1");
+    }
+
+    #[test]
+    fn suffixes_override_the_mode() {
+        for mode in [AngleMode::Rad, AngleMode::Deg, AngleMode::Grad] {
+            let ctx = in_mode(mode);
+            assert_eq!(show(&ctx, "sin(30deg)"), "0.5");
+            assert_eq!(show(&ctx, "sin(100grad)"), "1");
+            assert_eq!(show(&ctx, "cos(0rad)"), "1");
+        }
+        // A bare suffixed angle is expressed in the current mode
+        assert_eq!(
+            show(&in_mode(AngleMode::Rad), "180deg"),
+            "3.141592653589793"
+        );
+        assert_eq!(show(&in_mode(AngleMode::Deg), "100grad"), "90");
+        assert_eq!(show(&in_mode(AngleMode::Grad), "90deg"), "100");
+        let mut ctx = Context::new();
+        ctx.settings.numbers = NumberMode::Decimal;
+        assert_eq!(show(&ctx, "90deg"), "1.570796326794896619231321691639751");
+    }
+
+    #[test]
+    fn spaced_suffixes() {
+        let ctx = Context::new();
+        assert_eq!(show(&ctx, "sin(30 deg)"), "0.5");
+        assert_eq!(show(&ctx, "sin(100 grad)"), "1");
+        assert_eq!(show(&in_mode(AngleMode::Deg), "cos(0 rad)"), "1");
+        assert_eq!(show(&in_mode(AngleMode::Deg), "45 deg + 45deg"), "90");
+    }
+
+    #[test]
+    fn inverse_trig_answers_in_the_mode() {
+        assert_eq!(
+            show(&in_mode(AngleMode::Rad), "atan(1)"),
+            "0.7853981633974483"
+        );
+        assert_eq!(show(&in_mode(AngleMode::Deg), "acos(0)"), "90");
+        assert_eq!(show(&in_mode(AngleMode::Deg), "atan(1)"), "45");
+        assert_eq!(show(&in_mode(AngleMode::Grad), "asin(1)"), "100");
+        assert_eq!(show(&in_mode(AngleMode::Grad), "atan(1)"), "50");
+    }
+}
@@ -0,0 +1,767 @@
+// Arbitrary-size integers, the building block for decimal and fraction arithmetic.
+// Like JS `BigInt`, but written by hand so the crate keeps its
+// single dependency.
+//
+// The magnitude is stored as base-10^9 "digits" (limbs), least significant
+// first: 12_345_678_901 is [345_678_901, 12]. Base 10^9 makes printing and
+// shifting by powers of ten cheap, and two limbs multiply within a u64.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+const BASE: u64 = 1_000_000_000;
+const BASE_DIGITS: usize = 9;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    negative: bool,
+    // No trailing (most significant) zero limbs; zero is the empty vector
+    mag: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        BigInt::default()
+    }
+
+    pub fn one() -> Self {
+        BigInt::from(1u64)
+    }
+
+    // 10^exp
+    pub fn pow10(exp: usize) -> Self {
+        let mut mag = vec![0; exp / BASE_DIGITS];
+        mag.push(10u32.pow((exp % BASE_DIGITS) as u32));
+        BigInt {
+            negative: false,
+            mag,
+        }
+    }
+
+    // Parses an optionally signed string of decimal digits, like "-12345"
+    pub fn parse(text: &str) -> Option<Self> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        // Cut the digits into 9-digit chunks, starting from the right
+        let bytes = digits.as_bytes();
+        let mut mag = Vec::with_capacity(bytes.len() / BASE_DIGITS + 1);
+        let mut end = bytes.len();
+        while end > 0 {
+            let start = end.saturating_sub(BASE_DIGITS);
+            let chunk = std::str::from_utf8(&bytes[start..end]).ok()?;
+            mag.push(chunk.parse().ok()?);
+            end = start;
+        }
+        Some(BigInt::from_parts(negative, mag))
+    }
+
+    // Parses unsigned digits in base 2 to 36, like "ff" in base 16
+    pub fn parse_radix(digits: &str, radix: u32) -> Option<Self> {
+        if digits.is_empty() {
+            return None;
+        }
+        let mut mag = Vec::new();
+        for c in digits.chars() {
+            let digit = c.to_digit(radix)?;
+            mag = add_mag(&mul_small(&mag, radix), &[digit]);
+        }
+        Some(BigInt::from_parts(false, mag))
+    }
+
+    // The digits in base 2 to 36, lowercase and without a prefix: 255 is "ff"
+    pub fn to_str_radix(&self, radix: u32) -> String {
+        let mut digits = Vec::new();
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            let (quotient, digit) = div_rem_small(&mag, radix);
+            digits.push(char::from_digit(digit, radix).expect("digit below radix"));
+            mag = quotient;
+        }
+        if digits.is_empty() {
+            digits.push('0');
+        }
+        if self.negative {
+            digits.push('-');
+        }
+        digits.iter().rev().collect()
+    }
+
+    fn from_parts(negative: bool, mut mag: Vec<u32>) -> Self {
+        trim(&mut mag);
+        let negative = negative && !mag.is_empty();
+        BigInt { negative, mag }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_even(&self) -> bool {
+        self.mag.first().is_none_or(|limb| limb % 2 == 0)
+    }
+
+    pub fn abs(&self) -> Self {
+        BigInt {
+            negative: false,
+            mag: self.mag.clone(),
+        }
+    }
+
+    // -1, 0 or 1
+    pub fn signum(&self) -> i32 {
+        match (self.is_zero(), self.negative) {
+            (true, _) => 0,
+            (false, true) => -1,
+            (false, false) => 1,
+        }
+    }
+
+    // How many decimal digits the magnitude has (0 has one digit)
+    pub fn num_digits(&self) -> usize {
+        match self.mag.last() {
+            None => 1,
+            Some(top) => (self.mag.len() - 1) * BASE_DIGITS + top.to_string().len(),
+        }
+    }
+
+    // The size of the magnitude in bits, as a fraction: 8 has 3 and 10 has
+    // 3.32. Only the top two limbs count, which is plenty for estimates.
+    pub fn bits(&self) -> f64 {
+        let top = self
+            .mag
+            .iter()
+            .rev()
+            .take(2)
+            .fold(0.0, |acc, &limb| acc * BASE as f64 + limb as f64);
+        top.log2() + self.mag.len().saturating_sub(2) as f64 * (BASE as f64).log2()
+    }
+
+    // Quotient and remainder, truncating toward zero like Rust's `/` and `%`.
+    // Panics on division by zero; callers check first.
+    pub fn div_rem(&self, other: &BigInt) -> (BigInt, BigInt) {
+        assert!(!other.is_zero(), "BigInt division by zero");
+        let (q, r) = div_rem_mag(&self.mag, &other.mag);
+        (
+            BigInt::from_parts(self.negative != other.negative, q),
+            BigInt::from_parts(self.negative, r),
+        )
+    }
+
+    // Quotient rounded toward negative infinity, like Python's `//`
+    pub fn div_floor(&self, other: &BigInt) -> BigInt {
+        let (q, r) = self.div_rem(other);
+        if !r.is_zero() && r.negative != other.negative {
+            &q - &BigInt::one()
+        } else {
+            q
+        }
+    }
+
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let mut a = self.abs();
+        let mut b = other.abs();
+        while !b.is_zero() {
+            let (_, r) = a.div_rem(&b);
+            a = b;
+            b = r;
+        }
+        a
+    }
+
+    pub fn pow(&self, mut exp: u32) -> BigInt {
+        let mut base = self.clone();
+        let mut result = BigInt::one();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = &result * &base;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    // n! = 1 * 2 * ... * n
+    pub fn factorial(n: u32) -> BigInt {
+        let mut mag = vec![1];
+        for k in 2..=n {
+            mag = mul_small(&mag, k);
+        }
+        BigInt::from_parts(false, mag)
+    }
+
+    // self * 10^exp
+    pub fn mul_pow10(&self, exp: usize) -> BigInt {
+        if self.is_zero() || exp == 0 {
+            return self.clone();
+        }
+        let mut mag = vec![0; exp / BASE_DIGITS];
+        mag.extend(mul_small(&self.mag, 10u32.pow((exp % BASE_DIGITS) as u32)));
+        BigInt::from_parts(self.negative, mag)
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.mag.len() > 3 {
+            return None;
+        }
+        let mut value: i128 = 0;
+        for limb in self.mag.iter().rev() {
+            value = value * BASE as i128 + *limb as i128;
+        }
+        if self.negative {
+            value = -value;
+        }
+        i64::try_from(value).ok()
+    }
+
+    pub fn to_i128(&self) -> Option<i128> {
+        let mut value: u128 = 0;
+        for limb in self.mag.iter().rev() {
+            value = value
+                .checked_mul(BASE as u128)?
+                .checked_add(*limb as u128)?;
+        }
+        if self.negative {
+            // i128::MIN has no positive counterpart, so it's negated with wrapping
+            (value <= 1 << 127).then(|| (value as i128).wrapping_neg())
+        } else {
+            i128::try_from(value).ok()
+        }
+    }
+
+    // The lowest 128 bits in two's complement, like `as u128` in Rust:
+    // -1 is u128::MAX and 2^128 + 5 is 5
+    pub fn to_u128_wrapping(&self) -> u128 {
+        let mut value: u128 = 0;
+        for limb in self.mag.iter().rev() {
+            value = value.wrapping_mul(BASE as u128).wrapping_add(*limb as u128);
+        }
+        if self.negative {
+            value.wrapping_neg()
+        } else {
+            value
+        }
+    }
+
+    // &, | or xor bit by bit, with negative numbers in two's complement and
+    // sign bits going on forever, like Python's integers: -1 & 0xff is 0xff
+    pub fn bitwise(&self, other: &BigInt, op: impl Fn(u16, u16) -> u16) -> BigInt {
+        let (a, a_fill) = self.twos_complement();
+        let (b, b_fill) = other.twos_complement();
+        let word = |words: &[u16], fill: u16, i: usize| words.get(i).copied().unwrap_or(fill);
+        let words: Vec<u16> = (0..a.len().max(b.len()))
+            .map(|i| op(word(&a, a_fill, i), word(&b, b_fill, i)))
+            .collect();
+        if op(a_fill, b_fill) == 0 {
+            BigInt::from_words(&words)
+        } else {
+            // The sign bits are set, so the result is -(~words + 1)
+            let inverted: Vec<u16> = words.iter().map(|w| !w).collect();
+            -&(&BigInt::from_words(&inverted) + &BigInt::one())
+        }
+    }
+
+    // The 16-bit words of the two's complement form, least significant
+    // first, and the word the sign bits fill the rest with
+    fn twos_complement(&self) -> (Vec<u16>, u16) {
+        if self.negative {
+            // -n is ~(n - 1)
+            let below = &self.abs() - &BigInt::one();
+            (below.words().iter().map(|w| !w).collect(), u16::MAX)
+        } else {
+            (self.words(), 0)
+        }
+    }
+
+    // The magnitude in base 2^16, least significant word first
+    fn words(&self) -> Vec<u16> {
+        let mut words = Vec::new();
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            let (quotient, word) = div_rem_small(&mag, 1 << 16);
+            words.push(word as u16);
+            mag = quotient;
+        }
+        words
+    }
+
+    fn from_words(words: &[u16]) -> BigInt {
+        let mut mag = Vec::new();
+        for &word in words.iter().rev() {
+            mag = add_mag(&mul_small(&mag, 1 << 16), &[word as u32]);
+        }
+        BigInt::from_parts(false, mag)
+    }
+
+    // Largest integer whose square is <= self (self must not be negative)
+    pub fn sqrt(&self) -> BigInt {
+        if self.is_zero() {
+            return BigInt::zero();
+        }
+        // Newton's method from an overestimate converges downward
+        let two = BigInt::from(2u64);
+        let mut x = BigInt::pow10(self.num_digits().div_ceil(2));
+        loop {
+            let (q, _) = self.div_rem(&x);
+            let (next, _) = (&x + &q).div_rem(&two);
+            if next >= x {
+                return x;
+            }
+            x = next;
+        }
+    }
+}
+
+impl From<u64> for BigInt {
+    fn from(mut n: u64) -> Self {
+        let mut mag = Vec::new();
+        while n > 0 {
+            mag.push((n % BASE) as u32);
+            n /= BASE;
+        }
+        BigInt {
+            negative: false,
+            mag,
+        }
+    }
+}
+
+impl From<u128> for BigInt {
+    fn from(mut n: u128) -> Self {
+        let mut mag = Vec::new();
+        while n > 0 {
+            mag.push((n % BASE as u128) as u32);
+            n /= BASE as u128;
+        }
+        BigInt {
+            negative: false,
+            mag,
+        }
+    }
+}
+
+impl From<i128> for BigInt {
+    fn from(n: i128) -> Self {
+        let mut result = BigInt::from(n.unsigned_abs());
+        result.negative = n < 0;
+        result
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        let mut result = BigInt::from(n.unsigned_abs());
+        result.negative = n < 0;
+        result
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_parts(self.negative, add_mag(&self.mag, &other.mag));
+        }
+        // Different signs: subtract the smaller magnitude from the larger
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Equal => BigInt::zero(),
+            Ordering::Greater => BigInt::from_parts(self.negative, sub_mag(&self.mag, &other.mag)),
+            Ordering::Less => BigInt::from_parts(other.negative, sub_mag(&other.mag, &self.mag)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::from_parts(
+            self.negative != other.negative,
+            mul_mag(&self.mag, &other.mag),
+        )
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.mag.clone())
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut text = String::new();
+        if self.negative {
+            text.push('-');
+        }
+        match self.mag.split_last() {
+            None => text.push('0'),
+            Some((top, rest)) => {
+                text.push_str(&top.to_string());
+                // Every limb below the top one is zero-padded to 9 digits
+                for limb in rest.iter().rev() {
+                    text.push_str(&format!("{:09}", limb));
+                }
+            }
+        }
+        f.pad(&text)
+    }
+}
+
+// ---- Magnitude helpers (unsigned, little-endian limbs) ----
+
+fn trim(mag: &mut Vec<u32>) {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+// a - b, where a >= b
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &x) in a.iter().enumerate() {
+        let mut diff = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += BASE as i64;
+            borrow = 1;
+        }
+        result.push(diff as u32);
+    }
+    trim(&mut result);
+    result
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut result = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let cur = result[i + j] + x as u64 * y as u64 + carry;
+            result[i + j] = cur % BASE;
+            carry = cur / BASE;
+        }
+        result[i + b.len()] += carry;
+    }
+    let mut result: Vec<u32> = result.into_iter().map(|limb| limb as u32).collect();
+    trim(&mut result);
+    result
+}
+
+fn mul_small(a: &[u32], m: u32) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0u64;
+    for &x in a {
+        let cur = x as u64 * m as u64 + carry;
+        result.push((cur % BASE) as u32);
+        carry = cur / BASE;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    trim(&mut result);
+    result
+}
+
+fn div_rem_small(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0; a.len()];
+    let mut rem = 0u64;
+    for i in (0..a.len()).rev() {
+        let cur = rem * BASE + a[i] as u64;
+        quotient[i] = (cur / d as u64) as u32;
+        rem = cur % d as u64;
+    }
+    trim(&mut quotient);
+    (quotient, rem as u32)
+}
+
+// Long division (Knuth's Algorithm D) in base 10^9
+fn div_rem_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_mag(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let (q, r) = div_rem_small(a, b[0]);
+        let mut r = vec![r];
+        trim(&mut r);
+        return (q, r);
+    }
+
+    // Scale both so the divisor's top limb is at least BASE / 2;
+    // that keeps each estimated quotient digit off by at most 2.
+    let factor = (BASE / (*b.last().unwrap() as u64 + 1)) as u32;
+    let mut u = mul_small(a, factor);
+    u.resize(a.len() + 1, 0);
+    let v = mul_small(b, factor);
+    let n = v.len();
+    let m = u.len() - n;
+    let mut quotient = vec![0u32; m];
+
+    for j in (0..m).rev() {
+        let top = u[j + n] as u64 * BASE + u[j + n - 1] as u64;
+        let mut qhat = top / v[n - 1] as u64;
+        let mut rhat = top % v[n - 1] as u64;
+        while qhat >= BASE || qhat * v[n - 2] as u64 > rhat * BASE + u[j + n - 2] as u64 {
+            qhat -= 1;
+            rhat += v[n - 1] as u64;
+            if rhat >= BASE {
+                break;
+            }
+        }
+
+        // u[j..=j+n] -= qhat * v
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for i in 0..n {
+            let product = qhat * v[i] as u64 + carry;
+            carry = product / BASE;
+            let mut diff = u[i + j] as i64 - (product % BASE) as i64 - borrow;
+            borrow = 0;
+            if diff < 0 {
+                diff += BASE as i64;
+                borrow = 1;
+            }
+            u[i + j] = diff as u32;
+        }
+        let diff = u[j + n] as i64 - carry as i64 - borrow;
+        if diff < 0 {
+            // qhat was one too big: add the divisor back
+            u[j + n] = (diff + BASE as i64) as u32;
+            qhat -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let sum = u[i + j] as u64 + v[i] as u64 + carry;
+                u[i + j] = (sum % BASE) as u32;
+                carry = sum / BASE;
+            }
+            u[j + n] = ((u[j + n] as u64 + carry) % BASE) as u32;
+        } else {
+            u[j + n] = diff as u32;
+        }
+        quotient[j] = qhat as u32;
+    }
+
+    trim(&mut quotient);
+    u.truncate(n);
+    trim(&mut u);
+    let (remainder, _) = div_rem_small(&u, factor);
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse(text).unwrap()
+    }
+
+    #[test]
+    fn div_rem_truncates_toward_zero() {
+        let cases = [
+            (7, 2, 3, 1),
+            (-7, 2, -3, -1),
+            (7, -2, -3, 1),
+            (-7, -2, 3, -1),
+        ];
+        for (a, b, q, r) in cases {
+            let (quotient, remainder) = BigInt::from(a as i64).div_rem(&BigInt::from(b as i64));
+            assert_eq!((quotient.to_i64(), remainder.to_i64()), (Some(q), Some(r)));
+        }
+    }
+
+    #[test]
+    fn bitwise_matches_i128() {
+        let values = [
+            0i128,
+            1,
+            -1,
+            5,
+            -6,
+            255,
+            -256,
+            i64::MAX as i128,
+            -(1 << 100) + 3,
+        ];
+        for a in values {
+            for b in values {
+                let (x, y) = (BigInt::from(a), BigInt::from(b));
+                assert_eq!(
+                    x.bitwise(&y, |p, q| p & q),
+                    BigInt::from(a & b),
+                    "{} & {}",
+                    a,
+                    b
+                );
+                assert_eq!(
+                    x.bitwise(&y, |p, q| p | q),
+                    BigInt::from(a | b),
+                    "{} | {}",
+                    a,
+                    b
+                );
+                assert_eq!(
+                    x.bitwise(&y, |p, q| p ^ q),
+                    BigInt::from(a ^ b),
+                    "{} ^ {}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn div_floor_rounds_down() {
+        assert_eq!(big("-7").div_floor(&big("2")), big("-4"));
+        assert_eq!(big("7").div_floor(&big("-2")), big("-4"));
+        assert_eq!(big("-8").div_floor(&big("2")), big("-4"));
+    }
+
+    // Several limbs on both sides, so every step of the long division runs
+    #[test]
+    fn div_rem_matches_i128() {
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = || {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            seed
+        };
+        for _ in 0..2000 {
+            let a = ((next() as i128) << 64 | next() as i128) >> (next() % 64);
+            let b = ((next() as i128) << 64 | next() as i128) >> (64 + next() % 60);
+            if b == 0 {
+                continue;
+            }
+            let (q, r) = BigInt::from(a).div_rem(&BigInt::from(b));
+            assert_eq!(
+                (q.to_i128(), r.to_i128()),
+                (Some(a / b), Some(a % b)),
+                "{} / {}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn div_rem_beyond_i128() {
+        let a = big("123456789012345678901234567890123456789012345678901234567890");
+        let b = big("987654321098765432109876543210");
+        let r = big("12345");
+        let (q, rem) = (&(&a * &b) + &r).div_rem(&b);
+        assert_eq!((q, rem), (a, r));
+        // A quotient digit that is still one too big after the estimate is
+        // corrected, so the divisor has to be added back
+        let (q, rem) =
+            big("999999998000000000499999999000000000").div_rem(&big("2000000000000000002"));
+        assert_eq!(
+            (q, rem),
+            (big("499999998999999999"), big("1500000001000000002"))
+        );
+    }
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(big("000123").to_string(), "123");
+        assert_eq!(big("-0").to_string(), "0");
+        assert_eq!(big("+1000000000").to_string(), "1000000000");
+        assert_eq!(BigInt::parse("12a"), None);
+        assert_eq!(BigInt::parse("-"), None);
+        assert_eq!(big("-255").to_str_radix(16), "-ff");
+        assert_eq!(BigInt::parse_radix("ff", 16), Some(big("255")));
+    }
+
+    #[test]
+    fn bits() {
+        assert_eq!(big("8").bits(), 3.0);
+        assert_eq!(big("-1024").bits(), 10.0);
+        assert!(
+            (big("1000000000000000000000").bits() - 21.0 / std::f64::consts::LOG10_2).abs() < 1e-9
+        );
+        assert_eq!(big("0").bits(), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn sqrt_rounds_down() {
+        assert_eq!(big("99").sqrt(), big("9"));
+        assert_eq!(big("100").sqrt(), big("10"));
+        assert_eq!(BigInt::pow10(41).sqrt(), big("316227766016837933199"));
+    }
+
+    #[test]
+    fn conversions_at_the_limits() {
+        assert_eq!(BigInt::from(i128::MIN).to_i128(), Some(i128::MIN));
+        assert_eq!((&BigInt::from(i128::MAX) + &BigInt::one()).to_i128(), None);
+        assert_eq!(BigInt::from(-1i64).to_u128_wrapping(), u128::MAX);
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(big("12").mul_pow10(10), big("120000000000"));
+        assert_eq!(
+            BigInt::factorial(20).to_i64(),
+            Some(2_432_902_008_176_640_000)
+        );
+    }
+}
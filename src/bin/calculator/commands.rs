@@ -0,0 +1,478 @@
+// Session commands like `vars` or `mode deg`, shared by the REPL and batch mode.
+// Anything that isn't a command is handed to the engine as an expression.
+
+use std::path::Path;
+
+use cli_calculator::decimal::MAX_PRECISION;
+use cli_calculator::session;
+use cli_calculator::{
+    AngleMode, ComplexForm, Context, DecimalMark, Notation, NumberMode, Radix, Rates, RoundingMode,
+    Settings,
+};
+
+use crate::data;
+
+// Every command, for tab completion; `quit` and `exit` are handled by the REPL
+pub const NAMES: &[&str] = &[
+    "history",
+    "clear",
+    "vars",
+    "funcs",
+    "mode",
+    "precision",
+    "rounding",
+    "fractions",
+    "format",
+    "base",
+    "overflow",
+    "rates",
+    "unset",
+    "save",
+    "load",
+    "sessions",
+    "quit",
+    "exit",
+];
+
+// Runs `input` if it is a command: None when it isn't one, otherwise the
+// text it shows (possibly none) or why it failed. The caller prints either,
+// so batch mode can report a failure with its file and line.
+// With `verbose` off, commands that only change settings stay quiet,
+// so a batch file full of `mode deg` lines doesn't clutter the output.
+pub fn run(ctx: &mut Context, input: &str, verbose: bool) -> Option<Result<String, String>> {
+    let (name, arg) = match input.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (input, ""),
+    };
+    command_name(ctx, input)?;
+    // What a setting command says when it's done, if anyone's listening
+    let quiet = |message: String| if verbose { message } else { String::new() };
+
+    let result = match (name, arg) {
+        ("history", "") => {
+            if ctx.history.is_empty() {
+                Ok("No results yet.".to_string())
+            } else {
+                let lines: Vec<String> = ctx
+                    .history
+                    .iter()
+                    .enumerate()
+                    .map(|(i, entry)| {
+                        let label = format!("${:<3} {} = ", i + 1, entry.input);
+                        labelled(&label, &ctx.settings.format(&entry.value))
+                    })
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+        }
+        // In RPN mode `clear` empties the stack instead; see rpn.rs
+        ("clear", "") if ctx.settings.rpn => return None,
+        ("clear", "") => {
+            ctx.clear();
+            Ok(quiet("History cleared.".to_string()))
+        }
+        ("vars", "") => {
+            if ctx.vars.is_empty() {
+                Ok("No variables defined.".to_string())
+            } else {
+                let lines: Vec<String> = ctx
+                    .vars
+                    .iter()
+                    .map(|(name, value)| {
+                        labelled(&format!("{} = ", name), &ctx.settings.format(value))
+                    })
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+        }
+        ("funcs", "") => {
+            if ctx.funcs.is_empty() {
+                Ok("No functions defined.".to_string())
+            } else {
+                let lines: Vec<String> = ctx.funcs.values().map(|f| f.to_string()).collect();
+                Ok(lines.join("\n"))
+            }
+        }
+        ("mode", "") => Ok([
+            format!("Angle mode: {}", ctx.settings.angle),
+            format!("Number mode: {}", describe_numbers(&ctx.settings)),
+            format!("Complex numbers: {}", ctx.settings.complex),
+            format!("Output base: {}", ctx.settings.radix),
+            format!("Input: {}", describe_input(&ctx.settings)),
+        ]
+        .join("\n")),
+        ("mode", arg) => {
+            if let Some(mode) = AngleMode::parse(arg) {
+                ctx.settings.angle = mode;
+                Ok(quiet(format!("Angle mode: {}", mode)))
+            } else if let Some(mode) = NumberMode::parse(arg) {
+                ctx.settings.numbers = mode;
+                Ok(quiet(format!(
+                    "Number mode: {}",
+                    describe_numbers(&ctx.settings)
+                )))
+            } else if let Some(form) = ComplexForm::parse(arg) {
+                ctx.settings.complex = form;
+                Ok(quiet(format!("Complex numbers: {}", form)))
+            } else if arg == "rpn" || arg == "infix" {
+                ctx.settings.rpn = arg == "rpn";
+                Ok(quiet(format!("Input: {}", describe_input(&ctx.settings))))
+            } else {
+                Err(format!(
+                    "Unknown mode '{}'. Try: deg, rad, grad, float, decimal, rect, polar, \
+                     i8 to i128, u8 to u128, rpn, infix",
+                    arg
+                ))
+            }
+        }
+        ("precision", "") => Ok(format!(
+            "Precision: {} digits",
+            ctx.settings.decimal.precision
+        )),
+        // Asking for a precision implies wanting decimal mode
+        ("precision", arg) => parse_precision(arg).map(|digits| {
+            ctx.settings.decimal.precision = digits;
+            ctx.settings.numbers = NumberMode::Decimal;
+            quiet(format!("Number mode: {}", describe_numbers(&ctx.settings)))
+        }),
+        ("rounding", "") => Ok(format!("Rounding: {}", ctx.settings.decimal.rounding)),
+        ("rounding", arg) => parse_rounding(arg).map(|mode| {
+            ctx.settings.decimal.rounding = mode;
+            quiet(format!("Rounding: {}", mode))
+        }),
+        ("fractions", "") => Ok(format!("Fractions: {}", describe_fractions(&ctx.settings))),
+        ("fractions", arg) => match arg {
+            "mixed" | "improper" => {
+                ctx.settings.mixed_fractions = arg == "mixed";
+                Ok(quiet(format!(
+                    "Fractions: {}",
+                    describe_fractions(&ctx.settings)
+                )))
+            }
+            _ => Err(format!(
+                "Unknown fraction style '{}'. Try: mixed, improper",
+                arg
+            )),
+        },
+        ("format", "") => Ok([
+            format!("Format: {}", ctx.settings.output.notation),
+            format!(
+                "Thousands separators: {}",
+                on_off(ctx.settings.output.group)
+            ),
+            format!("Decimal mark: {}", ctx.settings.output.mark),
+        ]
+        .join("\n")),
+        ("format", arg) => {
+            let (setting, value) = arg.split_once(' ').unwrap_or((arg, ""));
+            let output = &mut ctx.settings.output;
+            let result = match (setting, value.trim()) {
+                ("group", "on" | "off") => {
+                    output.group = value.trim() == "on";
+                    Ok(format!("Thousands separators: {}", on_off(output.group)))
+                }
+                ("group", _) => Err("Try: format group on, format group off".to_string()),
+                ("mark", mark) => parse_mark(mark).map(|mark| {
+                    output.mark = mark;
+                    format!("Decimal mark: {}", mark)
+                }),
+                _ => parse_format(arg).map(|notation| {
+                    output.notation = notation;
+                    format!("Format: {}", notation)
+                }),
+            };
+            result.map(quiet)
+        }
+        ("base", "") => Ok(format!("Output base: {}", ctx.settings.radix)),
+        ("base", arg) => parse_base(arg).map(|radix| {
+            ctx.settings.radix = radix;
+            quiet(format!("Output base: {}", radix))
+        }),
+        ("overflow", "") => Ok(format!("Overflow: {}", describe_overflow(&ctx.settings))),
+        ("overflow", arg) => match arg {
+            "wrap" | "error" => {
+                ctx.settings.wrap = arg == "wrap";
+                Ok(quiet(format!(
+                    "Overflow: {}",
+                    describe_overflow(&ctx.settings)
+                )))
+            }
+            _ => Err(format!(
+                "Unknown overflow behavior '{}'. Try: wrap, error",
+                arg
+            )),
+        },
+        ("rates", "") if ctx.rates.is_empty() => Ok(
+            "No exchange rates loaded. Load a CSV or JSON file with 'rates <file>'.".to_string(),
+        ),
+        ("rates", "") => Ok(format!("Exchange rates: {}", ctx.rates.describe())),
+        ("rates", path) => Rates::load(Path::new(path)).map(|rates| {
+            ctx.rates = rates;
+            quiet(format!("Exchange rates: {}", ctx.rates.describe()))
+        }),
+        ("save", "") => Err("Usage: save <name>, then 'load <name>' to get it back".to_string()),
+        ("save", name) => data::session_path(name)
+            .and_then(|path| {
+                session::save(ctx, &path)?;
+                Ok(path)
+            })
+            .map(|path| quiet(format!("Saved session '{}' to {}", name, path.display()))),
+        ("load", "") => Err("Usage: load <name>; 'sessions' lists the saved ones".to_string()),
+        ("load", name) => data::session_path(name)
+            .and_then(|path| {
+                if !path.exists() {
+                    return Err(format!(
+                        "No saved session named '{}'; 'sessions' lists them",
+                        name
+                    ));
+                }
+                session::load(&path)
+            })
+            .map(|loaded| {
+                // Exchange rates aren't saved with a session, and whether long
+                // numbers are grouped depends on the front end, so keep those
+                let rates = std::mem::take(&mut ctx.rates);
+                let group_long = ctx.settings.output.group_long;
+                *ctx = loaded;
+                ctx.rates = rates;
+                ctx.settings.output.group_long = group_long;
+                quiet(format!(
+                    "Loaded session '{}' ({})",
+                    name,
+                    describe_session(ctx)
+                ))
+            }),
+        ("sessions", "") => data::session_names().map(|names| {
+            if names.is_empty() {
+                "No saved sessions. Save one with 'save <name>'.".to_string()
+            } else {
+                format!("Saved sessions: {}", names.join(", "))
+            }
+        }),
+        ("unset", arg) if !arg.is_empty() => {
+            if ctx.unset(arg) {
+                Ok(quiet(format!("Removed '{}'", arg)))
+            } else {
+                Err(format!("No variable or function named '{}'", arg))
+            }
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+// The command `input` starts with, if it is a command line at all. Once
+// `base = 10` has been assigned, `base * 2` is about the variable: a line
+// that reads as an expression is one, as long as it has more than the name.
+pub fn command_name<'a>(ctx: &Context, input: &'a str) -> Option<&'a str> {
+    let input = input.trim_start();
+    let name = input.split_whitespace().next()?;
+    let rest = input[name.len()..].trim_start();
+    let variable = ctx.vars.contains_key(name)
+        && !rest.is_empty()
+        && cli_calculator::parse_statement(input).is_ok();
+    (NAMES.contains(&name) && !assigns(rest) && !variable).then_some(name)
+}
+
+// Whether what follows a command word makes the line an assignment, like
+// `base = 10`: a variable may be named like a command
+fn assigns(rest: &str) -> bool {
+    rest.starts_with('=') && !rest.starts_with("==")
+}
+
+// `label` and then `text`, with the later lines of a value that takes
+// several, like a matrix, lined up under its first line:
+//
+//   $1 = [[1, 2],
+//         [3, 4]]
+pub fn labelled(label: &str, text: &str) -> String {
+    let indent = format!("\n{}", " ".repeat(label.chars().count()));
+    format!("{}{}", label, text.replace('\n', &indent))
+}
+
+// e.g. "2 variables, 1 function, 5 results"
+pub fn describe_session(ctx: &Context) -> String {
+    let count = |n: usize, what: &str| {
+        let plural = if n == 1 { "" } else { "s" };
+        format!("{} {}{}", n, what, plural)
+    };
+    format!(
+        "{}, {}, {}",
+        count(ctx.vars.len(), "variable"),
+        count(ctx.funcs.len(), "function"),
+        count(ctx.history.len(), "result")
+    )
+}
+
+// "float", or e.g. "decimal (34 digits, half-even rounding)"
+pub fn describe_numbers(settings: &Settings) -> String {
+    match settings.numbers {
+        NumberMode::Float => "float".to_string(),
+        NumberMode::Decimal => format!(
+            "decimal ({} digits, {} rounding)",
+            settings.decimal.precision, settings.decimal.rounding
+        ),
+        NumberMode::Integer(int) => format!(
+            "{} ({} to {}, {})",
+            int,
+            int.min(),
+            int.max(),
+            describe_overflow(settings)
+        ),
+    }
+}
+
+// "overflow wraps around" or "overflow is an error"
+fn describe_overflow(settings: &Settings) -> &'static str {
+    if settings.wrap {
+        "overflow wraps around"
+    } else {
+        "overflow is an error"
+    }
+}
+
+// "infix (3 + 4)" or "rpn (3 4 +, ...)"
+fn describe_input(settings: &Settings) -> &'static str {
+    if settings.rpn {
+        "rpn (3 4 +, with dup, swap, drop, roll and clear for the stack)"
+    } else {
+        "infix (3 + 4)"
+    }
+}
+
+// "mixed (7/2 shows as 3 1/2)" or "improper (7/2)"
+fn describe_fractions(settings: &Settings) -> &'static str {
+    if settings.mixed_fractions {
+        "mixed (7/2 shows as 3 1/2)"
+    } else {
+        "improper (7/2)"
+    }
+}
+
+// Shared with the --precision flag
+pub fn parse_precision(arg: &str) -> Result<usize, String> {
+    match arg.parse() {
+        Ok(digits) if (1..=MAX_PRECISION).contains(&digits) => Ok(digits),
+        _ => Err(format!(
+            "Precision must be a number of digits from 1 to {} (got '{}')",
+            MAX_PRECISION, arg
+        )),
+    }
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on { "on" } else { "off" }
+}
+
+// Shared with the --format flag
+pub fn parse_format(arg: &str) -> Result<Notation, String> {
+    Notation::parse(arg).ok_or_else(|| {
+        format!(
+            "Unknown format '{}'. Try: auto, fix <places>, sig <digits>, sci <digits>, \
+             eng <digits>, group on|off, mark .|,",
+            arg
+        )
+    })
+}
+
+// Shared with the --mark flag
+pub fn parse_mark(arg: &str) -> Result<DecimalMark, String> {
+    DecimalMark::parse(arg).ok_or_else(|| {
+        format!(
+            "Unknown decimal mark '{}'. Try: . or , (or a language like en or de)",
+            arg
+        )
+    })
+}
+
+// Shared with the --base flag
+pub fn parse_base(arg: &str) -> Result<Radix, String> {
+    Radix::parse(arg).ok_or_else(|| {
+        format!(
+            "Unknown base '{}'. Try: 2, 8, 10, 16 (or bin, oct, dec, hex)",
+            arg
+        )
+    })
+}
+
+// Shared with the --rounding flag
+pub fn parse_rounding(arg: &str) -> Result<RoundingMode, String> {
+    RoundingMode::parse(arg).ok_or_else(|| {
+        format!(
+            "Unknown rounding mode '{}'. Try: half-even, half-up, truncate",
+            arg
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_change_settings() {
+        let mut ctx = Context::new();
+        assert_eq!(
+            run(&mut ctx, "base 16", true),
+            Some(Ok("Output base: 16 (hex)".to_string()))
+        );
+        assert_eq!(ctx.settings.radix, Radix::Hex);
+        assert_eq!(run(&mut ctx, "precision 5", false), Some(Ok(String::new())));
+        assert_eq!(ctx.settings.numbers, NumberMode::Decimal);
+        assert!(matches!(
+            run(&mut ctx, "precision lots", false),
+            Some(Err(_))
+        ));
+        assert_eq!(run(&mut ctx, "2 + 2", false), None);
+    }
+
+    #[test]
+    fn history_and_clear() {
+        let mut ctx = Context::new();
+        assert_eq!(
+            run(&mut ctx, "history", true),
+            Some(Ok("No results yet.".to_string()))
+        );
+        for input in ["1/3", "[[1, 2], [3, 4]]"] {
+            let value = ctx.evaluate(input).unwrap();
+            ctx.record(input, value);
+        }
+        assert_eq!(
+            run(&mut ctx, "history", true),
+            Some(Ok(concat!(
+                "$1   1/3 = 1/3\n",
+                "$2   [[1, 2], [3, 4]] = [[1, 2],\n",
+                "                         [3, 4]]"
+            )
+            .to_string()))
+        );
+        assert_eq!(
+            run(&mut ctx, "clear", true),
+            Some(Ok("History cleared.".to_string()))
+        );
+        assert!(ctx.history.is_empty());
+        // In RPN mode `clear` is left to empty the stack
+        let one = ctx.evaluate("1").unwrap();
+        ctx.record("1", one);
+        ctx.settings.rpn = true;
+        assert_eq!(run(&mut ctx, "clear", false), None);
+        assert_eq!(ctx.history.len(), 1);
+    }
+
+    #[test]
+    fn variables_named_like_commands() {
+        let mut ctx = Context::new();
+        for line in ["precision = 2", "base = 10", "rates = 0.1", "history=3"] {
+            assert_eq!(run(&mut ctx, line, true), None, "{}", line);
+            assert_eq!(command_name(&ctx, line), None, "{}", line);
+            ctx.run(line).unwrap();
+        }
+        assert_eq!(ctx.evaluate("precision * base").unwrap().to_string(), "20");
+        assert_eq!(run(&mut ctx, "base * rates", false), None);
+        assert!(matches!(run(&mut ctx, "mode * 2", false), Some(Err(_))));
+        assert_eq!(ctx.settings.decimal, Settings::default().decimal);
+        assert_eq!(command_name(&ctx, "base * rates"), None);
+        assert_eq!(command_name(&ctx, "  base 16"), Some("base"));
+        assert_eq!(command_name(&ctx, "history"), Some("history"));
+    }
+}
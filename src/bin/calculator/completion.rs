@@ -0,0 +1,176 @@
+// Tab completion for the REPL: built-in functions and constants, the
+// session's variables and functions, units, currencies with loaded rates,
+// and commands with their arguments.
+
+use cli_calculator::builtins::{BUILTINS, CONSTANTS};
+use cli_calculator::units::UNITS;
+use cli_calculator::{Context, rpn};
+
+use crate::{commands, data};
+
+// The words each command takes; `load`, `save` and `unset` complete from
+// the saved sessions and the session's names instead
+const ARGUMENTS: &[(&str, &[&str])] = &[
+    (
+        "mode",
+        &[
+            "deg", "rad", "grad", "float", "decimal", "rect", "polar", "rpn", "infix", "i8", "i16",
+            "i32", "i64", "i128", "u8", "u16", "u32", "u64", "u128",
+        ],
+    ),
+    ("rounding", &["half-even", "half-up", "truncate"]),
+    ("fractions", &["mixed", "improper"]),
+    (
+        "format",
+        &["auto", "fix", "sig", "sci", "eng", "group", "mark"],
+    ),
+    ("base", &["bin", "oct", "dec", "hex"]),
+    ("overflow", &["wrap", "error"]),
+];
+
+// The completions for the word that ends at `cursor` (a char index), and
+// where that word starts. Functions come with their opening parenthesis.
+pub fn complete(ctx: &Context, line: &[char], cursor: usize) -> (usize, Vec<String>) {
+    let before: String = line[..cursor].iter().collect();
+    // A command's argument, like the "half-e" of "rounding half-e"
+    let argument = before
+        .trim_start()
+        .split_once(' ')
+        .filter(|(command, argument)| {
+            commands::NAMES.contains(command) && !argument.trim_start().contains(' ')
+        });
+    let (start, mut candidates) = match argument {
+        Some((command, argument)) => {
+            let word = argument.trim_start().chars().count();
+            (cursor - word, arguments(ctx, command))
+        }
+        None => {
+            let mut start = cursor;
+            while start > 0 && (line[start - 1].is_alphanumeric() || line[start - 1] == '_') {
+                start -= 1;
+            }
+            let line_start = line[..start].iter().all(|c| c.is_whitespace());
+            (start, names(ctx, line_start))
+        }
+    };
+    let word: String = line[start..cursor].iter().collect();
+    candidates.retain(|candidate| candidate.starts_with(&word) && *candidate != word);
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+// The longest start all the candidates share
+pub fn common_prefix(candidates: &[String]) -> String {
+    let Some(first) = candidates.first() else {
+        return String::new();
+    };
+    let mut prefix: &str = first;
+    for candidate in &candidates[1..] {
+        let shared = prefix
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(prefix.len().min(candidate.len()), |((i, _), _)| i);
+        prefix = &prefix[..shared];
+    }
+    prefix.to_string()
+}
+
+fn arguments(ctx: &Context, command: &str) -> Vec<String> {
+    match command {
+        "load" | "save" => data::session_names().unwrap_or_default(),
+        "unset" => ctx.vars.keys().chain(ctx.funcs.keys()).cloned().collect(),
+        _ => ARGUMENTS
+            .iter()
+            .find(|(name, _)| *name == command)
+            .map(|(_, words)| words.iter().map(|word| word.to_string()).collect())
+            .unwrap_or_default(),
+    }
+}
+
+// Everything a name in an expression can be, plus the commands when the
+// word starts the line
+fn names(ctx: &Context, line_start: bool) -> Vec<String> {
+    let mut names: Vec<String> = BUILTINS
+        .iter()
+        .map(|builtin| format!("{}(", builtin.name))
+        .chain(ctx.funcs.keys().map(|name| format!("{}(", name)))
+        .chain(CONSTANTS.iter().map(|constant| constant.name.to_string()))
+        .chain(ctx.vars.keys().cloned())
+        .chain(UNITS.iter().map(|unit| unit.name.to_string()))
+        .chain(ctx.rates.codes().map(str::to_string))
+        .chain(["ans".to_string(), "in".to_string(), "to".to_string()])
+        .collect();
+    if ctx.settings.rpn {
+        names.extend(rpn::STACK_WORDS.iter().map(|word| word.to_string()));
+    }
+    if line_start {
+        names.extend(commands::NAMES.iter().map(|name| name.to_string()));
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete_at_end(ctx: &Context, line: &str) -> (usize, Vec<String>) {
+        let chars: Vec<char> = line.chars().collect();
+        complete(ctx, &chars, chars.len())
+    }
+
+    #[test]
+    fn names() {
+        let mut ctx = Context::new();
+        ctx.run("rate = 2").unwrap();
+        ctx.run("rebate(x) = x").unwrap();
+        assert_eq!(
+            complete_at_end(&ctx, "2 * sq"),
+            (4, vec!["sqrt(".to_string()])
+        );
+        assert_eq!(complete_at_end(&ctx, "sin(p").1, ["pi", "pow(", "psi"]);
+        assert_eq!(complete_at_end(&ctx, "2 * ra").1, ["rate"]);
+        // The session's functions get a parenthesis like the built-in ones
+        assert!(
+            complete_at_end(&ctx, "1 + re")
+                .1
+                .contains(&"rebate(".to_string())
+        );
+        // A word that's already complete offers nothing
+        assert!(complete_at_end(&ctx, "ans").1.is_empty());
+        // Completing in the middle of a line only looks before the cursor
+        let chars: Vec<char> = "sq + 1".chars().collect();
+        assert_eq!(complete(&ctx, &chars, 2), (0, vec!["sqrt(".to_string()]));
+    }
+
+    #[test]
+    fn commands() {
+        let mut ctx = Context::new();
+        assert_eq!(complete_at_end(&ctx, "mo").1, ["mod(", "mode", "mol"]);
+        assert_eq!(complete_at_end(&ctx, "1 + mo").1, ["mod(", "mol"]);
+        assert_eq!(
+            complete_at_end(&ctx, "mode d"),
+            (5, vec!["decimal".to_string(), "deg".to_string()])
+        );
+        assert_eq!(
+            complete_at_end(&ctx, "mode  i1"),
+            (6, vec!["i128".to_string(), "i16".to_string()])
+        );
+        assert_eq!(complete_at_end(&ctx, "format ").1.len(), 7);
+        assert!(complete_at_end(&ctx, "base x").1.is_empty());
+        ctx.run("rate = 2").unwrap();
+        ctx.run("rebate(x) = x").unwrap();
+        assert_eq!(complete_at_end(&ctx, "unset r").1, ["rate", "rebate"]);
+    }
+
+    #[test]
+    fn shared_prefixes() {
+        let words = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(common_prefix(&words(&["half-even", "half-up"])), "half-");
+        assert_eq!(common_prefix(&words(&["deg", "decimal"])), "de");
+        assert_eq!(common_prefix(&words(&["sqrt("])), "sqrt(");
+        assert_eq!(common_prefix(&words(&["µm", "µs"])), "µ");
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
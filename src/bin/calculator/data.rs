@@ -0,0 +1,72 @@
+// Where the calculator keeps files between runs: saved sessions under
+// sessions/<name>.json in the user's data directory.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Overrides the data directory, e.g. for a portable install
+const DATA_DIR_VAR: &str = "CALC_DATA_DIR";
+
+// The session the REPL saves on exit and --restore reads back
+pub const LAST_SESSION: &str = "last";
+
+// $CALC_DATA_DIR, or the platform's usual place: $XDG_DATA_HOME/calculator,
+// ~/.local/share/calculator, or %APPDATA%\calculator on Windows
+pub fn data_dir() -> Result<PathBuf, String> {
+    let var = |name| env::var_os(name).filter(|value| !value.is_empty());
+    if let Some(dir) = var(DATA_DIR_VAR) {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Some(dir) = var("XDG_DATA_HOME") {
+        return Ok(PathBuf::from(dir).join("calculator"));
+    }
+    if let Some(home) = var("HOME") {
+        return Ok(PathBuf::from(home).join(".local/share/calculator"));
+    }
+    if let Some(dir) = var("APPDATA") {
+        return Ok(PathBuf::from(dir).join("calculator"));
+    }
+    Err(format!(
+        "no data directory found; set {} to a folder for saved sessions",
+        DATA_DIR_VAR
+    ))
+}
+
+// The file for a named session. Names are kept to letters, digits, '-' and
+// '_' so `save ../x` can't write outside the sessions folder.
+pub fn session_path(name: &str) -> Result<PathBuf, String> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!(
+            "'{}' can't be a session name; use letters, digits, '-' and '_'",
+            name
+        ));
+    }
+    Ok(data_dir()?.join("sessions").join(format!("{}.json", name)))
+}
+
+// The names of all saved sessions, sorted
+pub fn session_names() -> Result<Vec<String>, String> {
+    let dir = data_dir()?.join("sessions");
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        // Nothing saved yet
+        Err(_) => return Ok(Vec::new()),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_string())
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
@@ -0,0 +1,739 @@
+// The REPL's line editor: cursor movement, history recall with the arrow
+// keys, Ctrl-R search and tab completion. History is kept in the data
+// directory so it carries over between runs.
+//
+// When stdin isn't a terminal, or the terminal can't be put in raw mode,
+// lines are read plainly, so piping input into the REPL still works.
+//
+// The line is colored as it's typed and the result it would give is
+// previewed beneath it; see highlight.rs. The preview is worked out on
+// another thread, so a slow one never holds up the keyboard.
+//
+// Keys: Left/Right (Ctrl-B/F) and Home/End (Ctrl-A/E) move, Ctrl-Left/Right
+// jump a word, Up/Down (Ctrl-P/N) step through history, Ctrl-R searches it,
+// Backspace/Delete, Ctrl-W, Ctrl-U and Ctrl-K delete, Ctrl-L clears the
+// screen, Ctrl-C drops the line and Ctrl-D on an empty line quits.
+
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cli_calculator::Context;
+
+use crate::terminal::{self, RawMode};
+use crate::{completion, highlight};
+
+// Older lines are dropped from the history file beyond this
+const MAX_HISTORY: usize = 1000;
+
+// A preview that takes longer than this isn't shown, so something like
+// identity(120) * identity(120) doesn't stall typing
+const PREVIEW_BUDGET: Duration = Duration::from_millis(250);
+
+// How often a slow preview looks for a key press
+const PREVIEW_POLL: Duration = Duration::from_millis(10);
+
+// As much stack as the main thread gets, for deeply nested input
+const PREVIEW_STACK: usize = 8 << 20;
+
+pub struct Editor {
+    // Oldest first
+    history: Vec<String>,
+    // Where history is saved; None when there's no data directory
+    path: Option<PathBuf>,
+    keys: Keys,
+}
+
+impl Editor {
+    // An editor with the history saved at `path`, if any
+    pub fn new(path: Option<PathBuf>) -> Editor {
+        let mut history: Vec<String> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        // The file only ever grows by appending, so trim it now and then
+        if history.len() > MAX_HISTORY {
+            history.drain(..history.len() - MAX_HISTORY);
+            if let Some(path) = &path {
+                let _ = fs::write(path, history.join("\n") + "\n");
+            }
+        }
+        Editor {
+            history,
+            path,
+            keys: Keys::default(),
+        }
+    }
+
+    // Reads one line after showing `prompt`; None once input has ended
+    pub fn read_line(&mut self, prompt: &str, ctx: &Context) -> io::Result<Option<String>> {
+        use std::io::IsTerminal;
+        let raw = if io::stdin().is_terminal() {
+            RawMode::enable()
+        } else {
+            None
+        };
+        let Some(_raw) = raw else {
+            print!("{}", prompt);
+            io::stdout().flush()?;
+            let mut line = String::new();
+            return match io::stdin().lock().read_line(&mut line)? {
+                // 0 bytes means stdin was closed (Ctrl-D or end of a piped file)
+                0 => Ok(None),
+                _ => Ok(Some(line)),
+            };
+        };
+        match self.edit(prompt, ctx) {
+            // The terminal went away, which is as good as Ctrl-D
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            result => result,
+        }
+    }
+
+    fn edit(&mut self, prompt: &str, ctx: &Context) -> io::Result<Option<String>> {
+        let mut line = Line::new(prompt, ctx);
+        line.render()?;
+        // Which history entry is shown; history.len() is the line being typed
+        let mut recalled = self.history.len();
+        let mut draft = Vec::new();
+        loop {
+            let key = self.keys.next()?;
+            match key {
+                Key::Enter => {
+                    line.finish()?;
+                    println!();
+                    let text: String = line.chars.iter().collect();
+                    self.remember(&text);
+                    return Ok(Some(text));
+                }
+                Key::Ctrl('d') if line.chars.is_empty() => {
+                    println!();
+                    return Ok(None);
+                }
+                Key::Ctrl('c') => {
+                    println!("^C\x1b[J");
+                    return Ok(Some(String::new()));
+                }
+                Key::Up | Key::Ctrl('p') | Key::Down | Key::Ctrl('n') => {
+                    let older = matches!(key, Key::Up | Key::Ctrl('p'));
+                    self.recall(&mut line, &mut recalled, &mut draft, older);
+                }
+                Key::Ctrl('r') => {
+                    if let Some(accepted) = self.search(&mut line)? {
+                        return Ok(Some(accepted));
+                    }
+                }
+                Key::Tab => self.complete(&mut line)?,
+                Key::Ctrl('l') => print!("\x1b[H\x1b[2J"),
+                key => line.edit(key),
+            }
+            // Pasted text arrives all at once; draw it once it's all in
+            if self.keys.pending.is_empty() {
+                line.render()?;
+            }
+        }
+    }
+
+    // Up or Down: shows the entry before or after `recalled`, and past the
+    // newest one the line as it was before Up was first pressed. Nothing
+    // happens at either end.
+    fn recall(
+        &self,
+        line: &mut Line<'_>,
+        recalled: &mut usize,
+        draft: &mut Vec<char>,
+        older: bool,
+    ) {
+        let next = if older {
+            recalled.checked_sub(1)
+        } else {
+            Some(*recalled + 1).filter(|&next| next <= self.history.len())
+        };
+        let Some(next) = next else { return };
+        if *recalled == self.history.len() {
+            *draft = line.chars.clone();
+        }
+        *recalled = next;
+        line.chars = match self.history.get(next) {
+            Some(entry) => entry.chars().collect(),
+            None => draft.clone(),
+        };
+        line.cursor = line.chars.len();
+    }
+
+    // Ctrl-R: finds older and older lines containing what's typed. Enter
+    // runs the match, Ctrl-G or Escape goes back to the line as it was,
+    // and any other key keeps the match for editing. Returns the line if
+    // it was accepted with Enter.
+    fn search(&mut self, line: &mut Line<'_>) -> io::Result<Option<String>> {
+        let original = (line.chars.clone(), line.cursor);
+        let mut query = String::new();
+        // The entry the match is in, searching back from the newest
+        let mut found = self.history.len();
+        let mut failed = false;
+        loop {
+            let status = if failed { "failed " } else { "" };
+            let label = format!("({}reverse-i-search)`{}': ", status, query);
+            line.render_with(&label)?;
+            let key = self.keys.next()?;
+            let from = match key {
+                Key::Char(c) => {
+                    query.push(c);
+                    found
+                }
+                Key::Backspace => {
+                    query.pop();
+                    self.history.len()
+                }
+                Key::Ctrl('r') => found.saturating_sub(1),
+                Key::Ctrl('g' | 'c') | Key::Escape => {
+                    (line.chars, line.cursor) = original;
+                    return Ok(None);
+                }
+                Key::Enter => {
+                    line.finish()?;
+                    println!();
+                    let text: String = line.chars.iter().collect();
+                    self.remember(&text);
+                    return Ok(Some(text));
+                }
+                key => {
+                    line.edit(key);
+                    return Ok(None);
+                }
+            };
+            if query.is_empty() {
+                continue;
+            }
+            let hit = self.find(&query, from);
+            failed = hit.is_none();
+            if let Some(hit) = hit {
+                found = hit;
+                let entry = &self.history[hit];
+                let at = entry.find(&query).unwrap_or(0);
+                line.chars = entry.chars().collect();
+                line.cursor = entry[..at].chars().count();
+            }
+        }
+    }
+
+    // The newest entry at or before `from` that contains `query`
+    fn find(&self, query: &str, from: usize) -> Option<usize> {
+        self.history[..(from + 1).min(self.history.len())]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+
+    // Tab: fills in a unique completion, or as much as the candidates
+    // share; if that adds nothing, lists them under the line
+    fn complete(&mut self, line: &mut Line<'_>) -> io::Result<()> {
+        let (start, candidates) = completion::complete(line.ctx, &line.chars, line.cursor);
+        let typed = line.cursor - start;
+        let fill = if candidates.len() == 1 {
+            candidates[0].clone()
+        } else {
+            completion::common_prefix(&candidates)
+        };
+        if fill.chars().count() > typed {
+            let rest: Vec<char> = fill.chars().skip(typed).collect();
+            line.insert(&rest);
+            return Ok(());
+        }
+        if candidates.len() > 1 {
+            line.finish()?;
+            println!();
+            print_columns(&candidates);
+        }
+        Ok(())
+    }
+
+    // Adds an entered line to the history, skipping blanks and repeats
+    fn remember(&mut self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() || self.history.last().is_some_and(|last| last == text) {
+            return;
+        }
+        self.history.push(text.to_string());
+        // History is a convenience; failing to save it isn't worth a message
+        if let Some(path) = &self.path {
+            let _ = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| OpenOptions::new().create(true).append(true).open(path))
+                .and_then(|mut file| writeln!(file, "{}", text));
+        }
+    }
+}
+
+// The preview of `text`, worked out on another thread while this one
+// watches the keyboard. A key press, Ctrl-C included, stops it and gives
+// None; running past PREVIEW_BUDGET stops it and gives no preview.
+fn background_preview(ctx: &Context, text: String) -> Option<Option<String>> {
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let mut ctx = ctx.clone();
+    ctx.interrupt = Some(Arc::clone(&interrupt));
+    let (sender, receiver) = mpsc::channel();
+    let spawned = thread::Builder::new()
+        .stack_size(PREVIEW_STACK)
+        .spawn(move || {
+            let _ = sender.send(highlight::preview(&ctx, &text));
+        });
+    if spawned.is_err() {
+        return Some(None);
+    }
+    let start = Instant::now();
+    loop {
+        match receiver.recv_timeout(PREVIEW_POLL) {
+            Ok(preview) => return Some(preview),
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Some(None),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+        }
+        // The thread notices at its next step and finishes on its own
+        if terminal::key_waiting(Duration::ZERO) {
+            interrupt.store(true, Ordering::Relaxed);
+            return None;
+        }
+        if start.elapsed() > PREVIEW_BUDGET {
+            interrupt.store(true, Ordering::Relaxed);
+            return Some(None);
+        }
+    }
+}
+
+// Candidates in columns across the terminal, like a shell's listing
+fn print_columns(candidates: &[String]) {
+    let width = terminal::width().unwrap_or(80);
+    let column = candidates
+        .iter()
+        .map(|candidate| candidate.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let per_row = (width / column).max(1);
+    for row in candidates.chunks(per_row) {
+        let cells: Vec<String> = row
+            .iter()
+            .map(|candidate| format!("{:<column$}", candidate, column = column))
+            .collect();
+        println!("{}", cells.concat().trim_end());
+    }
+}
+
+// The line being edited and where the cursor is, as a char index
+struct Line<'a> {
+    prompt: String,
+    chars: Vec<char>,
+    cursor: usize,
+    // The first char shown, when the line is wider than the terminal
+    scroll: usize,
+    // For highlighting names and previewing the result
+    ctx: &'a Context,
+    color: bool,
+    // The last preview worked out, and the text it was for
+    preview: Option<(Vec<char>, Option<String>)>,
+}
+
+impl<'a> Line<'a> {
+    fn new(prompt: &str, ctx: &'a Context) -> Line<'a> {
+        Line {
+            prompt: prompt.to_string(),
+            chars: Vec::new(),
+            cursor: 0,
+            scroll: 0,
+            ctx,
+            color: highlight::enabled(),
+            preview: None,
+        }
+    }
+
+    fn insert(&mut self, text: &[char]) {
+        self.chars
+            .splice(self.cursor..self.cursor, text.iter().copied());
+        self.cursor += text.len();
+    }
+
+    // The keys that only change the text or move the cursor
+    fn edit(&mut self, key: Key) {
+        let len = self.chars.len();
+        match key {
+            Key::Char(c) => self.insert(&[c]),
+            Key::Backspace | Key::Ctrl('h') if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+            }
+            Key::Delete | Key::Ctrl('d') if self.cursor < len => {
+                self.chars.remove(self.cursor);
+            }
+            Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right | Key::Ctrl('f') => self.cursor = (self.cursor + 1).min(len),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = len,
+            Key::WordLeft => self.cursor = self.word_start(),
+            Key::WordRight => {
+                while self.cursor < len && !self.chars[self.cursor].is_alphanumeric() {
+                    self.cursor += 1;
+                }
+                while self.cursor < len && self.chars[self.cursor].is_alphanumeric() {
+                    self.cursor += 1;
+                }
+            }
+            Key::Ctrl('w') => {
+                let start = self.word_start();
+                self.chars.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            Key::Ctrl('u') => {
+                self.chars.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Ctrl('k') => self.chars.truncate(self.cursor),
+            _ => {}
+        }
+    }
+
+    // Where the word before the cursor starts, skipping spaces first
+    fn word_start(&self) -> usize {
+        let mut start = self.cursor;
+        while start > 0 && !self.chars[start - 1].is_alphanumeric() {
+            start -= 1;
+        }
+        while start > 0 && self.chars[start - 1].is_alphanumeric() {
+            start -= 1;
+        }
+        start
+    }
+
+    // Redraws the line while it's being edited, with the preview below it
+    fn render(&mut self) -> io::Result<()> {
+        let prompt = self.prompt.clone();
+        self.draw(&prompt, true, true)
+    }
+
+    // Redraws the line as it will stay on screen once entered: cursor at
+    // the end and no preview
+    fn finish(&mut self) -> io::Result<()> {
+        let prompt = self.prompt.clone();
+        self.cursor = self.chars.len();
+        self.draw(&prompt, true, false)
+    }
+
+    // Redraws the line after another prompt, like Ctrl-R's, without colors
+    fn render_with(&mut self, prompt: &str) -> io::Result<()> {
+        self.draw(prompt, false, false)
+    }
+
+    // The line after `prompt`, scrolled sideways if it doesn't fit. The
+    // preview goes on the line below; clearing to the end of the screen
+    // removes an old one.
+    fn draw(&mut self, prompt: &str, highlighted: bool, preview: bool) -> io::Result<()> {
+        let width = terminal::width().unwrap_or(80);
+        let prompt_width = prompt.chars().count();
+        let room = width.saturating_sub(prompt_width + 1).max(10);
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        } else if self.cursor > self.scroll + room {
+            self.scroll = self.cursor - room;
+        }
+        let end = self.chars.len().min(self.scroll + room);
+        let shown = &self.chars[self.scroll..end];
+        let shown = if highlighted && self.color {
+            let styles = highlight::styles(self.ctx, &self.chars, self.cursor);
+            highlight::paint(shown, &styles[self.scroll..end])
+        } else {
+            shown.iter().collect()
+        };
+        let preview = if preview { self.preview() } else { None };
+        let column = prompt_width + self.cursor - self.scroll;
+        let mut out = io::stdout().lock();
+        write!(out, "\r{}{}\x1b[J", prompt, shown)?;
+        if let Some(preview) = preview {
+            let preview: String = preview.chars().take(width.saturating_sub(1)).collect();
+            if self.color {
+                write!(out, "\n\x1b[2m{}\x1b[0m\x1b[A", preview)?;
+            } else {
+                write!(out, "\n{}\x1b[A", preview)?;
+            }
+        }
+        write!(out, "\r")?;
+        if column > 0 {
+            write!(out, "\x1b[{}C", column)?;
+        }
+        out.flush()
+    }
+
+    // Worked out again only when the text changes, not as the cursor moves
+    fn preview(&mut self) -> Option<String> {
+        let stale = self
+            .preview
+            .as_ref()
+            .is_none_or(|(chars, _)| *chars != self.chars);
+        if stale {
+            let text: String = self.chars.iter().collect();
+            // Cut short by a key press: the key's own redraw tries again
+            let preview = background_preview(self.ctx, text)?;
+            self.preview = Some((self.chars.clone(), preview));
+        }
+        self.preview
+            .as_ref()
+            .and_then(|(_, preview)| preview.clone())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    // Ctrl plus a letter, as the lowercase letter
+    Ctrl(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Escape,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    WordLeft,
+    WordRight,
+    Unknown,
+}
+
+// Turns the bytes a terminal sends into keys. Escape sequences arrive in
+// one read, so an escape byte with nothing after it is the Escape key.
+#[derive(Default)]
+struct Keys {
+    pending: VecDeque<u8>,
+}
+
+impl Keys {
+    fn next(&mut self) -> io::Result<Key> {
+        let byte = self.byte()?;
+        let key = match byte {
+            b'\r' | b'\n' => Key::Enter,
+            b'\t' => Key::Tab,
+            0x7f | 0x08 => Key::Backspace,
+            0x1b if self.pending.is_empty() => Key::Escape,
+            0x1b => self.escape()?,
+            1..=26 => Key::Ctrl((b'a' + byte - 1) as char),
+            0..=31 => Key::Unknown,
+            _ => self.char(byte)?,
+        };
+        Ok(key)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        if self.pending.is_empty() {
+            let mut buffer = [0; 64];
+            let n = io::stdin().lock().read(&mut buffer)?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.pending.extend(&buffer[..n]);
+        }
+        Ok(self.pending.pop_front().expect("filled above"))
+    }
+
+    // A UTF-8 character starting with `first`
+    fn char(&mut self, first: u8) -> io::Result<Key> {
+        let len = match first {
+            0xf0.. => 4,
+            0xe0.. => 3,
+            0xc0.. => 2,
+            _ => 1,
+        };
+        let mut bytes = vec![first];
+        for _ in 1..len {
+            bytes.push(self.byte()?);
+        }
+        Ok(std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|text| text.chars().next())
+            .map_or(Key::Unknown, Key::Char))
+    }
+
+    // The rest of an escape sequence: ESC [ or ESC O, any parameters, then
+    // a final letter or '~', e.g. ESC [ A for Up or ESC [ 3 ~ for Delete
+    fn escape(&mut self) -> io::Result<Key> {
+        let kind = self.byte()?;
+        if kind != b'[' && kind != b'O' {
+            return Ok(Key::Unknown);
+        }
+        let mut params = String::new();
+        let last = loop {
+            let byte = self.byte()?;
+            if (0x40..=0x7e).contains(&byte) {
+                break byte;
+            }
+            params.push(byte as char);
+        };
+        let key = match (params.as_str(), last) {
+            ("", b'A') => Key::Up,
+            ("", b'B') => Key::Down,
+            ("", b'C') => Key::Right,
+            ("", b'D') => Key::Left,
+            ("", b'H') | ("1" | "7", b'~') => Key::Home,
+            ("", b'F') | ("4" | "8", b'~') => Key::End,
+            ("3", b'~') => Key::Delete,
+            ("1;5" | "1;3", b'C') => Key::WordRight,
+            ("1;5" | "1;3", b'D') => Key::WordLeft,
+            _ => Key::Unknown,
+        };
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The keys a terminal's bytes decode to, as if they arrived in one read
+    fn keys(bytes: &[u8]) -> Vec<Key> {
+        let mut keys = Keys {
+            pending: bytes.iter().copied().collect(),
+        };
+        let mut decoded = Vec::new();
+        while !keys.pending.is_empty() {
+            decoded.push(keys.next().unwrap());
+        }
+        decoded
+    }
+
+    fn editor(history: &[&str]) -> Editor {
+        Editor {
+            history: history.iter().map(|entry| entry.to_string()).collect(),
+            path: None,
+            keys: Keys::default(),
+        }
+    }
+
+    fn text(line: &Line<'_>) -> String {
+        line.chars.iter().collect()
+    }
+
+    #[test]
+    fn decoding_keys() {
+        assert_eq!(
+            keys(b"a1\r\t\x7f\x01\x12"),
+            [
+                Key::Char('a'),
+                Key::Char('1'),
+                Key::Enter,
+                Key::Tab,
+                Key::Backspace,
+                Key::Ctrl('a'),
+                Key::Ctrl('r'),
+            ]
+        );
+        assert_eq!(keys("π√".as_bytes()), [Key::Char('π'), Key::Char('√')]);
+        assert_eq!(keys(b"\x1b"), [Key::Escape]);
+    }
+
+    #[test]
+    fn decoding_escape_sequences() {
+        assert_eq!(
+            keys(b"\x1b[A\x1b[B\x1b[C\x1b[D\x1bOA"),
+            [Key::Up, Key::Down, Key::Right, Key::Left, Key::Up]
+        );
+        assert_eq!(
+            keys(b"\x1b[H\x1b[1~\x1b[7~\x1b[F\x1b[4~\x1b[8~\x1b[3~"),
+            [
+                Key::Home,
+                Key::Home,
+                Key::Home,
+                Key::End,
+                Key::End,
+                Key::End,
+                Key::Delete,
+            ]
+        );
+        assert_eq!(keys(b"\x1b[1;5C\x1b[1;3D"), [Key::WordRight, Key::WordLeft]);
+        // Unknown sequences are swallowed whole, not typed as text
+        assert_eq!(keys(b"\x1b[15~x"), [Key::Unknown, Key::Char('x')]);
+        assert_eq!(keys(b"\x1bx"), [Key::Unknown]);
+    }
+
+    #[test]
+    fn editing_keys() {
+        let ctx = Context::new();
+        let mut line = Line::new("> ", &ctx);
+        line.insert(&"sqrt(2) + pi".chars().collect::<Vec<_>>());
+        line.edit(Key::Ctrl('w'));
+        assert_eq!(text(&line), "sqrt(2) + ");
+        line.edit(Key::WordLeft);
+        line.edit(Key::Backspace);
+        assert_eq!((text(&line).as_str(), line.cursor), ("sqrt2) + ", 4));
+        line.edit(Key::Ctrl('k'));
+        line.edit(Key::Home);
+        line.edit(Key::Delete);
+        assert_eq!(text(&line), "qrt");
+        line.edit(Key::End);
+        line.edit(Key::Ctrl('u'));
+        assert_eq!((text(&line).as_str(), line.cursor), ("", 0));
+    }
+
+    #[test]
+    fn recalling_history() {
+        let ctx = Context::new();
+        let editor = editor(&["1 + 1", "2 * 3"]);
+        let mut line = Line::new("> ", &ctx);
+        line.insert(&['x']);
+        let mut recalled = 2;
+        let mut draft = Vec::new();
+        let mut step = |line: &mut Line<'_>, older| {
+            editor.recall(line, &mut recalled, &mut draft, older);
+            text(line)
+        };
+        assert_eq!(step(&mut line, true), "2 * 3");
+        assert_eq!(step(&mut line, true), "1 + 1");
+        // The oldest entry stays put, and so does the line being typed
+        assert_eq!(step(&mut line, true), "1 + 1");
+        assert_eq!(step(&mut line, false), "2 * 3");
+        assert_eq!(step(&mut line, false), "x");
+        assert_eq!(step(&mut line, false), "x");
+        assert_eq!(line.cursor, 1);
+    }
+
+    #[test]
+    fn searching_history() {
+        let editor = editor(&["sin(1)", "2 + 2", "sin(2)", "cos(1)"]);
+        assert_eq!(editor.find("sin", 4), Some(2));
+        // Ctrl-R again searches from before the last match
+        assert_eq!(editor.find("sin", 1), Some(0));
+        assert_eq!(editor.find("sin", 0), Some(0));
+        assert_eq!(editor.find("(1", 4), Some(3));
+        assert_eq!(editor.find("tan", 4), None);
+    }
+
+    #[test]
+    fn saving_history() {
+        let dir = std::env::temp_dir().join(format!("calculator-history-{}", std::process::id()));
+        let path = dir.join("history");
+        let mut editor = Editor::new(Some(path.clone()));
+        assert!(editor.history.is_empty());
+        for line in ["1 + 1", "  ", "2 * 3", "2 * 3 ", "ans"] {
+            editor.remember(line);
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1 + 1\n2 * 3\nans\n");
+        assert_eq!(Editor::new(Some(path.clone())).history, editor.history);
+
+        // A file that has grown too long loses its oldest lines
+        let lines: Vec<String> = (0..MAX_HISTORY + 5).map(|n| n.to_string()).collect();
+        fs::write(&path, lines.join("\n")).unwrap();
+        let editor = Editor::new(Some(path.clone()));
+        assert_eq!(editor.history.len(), MAX_HISTORY);
+        assert_eq!(editor.history[0], "5");
+        assert_eq!(
+            fs::read_to_string(&path).unwrap().lines().count(),
+            MAX_HISTORY
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+// Colors for the line being typed, and the preview of its result shown
+// underneath. Numbers, operators, functions, variables and units each get
+// a color, names the calculator doesn't know are red, and so is any
+// bracket without a partner, or with one of the other kind: ( with ].
+// The bracket next to the cursor and its match are shown in reverse video.
+//
+// Set NO_COLOR to turn the colors off; the preview stays.
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+use cli_calculator::builtins;
+use cli_calculator::lexer::{self, Token, TokenKind};
+use cli_calculator::{Context, Expr, Statement, Value, eval, matrix, rpn, units};
+
+use crate::commands;
+
+// A matrix with more elements than this is previewed by its shape, since
+// the preview line can't show it anyway and laying it out takes a while
+const PREVIEW_ELEMENTS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Plain,
+    Number,
+    Operator,
+    Function,
+    Variable,
+    Unit,
+    Command,
+    Comment,
+    // An unknown name, an unpaired bracket or text the lexer rejects
+    Error,
+    // The bracket by the cursor and its partner
+    Matched,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Plain | Style::Variable => "\x1b[0m",
+            Style::Number => "\x1b[0;36m",
+            Style::Operator => "\x1b[0;33m",
+            Style::Function => "\x1b[0;34m",
+            Style::Unit => "\x1b[0;32m",
+            Style::Command => "\x1b[0;1;35m",
+            Style::Comment => "\x1b[0;2m",
+            Style::Error => "\x1b[0;1;31m",
+            Style::Matched => "\x1b[0;7m",
+        }
+    }
+}
+
+// Whether to color at all: not when output goes to a file or NO_COLOR is set
+pub fn enabled() -> bool {
+    io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+// `chars` with escape codes for `styles`, which has one entry per char
+pub fn paint(chars: &[char], styles: &[Style]) -> String {
+    let mut out = String::new();
+    let mut current = Style::Plain;
+    for (&c, &style) in chars.iter().zip(styles) {
+        if style != current {
+            out.push_str(style.code());
+            current = style;
+        }
+        out.push(c);
+    }
+    if current != Style::Plain {
+        out.push_str(Style::Plain.code());
+    }
+    out
+}
+
+// The style of each char of `line`, with the cursor at char index `cursor`
+pub fn styles(ctx: &Context, line: &[char], cursor: usize) -> Vec<Style> {
+    let mut styles = vec![Style::Plain; line.len()];
+    let text: String = line.iter().collect();
+
+    // Commands take words, not expressions
+    if let Some(command) = commands::command_name(ctx, &text) {
+        let start = line.iter().take_while(|c| c.is_whitespace()).count();
+        let len = command.chars().count();
+        styles[start..start + len].fill(Style::Command);
+        return styles;
+    }
+
+    // Tokenize as far as possible; whatever the lexer rejects is an error
+    let (tokens, rest) = match lexer::tokenize(&text) {
+        Ok(tokens) => (tokens, line.len()),
+        Err(error) => {
+            let start = error.span().start.min(line.len());
+            styles[start..].fill(Style::Error);
+            let before: String = line[..start].iter().collect();
+            (lexer::tokenize(&before).unwrap_or_default(), start)
+        }
+    };
+    // Everything after the last token is a comment, unless it's an error
+    let code_end = tokens.last().map_or(0, |token| token.span.end);
+    if let Some(hash) = line[..rest].iter().position(|&c| c == '#')
+        && hash >= code_end
+    {
+        styles[hash..rest].fill(Style::Comment);
+    }
+
+    let locals = locals(&text);
+    for (i, token) in tokens.iter().enumerate() {
+        let next = tokens.get(i + 1).map(|token| &token.kind);
+        let style = match &token.kind {
+            TokenKind::Number(_)
+            | TokenKind::Angle(..)
+            | TokenKind::Imaginary(_)
+            | TokenKind::HistoryRef(_) => Style::Number,
+            TokenKind::Ident(name) => name_style(ctx, name, next, &locals),
+            TokenKind::LParen
+            | TokenKind::RParen
+            | TokenKind::LBracket
+            | TokenKind::RBracket
+            | TokenKind::Comma => Style::Plain,
+            _ => Style::Operator,
+        };
+        let span = token.span.start.min(line.len())..token.span.end.min(line.len());
+        styles[span].fill(style);
+    }
+
+    match_brackets(&tokens, cursor, &mut styles);
+    styles
+}
+
+// Names the line itself brings in: what an assignment defines, a
+// function definition's name and parameters, and the unknowns of solve()
+fn locals(text: &str) -> Vec<String> {
+    match cli_calculator::parse_statement(text) {
+        Ok(Statement::Assign { name, .. }) => vec![name],
+        Ok(Statement::Define { name, params, .. }) => {
+            params.into_iter().chain(std::iter::once(name)).collect()
+        }
+        Ok(Statement::Expr(expr)) => eval::equations(&expr)
+            .map(|equations| equations.iter().flat_map(Expr::names).collect())
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+fn name_style(ctx: &Context, name: &str, next: Option<&TokenKind>, locals: &[String]) -> Style {
+    let known_function = builtins::lookup(name).is_some() || ctx.funcs.contains_key(name);
+    if next == Some(&TokenKind::LParen) && (known_function || locals.iter().any(|l| l == name)) {
+        return Style::Function;
+    }
+    if matches!(name, "in" | "to" | "xor") {
+        return Style::Operator;
+    }
+    let variable = ctx.vars.contains_key(name)
+        || builtins::constant(name).is_some()
+        || name == "ans"
+        || locals.iter().any(|local| local == name)
+        || (ctx.settings.rpn && rpn::STACK_WORDS.contains(&name));
+    if variable {
+        Style::Variable
+    } else if units::lookup(name).is_some() {
+        Style::Unit
+    } else if known_function {
+        // A function name without its parentheses, fine in RPN
+        Style::Function
+    } else {
+        Style::Error
+    }
+}
+
+// Marks brackets without a partner as errors, and the bracket just before
+// or under the cursor together with its partner as matched
+fn match_brackets(tokens: &[Token], cursor: usize, styles: &mut [Style]) {
+    // Where each open bracket is, and whether it's a square one
+    let mut open: Vec<(usize, bool)> = Vec::new();
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    for token in tokens {
+        let at = token.span.start;
+        match token.kind {
+            TokenKind::LParen => open.push((at, false)),
+            TokenKind::LBracket => open.push((at, true)),
+            TokenKind::RParen | TokenKind::RBracket => {
+                let square = token.kind == TokenKind::RBracket;
+                match open.pop() {
+                    Some((start, kind)) if kind == square => pairs.push((start, at)),
+                    Some((start, _)) => {
+                        styles[start] = Style::Error;
+                        styles[at] = Style::Error;
+                    }
+                    None => styles[at] = Style::Error,
+                }
+            }
+            _ => {}
+        }
+    }
+    for (at, _) in open {
+        styles[at] = Style::Error;
+    }
+    let by_cursor = [cursor.checked_sub(1), Some(cursor)]
+        .into_iter()
+        .flatten()
+        .find_map(|at| pairs.iter().find(|(start, end)| *start == at || *end == at));
+    if let Some(&(start, end)) = by_cursor {
+        styles[start] = Style::Matched;
+        styles[end] = Style::Matched;
+    }
+}
+
+// What the line would give if Enter were pressed now, as "= 42"; None
+// while it's incomplete or wrong, and for commands. Nothing is changed:
+// assignments and definitions aren't made, and RPN runs on a copy of the
+// stack.
+pub fn preview(ctx: &Context, line: &str) -> Option<String> {
+    let input = line.trim();
+    if commands::command_name(ctx, input).is_some() {
+        return None;
+    }
+    let value = if crate::is_rpn(ctx, input) {
+        let mut scratch = ctx.clone();
+        rpn::run(&mut scratch, input).ok()?;
+        scratch.stack.pop()?
+    } else {
+        match cli_calculator::parse_statement(input).ok()? {
+            Statement::Expr(expr) => match eval::equations(&expr) {
+                // "x = 4/5, y = 7/5" names itself, so there's no "=" in front
+                Some(equations) => {
+                    let solution = ctx.solve(equations, expr.span).ok()?;
+                    let parts: Vec<String> = solution
+                        .iter()
+                        .map(|(name, value)| format!("{} = {}", name, ctx.settings.format(value)))
+                        .collect();
+                    return Some(parts.join(", ").replace('\n', ""));
+                }
+                None => ctx.eval(&expr).ok()?,
+            },
+            Statement::Assign { value, .. } => ctx.eval(&value).ok()?,
+            Statement::Define { .. } => return None,
+        }
+    };
+    let text = match &value {
+        Value::Matrix(m) if m.elems().len() > PREVIEW_ELEMENTS => matrix::describe(&value),
+        // The preview has one line, so a matrix's rows go side by side
+        _ => ctx.settings.format(&value).replace('\n', ""),
+    };
+    // "= 42" under "42" says nothing new
+    (text != input).then(|| format!("= {}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One letter per char, to line up under the input: Number, Operator,
+    // Function, Variable, Unit, Command, # for comments, Error, Matched
+    // and '.' for plain
+    fn marks(ctx: &Context, line: &str, cursor: usize) -> String {
+        let chars: Vec<char> = line.chars().collect();
+        styles(ctx, &chars, cursor)
+            .into_iter()
+            .map(|style| match style {
+                Style::Plain => '.',
+                Style::Number => 'N',
+                Style::Operator => 'O',
+                Style::Function => 'F',
+                Style::Variable => 'V',
+                Style::Unit => 'U',
+                Style::Command => 'C',
+                Style::Comment => '#',
+                Style::Error => 'E',
+                Style::Matched => 'M',
+            })
+            .collect()
+    }
+
+    // Styles with the cursor far from any bracket
+    fn colors(ctx: &Context, line: &str) -> String {
+        marks(ctx, line, usize::MAX - 1)
+    }
+
+    #[test]
+    fn token_colors() {
+        let mut ctx = Context::new();
+        ctx.run("rate = 2").unwrap();
+        assert_eq!(colors(&ctx, "sqrt(2) + pi * rate"), "FFFF.N..O.VV.O.VVVV");
+        assert_eq!(colors(&ctx, "5 km in m"), "N.UU.OO.U");
+        assert_eq!(colors(&ctx, "30deg + 2i + $1"), "NNNNN.O.NN.O.NN");
+        assert_eq!(colors(&ctx, "3 xor 5"), "N.OOO.N");
+        assert_eq!(colors(&ctx, "2 + 3 # sum"), "N.O.N.#####");
+        // Unknown names, and whatever the lexer can't read, are errors
+        assert_eq!(colors(&ctx, "x + nope"), "E.O.EEEE");
+        assert_eq!(colors(&ctx, "2 $ 3"), "N.EEE");
+        // A line's own names: the function and parameter being defined,
+        // and the unknowns of solve()
+        assert_eq!(colors(&ctx, "f(x) = x^2"), "F.V..O.VON");
+        assert_eq!(
+            colors(&ctx, "solve(2x + y = 1, x - y = 2)"),
+            "FFFFF.NV.O.V.O.N..V.O.V.O.N."
+        );
+    }
+
+    #[test]
+    fn command_lines() {
+        let mut ctx = Context::new();
+        assert_eq!(colors(&ctx, "mode deg"), "CCCC....");
+        assert_eq!(colors(&ctx, "  vars"), "..CCCC");
+        // Assigning to a command's name is an expression
+        assert_eq!(colors(&ctx, "base = 16"), "VVVV.O.NN");
+        ctx.run("base = 16").unwrap();
+        assert_eq!(colors(&ctx, "base * 2"), "VVVV.O.N");
+    }
+
+    #[test]
+    fn brackets() {
+        let ctx = Context::new();
+        // Just after or on a bracket, that bracket and its partner
+        assert_eq!(marks(&ctx, "((1) + [2])", 0), "M.N..O..N.M");
+        assert_eq!(marks(&ctx, "((1) + [2])", 11), "M.N..O..N.M");
+        assert_eq!(marks(&ctx, "((1) + [2])", 3), ".MNM.O..N..");
+        assert_eq!(marks(&ctx, "((1) + [2])", 4), ".MNM.O..N..");
+        assert_eq!(marks(&ctx, "((1) + [2])", 6), "..N..O..N..");
+        // Unpaired or mismatched brackets are errors, wherever the cursor is
+        assert_eq!(marks(&ctx, "(1 + (2)", 8), "EN.O.MNM");
+        assert_eq!(marks(&ctx, "1) + 2", 0), "NE.O.N");
+        assert_eq!(marks(&ctx, "(1]", 3), "ENE");
+        assert_eq!(marks(&ctx, "[1, 2)", 6), "EN..NE");
+    }
+
+    #[test]
+    fn previews() {
+        let mut ctx = Context::new();
+        assert_eq!(preview(&ctx, "2 + 2"), Some("= 4".to_string()));
+        assert_eq!(preview(&ctx, "solve(2x = 4)"), Some("x = 2".to_string()));
+        assert_eq!(preview(&ctx, "x = 3"), Some("= 3".to_string()));
+        assert_eq!(preview(&ctx, " 2  "), None);
+        assert_eq!(preview(&ctx, "1 +"), None);
+        assert_eq!(preview(&ctx, "f(x) = x"), None);
+        // Nothing is assigned while previewing
+        assert!(ctx.vars.is_empty());
+        // Commands would do something, so they're not previewed
+        assert_eq!(preview(&ctx, "mode deg"), None);
+        assert_eq!(preview(&ctx, "vars"), None);
+        assert_eq!(preview(&ctx, "mode = 2"), Some("= 2".to_string()));
+        ctx.settings.rpn = true;
+        assert_eq!(preview(&ctx, "3 4 +"), Some("= 7".to_string()));
+        assert!(ctx.stack.is_empty());
+    }
+}
@@ -0,0 +1,619 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process::ExitCode;
+
+// The engine lives in the library crate (src/lib.rs); this file is only the
+// front end. `cli_calculator::` is like importing from an npm package.
+use cli_calculator::{
+    ComplexForm, Context, IntType, NumberMode, Outcome, Rates, Settings, Value, eval, rpn, session,
+};
+
+mod commands;
+mod completion;
+mod data;
+mod editor;
+mod highlight;
+mod report;
+mod terminal;
+
+use editor::Editor;
+
+// Where to find exchange rates when --rates isn't given
+const RATES_VAR: &str = "CALC_RATES";
+
+const USAGE: &str = "\
+Usage: calculator [options]                 start the interactive calculator
+       calculator [options] <expression>    evaluate one expression and print the result
+       calculator [options] -f <file>       evaluate every line of a file
+       calculator [options] --batch         evaluate every line read from stdin
+
+Options:
+       --decimal            use exact decimal arithmetic instead of floating point
+       --precision <n>      decimal arithmetic with n significant digits (default 34)
+       --rounding <mode>    half-even (default), half-up or truncate
+       --mixed              show fractions as mixed numbers, e.g. 3 1/2 instead of 7/2
+       --polar              show complex numbers as magnitude and angle, e.g. 2 ∠ 90deg
+       --rates <file>       exchange rates for currencies like 120 EUR in USD, as CSV
+                            or JSON (default: the file named by $CALC_RATES, if set)
+       --int <type>         fixed-width integers: i8, i16, i32, i64, i128 or u8 ... u128
+       --wrap               let fixed-width integers wrap around instead of failing
+       --base <n>           show whole numbers in base 2, 8, 10 or 16
+       --format <style>     auto (default), \"fix 2\", \"sig 6\", \"sci 6\" or \"eng 3\"
+       --group              thousands separators in every number, e.g. 1,234,567.5
+       --mark <mark>        the decimal mark: . (default) or , or a language like de
+       --json               print each result as a JSON object, errors included
+       --rpn                Reverse Polish Notation: \"3 4 + 2 *\" instead of \"(3 + 4) * 2\"
+       --restore            start from the session saved when the calculator last
+                            exited; options given with it override its settings
+
+Sessions are saved under $CALC_DATA_DIR, or $XDG_DATA_HOME/calculator
+(~/.local/share/calculator). The interactive calculator saves to 'last' on exit.
+
+A name right after a value is a unit even if a variable has the same name:
+with m = 5, 3 m is still 3 metres, and 3 * m is 15.
+
+In RPN mode a unit word goes on the value on top of the stack, so 5 km 3 m +
+adds 3 m to 5 km. On top of a value that already has a unit it pushes the unit
+instead, for 'in' to convert to: 5 km mi in.
+
+In files and batch input, blank lines and lines starting with '#' are skipped,
+and variables and functions carry over from one line to the next.
+
+Examples:
+       calculator \"2*(3+4)\"
+       calculator --precision 50 \"1/7\"
+       calculator \"60 mph * 2 h in km\"
+       calculator \"inverse([[2,1],[1,3]]) * [3,5]\"
+       calculator \"solve(2x + y = 3, x + 3y = 5)\"
+       calculator --int u8 --base 16 \"0xf0 | 0b1010\"
+       calculator -f sheet.calc
+       cat exprs.txt | calculator --batch --json";
+
+// What the command-line arguments asked for
+enum Mode {
+    Repl,
+    Help,
+    Once(String),
+    File(String),
+    Batch,
+}
+
+// Everything the options set up besides the mode
+struct Options {
+    settings: Settings,
+    // Whether any option changed the settings, so they win over restored ones
+    settings_given: bool,
+    rates: Option<String>,
+    json: bool,
+    restore: bool,
+}
+
+// Options come first; the first word that isn't one starts the expression,
+// so "calculator 2 + 3" and "calculator -5 * 2" both work
+fn parse_args(args: &[String]) -> Result<(Mode, Options), String> {
+    let mut settings = Settings::default();
+    let mut rates = env::var(RATES_VAR).ok();
+    let mut json = false;
+    let mut restore = false;
+    let mut mode = None;
+    let mut i = 0;
+
+    while i < args.len() {
+        let arg = args[i].as_str();
+        // The value after an option like --precision, if there is one
+        let value = args.get(i + 1).map(String::as_str);
+        let next_mode = match arg {
+            "-h" | "--help" => {
+                let options = Options {
+                    settings,
+                    settings_given: false,
+                    rates,
+                    json,
+                    restore,
+                };
+                return Ok((Mode::Help, options));
+            }
+            "-f" | "--file" => {
+                let path = value.ok_or("-f needs a file name")?;
+                i += 1;
+                Some(Mode::File(path.to_string()))
+            }
+            "--batch" => Some(Mode::Batch),
+            "--decimal" => {
+                settings.numbers = NumberMode::Decimal;
+                None
+            }
+            "--precision" => {
+                let digits = value.ok_or("--precision needs a number of digits")?;
+                settings.decimal.precision = commands::parse_precision(digits)?;
+                settings.numbers = NumberMode::Decimal;
+                i += 1;
+                None
+            }
+            "--rounding" => {
+                let name = value.ok_or("--rounding needs a mode")?;
+                settings.decimal.rounding = commands::parse_rounding(name)?;
+                i += 1;
+                None
+            }
+            "--mixed" => {
+                settings.mixed_fractions = true;
+                None
+            }
+            "--polar" => {
+                settings.complex = ComplexForm::Polar;
+                None
+            }
+            "--int" => {
+                let name = value.ok_or("--int needs a type like u8 or i32")?;
+                let int = IntType::parse(name).ok_or_else(|| {
+                    format!(
+                        "unknown integer type '{}' (try i8 ... i128 or u8 ... u128)",
+                        name
+                    )
+                })?;
+                settings.numbers = NumberMode::Integer(int);
+                i += 1;
+                None
+            }
+            "--format" => {
+                let style = value.ok_or("--format needs a style like \"sci 6\"")?;
+                settings.output.notation = commands::parse_format(style)?;
+                i += 1;
+                None
+            }
+            "--group" => {
+                settings.output.group = true;
+                None
+            }
+            "--mark" => {
+                settings.output.mark = commands::parse_mark(value.ok_or("--mark needs . or ,")?)?;
+                i += 1;
+                None
+            }
+            "--json" => {
+                json = true;
+                None
+            }
+            "--restore" => {
+                restore = true;
+                None
+            }
+            "--rpn" => {
+                settings.rpn = true;
+                None
+            }
+            "--wrap" => {
+                settings.wrap = true;
+                None
+            }
+            "--base" => {
+                settings.radix = commands::parse_base(value.ok_or("--base needs a base")?)?;
+                i += 1;
+                None
+            }
+            "--rates" => {
+                rates = Some(value.ok_or("--rates needs a file name")?.to_string());
+                i += 1;
+                None
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+            _ => {
+                let expression = args[i..].join(" ");
+                i = args.len();
+                Some(Mode::Once(expression))
+            }
+        };
+        if let Some(next_mode) = next_mode {
+            if mode.is_some() {
+                return Err("give only one of -f, --batch or an expression".to_string());
+            }
+            mode = Some(next_mode);
+        }
+        i += 1;
+    }
+    let mode = mode.unwrap_or(Mode::Repl);
+    if json && matches!(mode, Mode::Repl) {
+        return Err("--json needs an expression, -f or --batch".to_string());
+    }
+    let options = Options {
+        settings_given: settings != Settings::default(),
+        settings,
+        rates,
+        json,
+        restore,
+    };
+    Ok((mode, options))
+}
+
+// A fresh session with the chosen settings and exchange rates, or with
+// --restore the one saved on the last exit (if there is one yet)
+fn start(options: &Options) -> Result<Context, String> {
+    let mut ctx = Context::new();
+    if options.restore {
+        let path = data::session_path(data::LAST_SESSION)?;
+        if path.exists() {
+            ctx = session::load(&path)?;
+        }
+    }
+    if !options.restore || options.settings_given {
+        ctx.settings = options.settings;
+    }
+    if let Some(path) = &options.rates {
+        ctx.rates = Rates::load(Path::new(path))?;
+    }
+    Ok(ctx)
+}
+
+// `ExitCode` lets main report success or failure to the shell, like
+// `process.exit(1)` in Node, so scripts can check `$?`.
+// 0 = success, 1 = an expression failed, 2 = bad arguments or unreadable input.
+fn main() -> ExitCode {
+    // skip(1) drops the program name, like process.argv.slice(2) in Node
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let (mode, options) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("calculator: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    if let Mode::Help = mode {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let ctx = match start(&options) {
+        Ok(ctx) => ctx,
+        Err(message) => {
+            eprintln!("calculator: {}", message);
+            return ExitCode::from(2);
+        }
+    };
+
+    match mode {
+        // Shown above, before any rate file is read
+        Mode::Help => ExitCode::SUCCESS,
+        Mode::Repl => {
+            repl(ctx);
+            ExitCode::SUCCESS
+        }
+        Mode::Once(input) => evaluate_once(&input, ctx, options.json),
+        Mode::File(path) => match fs::read_to_string(&path) {
+            Ok(text) => {
+                let lines = text.lines().map(|line| Ok(line.to_string()));
+                batch(&path, lines, ctx, options.json)
+            }
+            Err(e) => {
+                eprintln!("calculator: cannot read {}: {}", path, e);
+                ExitCode::from(2)
+            }
+        },
+        Mode::Batch => batch("<stdin>", io::stdin().lock().lines(), ctx, options.json),
+    }
+}
+
+// One-shot mode: print only the result on stdout, or the error on stderr.
+// With --json both go to stdout as one JSON object. Amounts of money add
+// the date of the exchange rates, on stderr or as a JSON field.
+fn evaluate_once(input: &str, mut ctx: Context, json: bool) -> ExitCode {
+    let outcome = if is_rpn(&ctx, input) {
+        // Only the top of the stack, like the result of an infix expression
+        rpn::run(&mut ctx, input).map(|_| ctx.stack.last().cloned().map(Outcome::Value))
+    } else {
+        ctx.run(input).map(Some)
+    };
+    if json {
+        return match outcome {
+            Ok(outcome) => {
+                println!("{}", report::outcome(input, outcome.as_ref(), &ctx.rates));
+                ExitCode::SUCCESS
+            }
+            Err(error) => {
+                println!("{}", report::error(input, &error, None));
+                ExitCode::FAILURE
+            }
+        };
+    }
+    match outcome {
+        Ok(Some(Outcome::Value(value) | Outcome::Assigned(_, value))) => {
+            println!("{}", ctx.settings.format(&value));
+            // On stderr, so scripts reading the result don't have to strip it
+            if let Some(note) = rates_note(&ctx, &value) {
+                eprintln!("({})", note);
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(Some(Outcome::Defined(function))) => {
+            println!("{}", function);
+            ExitCode::SUCCESS
+        }
+        Ok(Some(Outcome::Solved(solution))) => {
+            println!("{}", solution_text(&ctx, &solution));
+            ExitCode::SUCCESS
+        }
+        Ok(None) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error.render(input));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Batch mode: evaluate each line in order, printing one result per expression.
+// Errors, failed commands included, go to stderr as "file:line:column: message"
+// and don't stop the run, but make the exit code nonzero. The date of the
+// exchange rates behind amounts of money goes to stderr as "file:line: note".
+// With --json every line run, assignments, commands and errors included,
+// prints one JSON object on stdout instead.
+fn batch(
+    source: &str,
+    lines: impl Iterator<Item = io::Result<String>>,
+    mut ctx: Context,
+    json: bool,
+) -> ExitCode {
+    let mut failed = false;
+    // The rates note goes to stderr once, and again only if `rates` loads
+    // a table from another time
+    let mut noted = None;
+
+    for (number, line) in lines.enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("{}:{}: cannot read line: {}", source, number + 1, e);
+                return ExitCode::from(2);
+            }
+        };
+        let input = line.trim();
+        if input.is_empty() || input.starts_with('#') {
+            continue;
+        }
+        // Columns are 1-based for humans, and counted in the untrimmed line
+        let indent = line.chars().take_while(|c| c.is_whitespace()).count();
+        match commands::run(&mut ctx, input, false) {
+            Some(Ok(text)) => {
+                if json {
+                    println!("{}", report::command(input, &text));
+                } else if !text.is_empty() {
+                    println!("{}", text);
+                }
+                continue;
+            }
+            Some(Err(message)) => {
+                failed = true;
+                if json {
+                    println!("{}", report::command_error(input, &message, number + 1));
+                } else {
+                    eprintln!("{}:{}:{}: {}", source, number + 1, indent + 1, message);
+                }
+                continue;
+            }
+            None => {}
+        }
+
+        let outcome = if is_rpn(&ctx, input) {
+            // Each line that computes something prints the new top of the stack
+            rpn::run(&mut ctx, input).map(|produced| match ctx.stack.last() {
+                Some(top) if produced => Some(Outcome::Value(top.clone())),
+                _ => None,
+            })
+        } else {
+            ctx.run(input).map(Some)
+        };
+        if json {
+            match &outcome {
+                Ok(Some(outcome)) => {
+                    println!("{}", report::outcome(input, Some(outcome), &ctx.rates))
+                }
+                Ok(None) => {}
+                Err(error) => println!("{}", report::error(input, error, Some(number + 1))),
+            }
+        }
+        match outcome {
+            Ok(None) => {}
+            Ok(Some(Outcome::Value(value))) => {
+                if !json {
+                    println!("{}", ctx.settings.format(&value));
+                    let note = rates_note(&ctx, &value).filter(|note| noted.as_ref() != Some(note));
+                    if let Some(note) = note {
+                        eprintln!("{}:{}: {}", source, number + 1, note);
+                        noted = Some(note);
+                    }
+                }
+                ctx.record(input, value);
+            }
+            // Assignments and definitions are silent, like in `bc`
+            Ok(Some(Outcome::Assigned(..) | Outcome::Defined(_))) => {}
+            Ok(Some(Outcome::Solved(solution))) => {
+                if !json {
+                    println!("{}", solution_text(&ctx, &solution));
+                }
+                ctx.record(input, eval::solution_vector(&solution));
+            }
+            Err(_) if json => failed = true,
+            Err(error) => {
+                failed = true;
+                let column = indent + error.span().start + 1;
+                eprintln!("{}:{}:{}: {}", source, number + 1, column, error);
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+// The context lives outside the loop so results survive between lines
+fn repl(mut ctx: Context) {
+    ctx.settings.output.group_long = true;
+    println!("=== CLI CALCULATOR ===");
+    println!("Type an expression like (3 + 4) * 2 ^ 3 / -1.5");
+    println!("Built-ins: sqrt, sin, ln, log(base, x), max, ... and the constants pi, e");
+    println!("Assign with: rate = 0.075    Define with: f(x, y) = x^2 + y");
+    println!("Commands: history  |  clear  |  vars  |  funcs  |  unset <name>  |  quit");
+    println!("Angles: mode deg | rad | grad, or a suffix like sin(30deg)");
+    println!("Exact decimals: mode decimal | float, precision <digits>, rounding <mode>");
+    println!("Fractions: 1/3 + 1/6 is 1/2; to_decimal(x, digits), to_fraction(x), fractions mixed");
+    println!("Whole numbers never overflow: 30!, 2^200, 7 // 2, mod(-7, 3), pow(3, 40)");
+    println!("Complex: sqrt(-4) is 2i; re, im, arg, conj, abs; mode rect | polar");
+    println!("Units: 5 km + 300 m in mi, 60 mph * 2.5 h, 25 degC to degF, x km, (1/3) m");
+    println!("       after a value, a name is always a unit: with m = 5, 3 m is 3 metres");
+    println!("Currencies: 120 EUR in USD, with rates from 'rates <file>' or --rates");
+    println!(
+        "Matrices: [[1,2],[3,4]] * [5,6], det, inverse, transpose, identity(3), dot, cross, .*"
+    );
+    println!("Equations: solve(2x + y = 3, x + 3y = 5), or solve([[2,1],[1,3]], [3,5])");
+    println!(
+        "Programmer: 0xff & 0b1010, 1 << 4, ~x, 5 xor 3; mode u8 ... i128, base 16, overflow wrap"
+    );
+    println!("Output: format fix 2 | sig 6 | sci 6 | eng 3 | auto, format group on, format mark ,");
+    println!("RPN: mode rpn, then 3 4 + 2 * with dup, swap, drop, roll and clear");
+    println!("     units go on the value on top: 5 km 3 m +, and 5 km mi in converts");
+    println!("Sessions: save <name>, load <name>, sessions; saved as 'last' on exit (--restore)");
+    if !ctx.rates.is_empty() {
+        println!("Exchange rates: {}", ctx.rates.describe());
+    }
+    if !ctx.vars.is_empty() || !ctx.funcs.is_empty() || !ctx.history.is_empty() {
+        println!("Restored: {}", commands::describe_session(&ctx));
+    }
+    println!("Use 'ans' for the last result and $1, $2, ... for earlier ones");
+    println!("Keys: Up/Down recall earlier lines, Ctrl-R searches them, Tab completes names\n");
+
+    let history = data::data_dir().ok().map(|dir| dir.join("history"));
+    let mut editor = Editor::new(history);
+
+    loop {
+        // The prompt shows the modes, e.g. "deg> ", "rad dec> " or "deg u8 rpn> "
+        let rpn = if ctx.settings.rpn { " rpn" } else { "" };
+        let prompt = match ctx.settings.numbers {
+            NumberMode::Float => format!("{}{}> ", ctx.settings.angle, rpn),
+            NumberMode::Decimal => format!("{} dec{}> ", ctx.settings.angle, rpn),
+            NumberMode::Integer(int) => format!("{} {}{}> ", ctx.settings.angle, int, rpn),
+        };
+
+        let input_buffer = match editor.read_line(&prompt, &ctx) {
+            Ok(Some(line)) => line,
+            // stdin was closed (Ctrl-D or end of a piped file)
+            Ok(None) => {
+                println!();
+                break;
+            }
+            // e.g. bytes that aren't valid UTF-8: report it and ask again
+            Err(e) => {
+                println!("Error: could not read input ({})", e);
+                continue;
+            }
+        };
+
+        let input = input_buffer.trim();
+        // Blank lines and comments do nothing, as in batch mode
+        if input.is_empty() || input.starts_with('#') {
+            continue;
+        }
+        if let Some(result) = commands::run(&mut ctx, input, true) {
+            match result {
+                Ok(text) if text.is_empty() => {}
+                Ok(text) | Err(text) => println!("{}", text),
+            }
+            continue;
+        }
+        match input {
+            "quit" | "exit" => {
+                println!("Bye!");
+                break;
+            }
+            _ if is_rpn(&ctx, input) => match rpn::run(&mut ctx, input) {
+                Ok(produced) => {
+                    if let Some(top) = ctx.stack.last().filter(|_| produced) {
+                        ctx.record(input, top.clone());
+                    }
+                    print_stack(&ctx);
+                }
+                Err(error) => println!("{}", error.render(input)),
+            },
+            _ => match ctx.run(input) {
+                Ok(Outcome::Value(result)) => {
+                    let index = ctx.record(input, result.clone());
+                    let text = ctx.settings.format(&result);
+                    println!("{}", commands::labelled(&format!("${} = ", index), &text));
+                    print_rates_note(&ctx, &result);
+                }
+                Ok(Outcome::Assigned(name, value)) => {
+                    let text = ctx.settings.format(&value);
+                    println!("{}", commands::labelled(&format!("{} = ", name), &text));
+                    print_rates_note(&ctx, &value);
+                }
+                Ok(Outcome::Defined(function)) => println!("Defined {}", function),
+                // Recorded as the vector of values, so `ans` and $n work
+                Ok(Outcome::Solved(solution)) => {
+                    ctx.record(input, eval::solution_vector(&solution));
+                    println!("{}", solution_text(&ctx, &solution));
+                }
+                Err(error) => println!("{}", error.render(input)),
+            },
+        }
+    }
+
+    // Kept for --restore; a failure here shouldn't hide the session's output
+    let saved = data::session_path(data::LAST_SESSION).and_then(|path| session::save(&ctx, &path));
+    if let Err(message) = saved {
+        eprintln!("calculator: could not save the session: {}", message);
+    }
+}
+
+// "x = 4/5" and "y = 7/5", one unknown per line
+fn solution_text(ctx: &Context, solution: &[(String, Value)]) -> String {
+    solution
+        .iter()
+        .map(|(name, value)| {
+            commands::labelled(&format!("{} = ", name), &ctx.settings.format(value))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Assignments and definitions stay infix in RPN mode, so `rate = 0.075`
+// and `f(x) = x^2` work as usual and can then be used from the stack
+fn is_rpn(ctx: &Context, input: &str) -> bool {
+    ctx.settings.rpn && !input.contains('=')
+}
+
+// The stack after each RPN line, deepest value first so that level 1,
+// the top, sits just above the prompt:
+//
+//   2: 3
+//   1: 7
+fn print_stack(ctx: &Context) {
+    if ctx.stack.is_empty() {
+        println!("  (stack is empty)");
+    }
+    let depth = ctx.stack.len();
+    let width = depth.to_string().len();
+    for (i, value) in ctx.stack.iter().enumerate() {
+        let level = depth - i;
+        let label = format!("  {:>width$}: ", level);
+        println!(
+            "{}",
+            commands::labelled(&label, &ctx.settings.format(value))
+        );
+    }
+}
+
+// Money depends on when the rates were taken, so amounts come with a date
+fn rates_note(ctx: &Context, value: &Value) -> Option<String> {
+    value
+        .is_money()
+        .then(|| format!("exchange rates {}", ctx.rates.as_of()))
+}
+
+fn print_rates_note(ctx: &Context, value: &Value) {
+    if let Some(note) = rates_note(ctx, value) {
+        println!("  ({})", note);
+    }
+}
@@ -0,0 +1,258 @@
+// --json output: one JSON object per expression, each on its own line, so
+// scripts can read results without parsing the text meant for people.
+//
+//   {"input": "2+2", "value": 4, "type": "integer", "unit": null, "error": null}
+//   {"input": "1/0", "value": null, "type": null, "unit": null, "error":
+//    {"kind": "division_by_zero", "message": "Cannot divide by zero", "start": 2, "end": 3}}
+//
+// Values are exact and ignore the display settings: whole numbers are JSON
+// numbers with every digit, fractions are strings like "1/3" and complex
+// numbers are {"re": ..., "im": ...}. Vectors are arrays of numbers and
+// matrices arrays of rows, with type "vector" or "matrix". Solving
+// equations gives an object of the unknowns, with type "solution". Command
+// lines in batch mode give type "command" with the text they showed, and
+// failed ones an error of kind "command". `start` and `end` are 0-based
+// character offsets into `input`; in batch mode `line` is the 1-based line.
+// Amounts of money also get a "rates" field with the timestamp of the
+// exchange rates they were converted with, null if the table has none.
+
+use std::ops::Range;
+
+use cli_calculator::json::Json;
+use cli_calculator::{CalcError, Outcome, Rates, Value};
+
+// What a line produced; None when it left nothing to show, like an RPN
+// line that only rearranged the stack
+pub fn outcome(input: &str, outcome: Option<&Outcome>, rates: &Rates) -> Json {
+    let (value, kind, unit) = match outcome {
+        Some(Outcome::Value(value) | Outcome::Assigned(_, value)) => {
+            let (value, kind, unit) = describe(value);
+            (value, Json::String(kind.to_string()), unit)
+        }
+        // {"x": 0.8, "y": 1.4}
+        Some(Outcome::Solved(solution)) => {
+            let values = solution
+                .iter()
+                .map(|(name, value)| (name.clone(), describe(value).0))
+                .collect();
+            (
+                Json::Object(values),
+                Json::String("solution".to_string()),
+                Json::Null,
+            )
+        }
+        Some(Outcome::Defined(function)) => (
+            Json::String(function.to_string()),
+            Json::String("function".to_string()),
+            Json::Null,
+        ),
+        None => (Json::Null, Json::Null, Json::Null),
+    };
+    let mut json = object(input, value, kind, unit, Json::Null);
+    if let Some(Outcome::Value(value) | Outcome::Assigned(_, value)) = outcome
+        && value.is_money()
+        && let Json::Object(fields) = &mut json
+    {
+        let timestamp = rates.timestamp.clone().map_or(Json::Null, Json::String);
+        fields.insert(4, ("rates".to_string(), timestamp));
+    }
+    json
+}
+
+pub fn error(input: &str, error: &CalcError, line: Option<usize>) -> Json {
+    let span = error.span();
+    failure(
+        input,
+        error.kind(),
+        error.to_string(),
+        span.start..span.end,
+        line,
+    )
+}
+
+// A command line like `vars` or `mode deg`: what it showed, or null when
+// it changed a setting quietly
+pub fn command(input: &str, text: &str) -> Json {
+    let value = match text {
+        "" => Json::Null,
+        text => Json::String(text.to_string()),
+    };
+    object(
+        input,
+        value,
+        Json::String("command".to_string()),
+        Json::Null,
+        Json::Null,
+    )
+}
+
+// A command that failed, like `mode bogus`; the error covers the whole line
+pub fn command_error(input: &str, message: &str, line: usize) -> Json {
+    let end = input.chars().count();
+    failure(input, "command", message.to_string(), 0..end, Some(line))
+}
+
+fn failure(
+    input: &str,
+    kind: &str,
+    message: String,
+    span: Range<usize>,
+    line: Option<usize>,
+) -> Json {
+    let mut details = vec![
+        ("kind".to_string(), Json::String(kind.to_string())),
+        ("message".to_string(), Json::String(message)),
+        ("start".to_string(), Json::Number(span.start.to_string())),
+        ("end".to_string(), Json::Number(span.end.to_string())),
+    ];
+    if let Some(line) = line {
+        details.push(("line".to_string(), Json::Number(line.to_string())));
+    }
+    object(
+        input,
+        Json::Null,
+        Json::Null,
+        Json::Null,
+        Json::Object(details),
+    )
+}
+
+fn object(input: &str, value: Json, kind: Json, unit: Json, error: Json) -> Json {
+    Json::Object(vec![
+        ("input".to_string(), Json::String(input.to_string())),
+        ("value".to_string(), value),
+        ("type".to_string(), kind),
+        ("unit".to_string(), unit),
+        ("error".to_string(), error),
+    ])
+}
+
+// The value, its type name and its unit (null for plain numbers)
+fn describe(value: &Value) -> (Json, &'static str, Json) {
+    match value {
+        Value::Real(x) => (number(*x), "float", Json::Null),
+        Value::Decimal(d) => (Json::Number(d.to_string()), "decimal", Json::Null),
+        Value::Rational(r) if r.is_integer() => {
+            (Json::Number(r.to_string()), "integer", Json::Null)
+        }
+        Value::Rational(r) => (Json::String(r.to_string()), "fraction", Json::Null),
+        Value::Complex(z) => {
+            let parts = vec![
+                ("re".to_string(), number(z.re)),
+                ("im".to_string(), number(z.im)),
+            ];
+            (Json::Object(parts), "complex", Json::Null)
+        }
+        Value::Quantity(q) => {
+            // 5.3 km rather than "53/10" km, as in the text output
+            let magnitude = match &q.value {
+                Value::Rational(r) if !r.is_integer() => Value::Real(r.to_f64()),
+                other => other.clone(),
+            };
+            let (value, kind, _) = describe(&magnitude);
+            (value, kind, Json::String(q.unit.to_string()))
+        }
+        Value::Matrix(m) => {
+            let elements =
+                |elems: &[Value]| Json::Array(elems.iter().map(|x| describe(x).0).collect());
+            if m.is_vector() {
+                (elements(m.elems()), "vector", Json::Null)
+            } else {
+                let rows = (0..m.rows()).map(|row| elements(m.row(row))).collect();
+                (Json::Array(rows), "matrix", Json::Null)
+            }
+        }
+    }
+}
+
+// JSON has no infinity or NaN, so those become null
+fn number(x: f64) -> Json {
+    if x.is_finite() {
+        Json::Number(x.to_string())
+    } else {
+        Json::Null
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cli_calculator::Context;
+
+    fn json(input: &str) -> String {
+        let mut ctx = Context::new();
+        match ctx.run(input) {
+            Ok(result) => outcome(input, Some(&result), &ctx.rates).to_string(),
+            Err(err) => error(input, &err, None).to_string(),
+        }
+    }
+
+    fn value(input: &str) -> String {
+        let text = json(input);
+        let start = text.find("\"value\": ").unwrap() + 9;
+        let end = text.find(", \"error\"").unwrap();
+        text[start..end].to_string()
+    }
+
+    #[test]
+    fn whole_objects() {
+        assert_eq!(
+            json("2+2"),
+            r#"{"input": "2+2", "value": 4, "type": "integer", "unit": null, "error": null}"#
+        );
+        assert_eq!(
+            json("1/0"),
+            r#"{"input": "1/0", "value": null, "type": null, "unit": null, "error": {"kind": "division_by_zero", "message": "Cannot divide by zero", "start": 2, "end": 3}}"#
+        );
+        assert_eq!(
+            error(
+                "x",
+                &CalcError::Overflow {
+                    span: Default::default()
+                },
+                Some(7)
+            )
+            .to_string(),
+            r#"{"input": "x", "value": null, "type": null, "unit": null, "error": {"kind": "overflow", "message": "Result is too large (overflow)", "start": 0, "end": 0, "line": 7}}"#
+        );
+    }
+
+    #[test]
+    fn typed_values() {
+        assert_eq!(
+            value("30!"),
+            r#"265252859812191058636308480000000, "type": "integer", "unit": null"#
+        );
+        assert_eq!(value("1/3"), r#""1/3", "type": "fraction", "unit": null"#);
+        assert_eq!(
+            value("0.5 + sin(0)"),
+            r#"0.5, "type": "float", "unit": null"#
+        );
+        assert_eq!(
+            value("2 + 3i"),
+            r#"{"re": 2, "im": 3}, "type": "complex", "unit": null"#
+        );
+        assert_eq!(value("5.3 km"), r#"5.3, "type": "float", "unit": "km""#);
+        assert_eq!(
+            value("f(x) = x + 1"),
+            r#""f(x) = x + 1", "type": "function", "unit": null"#
+        );
+    }
+
+    #[test]
+    fn money_carries_the_rates_timestamp() {
+        let mut ctx = Context::new();
+        ctx.rates = Rates::parse_csv("base,EUR\ntimestamp,2026-10-14\nUSD,1.25").unwrap();
+        let run = |ctx: &mut Context, input: &str| {
+            let result = ctx.run(input).unwrap();
+            outcome(input, Some(&result), &ctx.rates).to_string()
+        };
+        assert_eq!(
+            run(&mut ctx, "10 EUR in USD"),
+            r#"{"input": "10 EUR in USD", "value": 12.5, "type": "float", "unit": "USD", "rates": "2026-10-14", "error": null}"#
+        );
+        assert!(!run(&mut ctx, "5 km").contains("rates"));
+        ctx.rates.timestamp = None;
+        assert!(run(&mut ctx, "price = 3 EUR").contains(r#""rates": null"#));
+    }
+}
@@ -0,0 +1,99 @@
+// Switching the terminal into raw mode, where every key press reaches the
+// line editor at once instead of after Enter, and isn't echoed.
+// Only Unix terminals are supported; elsewhere the REPL reads plain lines.
+
+#[cfg(unix)]
+mod imp {
+    use std::mem::MaybeUninit;
+    use std::time::Duration;
+
+    // Puts the original settings back when dropped, so a panic or an early
+    // return can't leave the terminal without echo
+    pub struct RawMode {
+        original: libc::termios,
+    }
+
+    impl RawMode {
+        pub fn enable() -> Option<RawMode> {
+            let mut termios = MaybeUninit::<libc::termios>::uninit();
+            // SAFETY: tcgetattr fills in the struct when it returns 0
+            let original = unsafe {
+                if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+                    return None;
+                }
+                termios.assume_init()
+            };
+            let mut raw = original;
+            // No line buffering, echo, signals from Ctrl-C or flow control
+            // from Ctrl-S; Enter arrives as '\r'. Output processing stays on,
+            // so '\n' still starts a new line.
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            raw.c_iflag &= !(libc::IXON | libc::ICRNL | libc::INPCK | libc::ISTRIP);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            // SAFETY: `raw` is a valid termios copied from the terminal's own
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) } != 0 {
+                return None;
+            }
+            Some(RawMode { original })
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: restores the settings read in `enable`
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original);
+            }
+        }
+    }
+
+    // Waits up to `timeout` for input on stdin, saying whether any came
+    pub fn key_waiting(timeout: Duration) -> bool {
+        let mut fd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+        // SAFETY: `fd` is one valid pollfd, and the count says so
+        unsafe { libc::poll(&mut fd, 1, millis) > 0 }
+    }
+
+    // The terminal's width in columns, if it will say
+    pub fn width() -> Option<usize> {
+        let mut size = MaybeUninit::<libc::winsize>::zeroed();
+        // SAFETY: TIOCGWINSZ fills in a winsize when it returns 0
+        let size = unsafe {
+            if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) != 0 {
+                return None;
+            }
+            size.assume_init()
+        };
+        (size.ws_col > 0).then_some(size.ws_col as usize)
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::time::Duration;
+
+    pub struct RawMode;
+
+    impl RawMode {
+        pub fn enable() -> Option<RawMode> {
+            None
+        }
+    }
+
+    pub fn key_waiting(timeout: Duration) -> bool {
+        std::thread::sleep(timeout);
+        false
+    }
+
+    pub fn width() -> Option<usize> {
+        None
+    }
+}
+
+pub use imp::{RawMode, key_waiting, width};
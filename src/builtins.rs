@@ -0,0 +1,1010 @@
+// The built-in function table: `sqrt`, `sin`, `log`, `max` and friends.
+// Each entry is a plain `fn` pointer, so the table is just static data,
+// much like an object literal of functions in JS.
+//
+// Every function checks its domain and returns an error instead of
+// letting NaN or infinity leak out as a "result".
+
+use std::f64::consts;
+
+use crate::angle::AngleMode;
+use crate::bigint::BigInt;
+use crate::complex::Complex;
+use crate::decimal::{self, Decimal, DecimalContext, GUARD_DIGITS, Round};
+use crate::error::{CalcError, Span};
+use crate::eval::{self, Context, Settings};
+use crate::matrix::{self, Matrix};
+use crate::rational::Rational;
+use crate::value::{NumberMode, Value};
+
+// A named constant, with a way to compute it to any decimal precision
+pub struct Constant {
+    pub name: &'static str,
+    pub float: f64,
+    pub decimal: fn(DecimalContext) -> Decimal,
+}
+
+pub const CONSTANTS: &[Constant] = &[
+    Constant {
+        name: "pi",
+        float: consts::PI,
+        decimal: decimal::pi,
+    },
+    Constant {
+        name: "e",
+        float: consts::E,
+        decimal: |ctx| decimal::exp(&Decimal::one(), ctx),
+    },
+];
+
+impl Constant {
+    pub fn value(&self, settings: &Settings) -> Value {
+        match settings.numbers {
+            NumberMode::Float | NumberMode::Integer(_) => Value::Real(self.float),
+            NumberMode::Decimal => Value::Decimal((self.decimal)(settings.decimal)),
+        }
+    }
+}
+
+type FloatFn = fn(f64) -> Result<f64, String>;
+type DecimalFn = fn(&Decimal, DecimalContext) -> Result<Decimal, String>;
+type FloatNaryFn = fn(&[f64]) -> Result<f64, String>;
+type DecimalNaryFn = fn(&[Decimal], DecimalContext) -> Result<Decimal, String>;
+// None means "no exact answer", e.g. sqrt(2), and the f64 version runs instead
+type ExactFn = fn(&[Rational]) -> Option<Rational>;
+type ValueFn = fn(&[Value], &Settings) -> Result<Value, String>;
+type ComplexFn = fn(&[Complex]) -> Result<Complex, String>;
+// Errors like a shape mismatch come back with an empty span
+type MatrixFn = fn(&Context, &[Value]) -> Result<Value, CalcError>;
+
+// The shapes a built-in can have. Most come as a pair: the f64 version
+// and the decimal version used in `mode decimal`.
+#[derive(Clone, Copy)]
+pub enum Func {
+    // Exactly one argument, e.g. `sqrt(x)`
+    Unary(FloatFn, DecimalFn),
+    // Takes an angle in the session's angle mode, e.g. `sin(x)`
+    AngleIn(FloatFn, DecimalFn),
+    // Returns an angle in the session's angle mode, e.g. `asin(x)`
+    AngleOut(FloatFn, DecimalFn),
+    // A fixed or minimum number of arguments, e.g. `log(base, x)` or `max(a, b, c)`
+    Nary {
+        min: usize,
+        max: Option<usize>,
+        call: FloatNaryFn,
+        decimal: DecimalNaryFn,
+    },
+    // Works on values of any kind and decides the result's kind itself,
+    // e.g. `to_fraction(x)`
+    Convert {
+        arity: usize,
+        call: ValueFn,
+    },
+    // Takes vectors or matrices, which every other function turns away,
+    // e.g. `det(A)`
+    Matrix {
+        arity: usize,
+        call: MatrixFn,
+    },
+}
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: Func,
+    // Used instead of `func` in float mode when every argument is exact
+    pub exact: Option<ExactFn>,
+    // Used when an argument is complex; angles are always in radians here
+    pub complex: Option<ComplexFn>,
+    // Whether `complex` also takes over when the real version rejects a
+    // real argument, so sqrt(-4) is 2i rather than an error
+    pub widens: bool,
+    // Whether the result is never 0 unless an argument is, so that a 0
+    // from exp(-1000) is reported as an underflow like 1e-400 is
+    pub nonzero: bool,
+}
+
+impl Builtin {
+    const fn exact(self, exact: ExactFn) -> Builtin {
+        Builtin {
+            exact: Some(exact),
+            ..self
+        }
+    }
+
+    const fn complex(self, complex: ComplexFn) -> Builtin {
+        Builtin {
+            complex: Some(complex),
+            ..self
+        }
+    }
+
+    const fn widens_to(self, complex: ComplexFn) -> Builtin {
+        Builtin {
+            complex: Some(complex),
+            widens: true,
+            ..self
+        }
+    }
+
+    const fn nonzero(self) -> Builtin {
+        Builtin {
+            nonzero: true,
+            ..self
+        }
+    }
+}
+
+const fn unary(name: &'static str, call: FloatFn, decimal: DecimalFn) -> Builtin {
+    Builtin {
+        name,
+        func: Func::Unary(call, decimal),
+        exact: None,
+        complex: None,
+        widens: false,
+        nonzero: false,
+    }
+}
+
+const fn angle_in(name: &'static str, call: FloatFn, decimal: DecimalFn) -> Builtin {
+    Builtin {
+        name,
+        func: Func::AngleIn(call, decimal),
+        exact: None,
+        complex: None,
+        widens: false,
+        nonzero: false,
+    }
+}
+
+const fn angle_out(name: &'static str, call: FloatFn, decimal: DecimalFn) -> Builtin {
+    Builtin {
+        name,
+        func: Func::AngleOut(call, decimal),
+        exact: None,
+        complex: None,
+        widens: false,
+        nonzero: false,
+    }
+}
+
+const fn nary(
+    name: &'static str,
+    min: usize,
+    max: Option<usize>,
+    call: FloatNaryFn,
+    decimal: DecimalNaryFn,
+) -> Builtin {
+    Builtin {
+        name,
+        func: Func::Nary {
+            min,
+            max,
+            call,
+            decimal,
+        },
+        exact: None,
+        complex: None,
+        widens: false,
+        nonzero: false,
+    }
+}
+
+const fn convert(name: &'static str, arity: usize, call: ValueFn) -> Builtin {
+    Builtin {
+        name,
+        func: Func::Convert { arity, call },
+        exact: None,
+        complex: None,
+        widens: false,
+        nonzero: false,
+    }
+}
+
+const fn matrix(name: &'static str, arity: usize, call: MatrixFn) -> Builtin {
+    Builtin {
+        name,
+        func: Func::Matrix { arity, call },
+        exact: None,
+        complex: None,
+        widens: false,
+        nonzero: false,
+    }
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    unary("sqrt", sqrt, sqrt_decimal)
+        .exact(|args| args[0].sqrt())
+        .widens_to(|args| Ok(args[0].sqrt())),
+    // The real cube root of a negative number is kept: cbrt(-8) is -2
+    unary("cbrt", |x| Ok(x.cbrt()), cbrt_decimal).complex(|args| {
+        let third = Complex::from(1.0 / 3.0);
+        Ok(args[0].pow(third).unwrap_or_default())
+    }),
+    unary("abs", |x| Ok(x.abs()), |x, _| Ok(x.abs()))
+        .exact(|args| Some(args[0].abs()))
+        .complex(|args| Ok(Complex::from(args[0].abs()))),
+    angle_in(
+        "sin",
+        |x| Ok(x.sin()),
+        |x, ctx| {
+            check_trig_size("sin", x)?;
+            Ok(decimal::sin(x, ctx))
+        },
+    )
+    .complex(|args| Ok(args[0].sin())),
+    angle_in(
+        "cos",
+        |x| Ok(x.cos()),
+        |x, ctx| {
+            check_trig_size("cos", x)?;
+            Ok(decimal::cos(x, ctx))
+        },
+    )
+    .complex(|args| Ok(args[0].cos())),
+    angle_in("tan", tan, tan_decimal).complex(|args| {
+        args[0]
+            .tan()
+            .ok_or_else(|| format!("tan() is undefined at {}", args[0]))
+    }),
+    angle_out("asin", asin, asin_decimal).widens_to(|args| Ok(args[0].asin())),
+    angle_out("acos", acos, acos_decimal).widens_to(|args| Ok(args[0].acos())),
+    angle_out("atan", |x| Ok(x.atan()), |x, ctx| Ok(decimal::atan(x, ctx))).complex(|args| {
+        args[0]
+            .atan()
+            .ok_or_else(|| format!("atan() is undefined at {}", args[0]))
+    }),
+    unary("ln", ln, ln_decimal).widens_to(|args| ln_complex(args[0])),
+    unary("log10", log10, log10_decimal)
+        .widens_to(|args| log_complex(Complex::from(10.0), args[0])),
+    nary("log", 2, Some(2), log, log_decimal).widens_to(|args| log_complex(args[0], args[1])),
+    // pow(x, y) is x ^ y and mod(x, y) is x % y, with the sign of y
+    nary("pow", 2, Some(2), pow, pow_decimal)
+        .nonzero()
+        .exact(pow_exact)
+        .widens_to(|args| {
+            args[0]
+                .pow(args[1])
+                .ok_or_else(|| format!("pow(0, {}) divides by zero", Value::complex(args[1])))
+        }),
+    nary("mod", 2, Some(2), modulo, modulo_decimal).exact(|args| args[0].modulo(&args[1])),
+    unary("exp", |x| Ok(x.exp()), |x, ctx| Ok(decimal::exp(x, ctx)))
+        .nonzero()
+        .complex(|args| Ok(args[0].exp())),
+    unary(
+        "floor",
+        |x| Ok(x.floor()),
+        |x, _| Ok(x.round_integer(Round::Floor)),
+    )
+    .exact(|args| Some(args[0].floor())),
+    unary(
+        "ceil",
+        |x| Ok(x.ceil()),
+        |x, _| Ok(x.round_integer(Round::Ceiling)),
+    )
+    .exact(|args| Some(args[0].ceil())),
+    // In decimal mode round() follows the session's rounding mode
+    unary(
+        "round",
+        |x| Ok(x.round()),
+        |x, ctx| Ok(x.round_integer(ctx.rounding.into())),
+    )
+    .exact(|args| Some(args[0].round())),
+    nary(
+        "min",
+        1,
+        None,
+        |args| Ok(args.iter().copied().fold(f64::INFINITY, f64::min)),
+        |args, _| Ok(args.iter().min().cloned().unwrap_or_default()),
+    )
+    .exact(|args| args.iter().min().cloned()),
+    nary(
+        "max",
+        1,
+        None,
+        |args| Ok(args.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+        |args, _| Ok(args.iter().max().cloned().unwrap_or_default()),
+    )
+    .exact(|args| args.iter().max().cloned()),
+    nary(
+        "hypot",
+        2,
+        Some(2),
+        |args| Ok(args[0].hypot(args[1])),
+        |args, ctx| {
+            let work = ctx.extended(GUARD_DIGITS);
+            let sum = args[0]
+                .mul(&args[0], work)
+                .add(&args[1].mul(&args[1], work), work);
+            Ok(decimal::sqrt(&sum, ctx))
+        },
+    ),
+    convert("to_decimal", 2, to_decimal),
+    convert("to_fraction", 1, to_fraction),
+    convert("re", 1, re),
+    convert("im", 1, im),
+    convert("arg", 1, arg),
+    convert("conj", 1, conj),
+    matrix("transpose", 1, |_, args| {
+        Ok(matrix_arg("transpose", &args[0])?.transpose().into())
+    }),
+    matrix("det", 1, |ctx, args| {
+        matrix::determinant(ctx, matrix_arg("det", &args[0])?)
+    }),
+    matrix("inverse", 1, |ctx, args| {
+        matrix::inverse(ctx, matrix_arg("inverse", &args[0])?).map(Value::from)
+    }),
+    matrix("identity", 1, |ctx, args| {
+        matrix::identity(ctx, &args[0]).map(Value::from)
+    }),
+    matrix("dot", 2, |ctx, args| {
+        let (a, b) = (vector_arg("dot", &args[0])?, vector_arg("dot", &args[1])?);
+        matrix::dot(ctx, a, b)
+    }),
+    matrix("cross", 2, |ctx, args| {
+        let (a, b) = (
+            vector_arg("cross", &args[0])?,
+            vector_arg("cross", &args[1])?,
+        );
+        matrix::cross(ctx, a, b).map(Value::from)
+    }),
+    // The equation form, solve(2x + y = 3, x + 3y = 5), is handled by Context::solve
+    matrix("solve", 2, |ctx, args| {
+        let (a, b) = (
+            matrix_arg("solve", &args[0])?,
+            matrix_arg("solve", &args[1])?,
+        );
+        matrix::solve(ctx, a, b).map(Value::from)
+    }),
+];
+
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+pub fn constant(name: &str) -> Option<&'static Constant> {
+    CONSTANTS.iter().find(|constant| constant.name == name)
+}
+
+impl Builtin {
+    // The fewest and most arguments it takes; None means no upper limit
+    pub fn arity(&self) -> (usize, Option<usize>) {
+        match self.func {
+            Func::Unary(..) | Func::AngleIn(..) | Func::AngleOut(..) => (1, Some(1)),
+            Func::Nary { min, max, .. } => (min, max),
+            Func::Convert { arity, .. } | Func::Matrix { arity, .. } => (arity, Some(arity)),
+        }
+    }
+
+    // `span` covers the whole call, so errors underline e.g. `sqrt(-1)`
+    pub fn call(&self, args: &[Value], ctx: &Context, span: Span) -> Result<Value, CalcError> {
+        let settings = &ctx.settings;
+        let (min, max) = self.arity();
+        if args.len() < min || max.is_some_and(|max| args.len() > max) {
+            let expected = match max {
+                Some(max) if max == min => format!("{}", min),
+                Some(max) => format!("{} to {}", min, max),
+                None => format!("at least {}", min),
+            };
+            return Err(CalcError::WrongArgCount {
+                name: self.name.to_string(),
+                expected,
+                found: args.len(),
+                span,
+            });
+        }
+        if let Func::Matrix { call, .. } = self.func {
+            return call(ctx, args).map_err(|error| error.at(span));
+        }
+        if let Some(Value::Matrix(m)) = args.iter().find(|arg| matches!(arg, Value::Matrix(_))) {
+            return Err(CalcError::Domain {
+                message: format!(
+                    "{}() needs plain numbers, not a {} (got {})",
+                    self.name,
+                    m.shape(),
+                    m
+                ),
+                span,
+            });
+        }
+        // sqrt(4 m^2) could be 2 m, but most functions have no sensible unit
+        // for their result, so units have to be divided out: sqrt(area / m^2)
+        if let Some(quantity) = args.iter().find_map(Value::as_quantity) {
+            return Err(CalcError::Domain {
+                message: format!(
+                    "{}() needs plain numbers, not units (got {})",
+                    self.name, quantity
+                ),
+                span,
+            });
+        }
+        if let Func::Convert { call, .. } = self.func {
+            return call(args, settings).map_err(|message| CalcError::Domain { message, span });
+        }
+        if args.iter().any(Value::is_complex) {
+            return self.call_complex(args, settings.angle, span);
+        }
+        let result = match settings.numbers {
+            // In integer mode the evaluator checks afterwards that the result
+            // is a whole number that fits, so abs(-5) works and sqrt(2) doesn't
+            NumberMode::Float | NumberMode::Integer(_) => {
+                let exact_args: Option<Vec<Rational>> =
+                    args.iter().map(|arg| arg.as_rational().cloned()).collect();
+                if let (Some(exact), Some(exact_args)) = (self.exact, exact_args)
+                    && let Some(result) = exact(&exact_args)
+                {
+                    return Ok(Value::Rational(result));
+                }
+                // ln(10^400) can't be worked out once 10^400 is inf
+                let args = args
+                    .iter()
+                    .map(Value::to_float)
+                    .collect::<Result<Vec<f64>, CalcError>>()
+                    .map_err(|error| error.at(span))?;
+                self.call_float(&args, settings.angle, span)
+                    .map(Value::Real)
+            }
+            NumberMode::Decimal => {
+                let args: Vec<Decimal> = args
+                    .iter()
+                    .map(|arg| arg.to_decimal(settings.decimal))
+                    .collect();
+                self.call_decimal(&args, settings.angle, settings.decimal, span)
+                    .map(Value::Decimal)
+            }
+        };
+        match result {
+            Err(CalcError::Domain { .. }) if self.widens => {
+                self.call_complex(args, settings.angle, span)
+            }
+            result => result,
+        }
+    }
+
+    fn call_complex(
+        &self,
+        args: &[Value],
+        angle: AngleMode,
+        span: Span,
+    ) -> Result<Value, CalcError> {
+        let Some(call) = self.complex else {
+            return Err(CalcError::Domain {
+                message: format!("{}() is only defined for real numbers", self.name),
+                span,
+            });
+        };
+        let mut args: Vec<Complex> = args.iter().map(Value::to_complex).collect();
+        let result = match self.func {
+            Func::AngleIn(..) => {
+                args[0] = args[0] * Complex::from(angle.to_radians(1.0));
+                call(&args)
+            }
+            Func::AngleOut(..) => call(&args).map(|z| z * Complex::from(angle.express(1.0))),
+            _ => call(&args),
+        };
+        let result = result
+            .map_err(|message| CalcError::Domain { message, span })?
+            .tidy();
+        if !result.is_finite() {
+            return Err(CalcError::Overflow { span });
+        }
+        if self.nonzero && result == Complex::from(0.0) && !args.contains(&Complex::from(0.0)) {
+            return Err(CalcError::Underflow { span });
+        }
+        Ok(Value::complex(result))
+    }
+
+    fn call_float(&self, args: &[f64], angle: AngleMode, span: Span) -> Result<f64, CalcError> {
+        let result = match self.func {
+            Func::Unary(call, _) => call(args[0]),
+            Func::AngleIn(call, _) => {
+                let radians = angle.to_radians(args[0]);
+                call(radians).map(|result| tidy_trig(result, radians))
+            }
+            // asin(0.5) comes out as 30.000000000000004 degrees for the
+            // same reason sin(30deg) needs tidying
+            Func::AngleOut(call, _) => call(args[0]).map(|radians| match angle {
+                AngleMode::Rad => radians,
+                _ => round_noise(angle.express(radians)),
+            }),
+            Func::Nary { call, .. } => call(args),
+            Func::Convert { .. } | Func::Matrix { .. } => unreachable!("handled in call()"),
+        };
+        let result = result.map_err(|message| CalcError::Domain { message, span })?;
+        if result.is_infinite() && args.iter().all(|x| x.is_finite()) {
+            return Err(CalcError::Overflow { span });
+        }
+        if self.nonzero && result == 0.0 && !args.contains(&0.0) {
+            return Err(CalcError::Underflow { span });
+        }
+        Ok(result)
+    }
+
+    fn call_decimal(
+        &self,
+        args: &[Decimal],
+        angle: AngleMode,
+        ctx: DecimalContext,
+        span: Span,
+    ) -> Result<Decimal, CalcError> {
+        // Angles are converted with guard digits and the result rounded once,
+        // so sin(30deg) comes out as exactly 0.5
+        let work = ctx.extended(GUARD_DIGITS);
+        let result = match self.func {
+            Func::Unary(_, call) => call(&args[0], ctx),
+            Func::AngleIn(_, call) => {
+                let radians = AngleMode::Rad.convert_decimal(&args[0], angle, work);
+                call(&radians, work).map(|result| tidy_trig_decimal(result, &radians, ctx))
+            }
+            Func::AngleOut(_, call) => call(&args[0], work).map(|radians| {
+                angle
+                    .convert_decimal(&radians, AngleMode::Rad, work)
+                    .round(ctx)
+            }),
+            Func::Nary { decimal, .. } => decimal(args, ctx),
+            Func::Convert { .. } | Func::Matrix { .. } => unreachable!("handled in call()"),
+        };
+        let result = result.map_err(|message| CalcError::Domain { message, span })?;
+        if !result.in_range() {
+            return Err(eval::decimal_range_error(&result, span));
+        }
+        if self.nonzero && result.is_zero() && !args.iter().any(Decimal::is_zero) {
+            return Err(CalcError::Underflow { span });
+        }
+        Ok(result)
+    }
+}
+
+// pi can't be stored exactly, so sin(30deg) comes out as 0.49999999999999994
+// and sin(180deg) as 1.2e-16. Rounding to 15 significant digits hides that
+// noise, and results that tiny next to a non-tiny input are really zero.
+fn tidy_trig(result: f64, radians: f64) -> f64 {
+    if result.abs() < 1e-15 && radians.abs() > 1e-6 {
+        return 0.0;
+    }
+    round_noise(result)
+}
+
+// `x` rounded to 15 significant digits
+fn round_noise(x: f64) -> f64 {
+    if x == 0.0 || !x.is_finite() {
+        return x;
+    }
+    let scale = 10f64.powi(14 - x.abs().log10().floor() as i32);
+    (x * scale).round() / scale
+}
+
+// The decimal version of `tidy_trig`. Results are computed with guard digits,
+// so rounding already hides the noise; only the zero check is needed. A result
+// below the input's last digit is noise from a rounded multiple of pi, but only
+// while that digit is well after the point: for sin(1e32) it is in the units,
+// and snapping would turn every result to 0.
+fn tidy_trig_decimal(result: Decimal, radians: &Decimal, ctx: DecimalContext) -> Decimal {
+    let precision = ctx.precision as i64;
+    if radians.adjusted() < precision / 2 && result.adjusted() < radians.adjusted() - precision + 2
+    {
+        return Decimal::zero();
+    }
+    result.round(ctx)
+}
+
+// Reducing a huge angle to one turn needs as many digits as it has
+fn check_trig_size(name: &str, x: &Decimal) -> Result<(), String> {
+    if x.adjusted() > decimal::MAX_PRECISION as i64 {
+        return Err(format!("{}() argument is too large ({})", name, x));
+    }
+    Ok(())
+}
+
+fn sqrt(x: f64) -> Result<f64, String> {
+    if x < 0.0 {
+        return Err(format!("sqrt() is undefined for negative numbers ({})", x));
+    }
+    Ok(x.sqrt())
+}
+
+fn tan(x: f64) -> Result<f64, String> {
+    // tan has poles at odd multiples of pi/2, where cos(x) is (almost) zero
+    if x.cos().abs() < 1e-15 {
+        return Err("tan() is undefined where cos() is zero".to_string());
+    }
+    Ok(x.tan())
+}
+
+fn asin(x: f64) -> Result<f64, String> {
+    if !(-1.0..=1.0).contains(&x) {
+        return Err(format!(
+            "asin() is only defined between -1 and 1 (got {})",
+            x
+        ));
+    }
+    Ok(x.asin())
+}
+
+fn acos(x: f64) -> Result<f64, String> {
+    if !(-1.0..=1.0).contains(&x) {
+        return Err(format!(
+            "acos() is only defined between -1 and 1 (got {})",
+            x
+        ));
+    }
+    Ok(x.acos())
+}
+
+fn ln(x: f64) -> Result<f64, String> {
+    if x <= 0.0 {
+        return Err(format!(
+            "ln() is only defined for positive numbers (got {})",
+            x
+        ));
+    }
+    Ok(x.ln())
+}
+
+fn log10(x: f64) -> Result<f64, String> {
+    if x <= 0.0 {
+        return Err(format!(
+            "log10() is only defined for positive numbers (got {})",
+            x
+        ));
+    }
+    Ok(x.log10())
+}
+
+fn log(args: &[f64]) -> Result<f64, String> {
+    let (base, x) = (args[0], args[1]);
+    if base <= 0.0 || base == 1.0 {
+        return Err(format!(
+            "log() base must be positive and not 1 (got {})",
+            base
+        ));
+    }
+    if x <= 0.0 {
+        return Err(format!(
+            "log() is only defined for positive numbers (got {})",
+            x
+        ));
+    }
+    Ok(x.ln() / base.ln())
+}
+
+fn pow(args: &[f64]) -> Result<f64, String> {
+    let (x, y) = (args[0], args[1]);
+    if x == 0.0 && y < 0.0 {
+        return Err(format!("pow(0, {}) divides by zero", y));
+    }
+    if x < 0.0 && y.fract() != 0.0 {
+        return Err(format!("pow({}, {}) is not a real number", x, y));
+    }
+    Ok(x.powf(y))
+}
+
+// Whole-number powers of fractions stay exact, as with `^`
+fn pow_exact(args: &[Rational]) -> Option<Rational> {
+    let (x, y) = (&args[0], &args[1]);
+    if !y.is_integer() {
+        return None;
+    }
+    x.pow(y.numer().to_i64()?)
+}
+
+fn modulo(args: &[f64]) -> Result<f64, String> {
+    let (x, y) = (args[0], args[1]);
+    if y == 0.0 {
+        return Err(format!("mod({}, 0) divides by zero", x));
+    }
+    Ok(eval::floored_rem(x, y))
+}
+
+fn sqrt_decimal(x: &Decimal, ctx: DecimalContext) -> Result<Decimal, String> {
+    if x.is_negative() {
+        return Err(format!("sqrt() is undefined for negative numbers ({})", x));
+    }
+    Ok(decimal::sqrt(x, ctx))
+}
+
+fn cbrt_decimal(x: &Decimal, ctx: DecimalContext) -> Result<Decimal, String> {
+    if x.is_zero() {
+        return Ok(Decimal::zero());
+    }
+    // cbrt(x) = e^(ln|x| / 3), with the sign put back
+    let work = ctx.extended(GUARD_DIGITS);
+    let third = decimal::ln(&x.abs(), work).div(&Decimal::from(3), work);
+    let root = decimal::exp(&third, work).round(ctx);
+    Ok(if x.is_negative() { -root } else { root })
+}
+
+fn tan_decimal(x: &Decimal, ctx: DecimalContext) -> Result<Decimal, String> {
+    check_trig_size("tan", x)?;
+    // Here `ctx` carries guard digits. A cos() that vanishes at the session's
+    // precision is a pole, even if x itself (like 90deg in radians) was rounded.
+    let cos = decimal::cos(x, ctx);
+    if cos.adjusted() < x.adjusted() - (ctx.precision - GUARD_DIGITS) as i64 + 2 {
+        return Err("tan() is undefined where cos() is zero".to_string());
+    }
+    Ok(decimal::sin(x, ctx).div(&cos, ctx))
+}
+
+fn asin_decimal(x: &Decimal, ctx: DecimalContext) -> Result<Decimal, String> {
+    let one = Decimal::one();
+    if x.abs() > one {
+        return Err(format!(
+            "asin() is only defined between -1 and 1 (got {})",
+            x
+        ));
+    }
+    if x.abs() == one {
+        let half_pi = decimal::pi(ctx).div(&Decimal::from(2), ctx);
+        return Ok(if x.is_negative() { -half_pi } else { half_pi });
+    }
+    // asin(x) = atan(x / sqrt(1 - x^2))
+    let root = decimal::sqrt(&one.sub(&x.mul(x, ctx), ctx), ctx);
+    Ok(decimal::atan(&x.div(&root, ctx), ctx))
+}
+
+fn acos_decimal(x: &Decimal, ctx: DecimalContext) -> Result<Decimal, String> {
+    if x.abs() > Decimal::one() {
+        return Err(format!(
+            "acos() is only defined between -1 and 1 (got {})",
+            x
+        ));
+    }
+    // acos(x) = pi/2 - asin(x)
+    let half_pi = decimal::pi(ctx).div(&Decimal::from(2), ctx);
+    Ok(half_pi.sub(&asin_decimal(x, ctx)?, ctx))
+}
+
+fn ln_decimal(x: &Decimal, ctx: DecimalContext) -> Result<Decimal, String> {
+    if x.is_negative() || x.is_zero() {
+        return Err(format!(
+            "ln() is only defined for positive numbers (got {})",
+            x
+        ));
+    }
+    Ok(decimal::ln(x, ctx))
+}
+
+fn log10_decimal(x: &Decimal, ctx: DecimalContext) -> Result<Decimal, String> {
+    if x.is_negative() || x.is_zero() {
+        return Err(format!(
+            "log10() is only defined for positive numbers (got {})",
+            x
+        ));
+    }
+    let work = ctx.extended(GUARD_DIGITS);
+    let ten = Decimal::from(10);
+    Ok(decimal::ln(x, work)
+        .div(&decimal::ln(&ten, work), work)
+        .round(ctx))
+}
+
+fn log_decimal(args: &[Decimal], ctx: DecimalContext) -> Result<Decimal, String> {
+    let (base, x) = (&args[0], &args[1]);
+    if base.is_negative() || base.is_zero() || *base == Decimal::one() {
+        return Err(format!(
+            "log() base must be positive and not 1 (got {})",
+            base
+        ));
+    }
+    if x.is_negative() || x.is_zero() {
+        return Err(format!(
+            "log() is only defined for positive numbers (got {})",
+            x
+        ));
+    }
+    let work = ctx.extended(GUARD_DIGITS);
+    Ok(decimal::ln(x, work)
+        .div(&decimal::ln(base, work), work)
+        .round(ctx))
+}
+
+fn pow_decimal(args: &[Decimal], ctx: DecimalContext) -> Result<Decimal, String> {
+    let (x, y) = (&args[0], &args[1]);
+    if x.is_zero() && y.is_negative() {
+        return Err(format!("pow(0, {}) divides by zero", y));
+    }
+    if x.is_negative() && !y.is_integer() {
+        return Err(format!("pow({}, {}) is not a real number", x, y));
+    }
+    Ok(decimal::pow(x, y, ctx))
+}
+
+fn modulo_decimal(args: &[Decimal], ctx: DecimalContext) -> Result<Decimal, String> {
+    let (x, y) = (&args[0], &args[1]);
+    if y.is_zero() {
+        return Err(format!("mod({}, 0) divides by zero", x));
+    }
+    x.modulo(y, ctx)
+        .ok_or_else(|| format!("mod({}, {}) needs too many digits", x, y))
+}
+
+// to_decimal(x, digits): x as a decimal with that many significant digits,
+// rounded with the session's rounding mode
+fn to_decimal(args: &[Value], settings: &Settings) -> Result<Value, String> {
+    if let Value::Complex(z) = args[0] {
+        return Err(format!("to_decimal() needs a real number (got {})", z));
+    }
+    let digits = args[1].to_f64();
+    if digits.fract() != 0.0 || !(1.0..=decimal::MAX_PRECISION as f64).contains(&digits) {
+        return Err(format!(
+            "to_decimal() digits must be a whole number from 1 to {} (got {})",
+            decimal::MAX_PRECISION,
+            args[1]
+        ));
+    }
+    let ctx = DecimalContext {
+        precision: digits as usize,
+        rounding: settings.decimal.rounding,
+    };
+    Ok(Value::Decimal(args[0].to_decimal(ctx)))
+}
+
+// to_fraction(x): decimals convert exactly (0.125 is 1/8), and an f64 becomes
+// the simplest fraction that rounds to it (0.1 is 1/10)
+fn to_fraction(args: &[Value], _: &Settings) -> Result<Value, String> {
+    let fraction = match &args[0] {
+        Value::Complex(_) | Value::Quantity(_) | Value::Matrix(_) => {
+            return Err(format!(
+                "to_fraction() needs a real number (got {})",
+                args[0]
+            ));
+        }
+        Value::Rational(r) => r.clone(),
+        Value::Decimal(d) => Rational::from(d),
+        Value::Real(x) => {
+            Rational::approximate(*x).ok_or_else(|| format!("{} has no fraction form", x))?
+        }
+    };
+    Ok(Value::Rational(fraction))
+}
+
+fn ln_complex(z: Complex) -> Result<Complex, String> {
+    z.ln().ok_or_else(|| "ln(0) is undefined".to_string())
+}
+
+fn log_complex(base: Complex, z: Complex) -> Result<Complex, String> {
+    ln_complex(z)?
+        .checked_div(ln_complex(base)?)
+        .ok_or_else(|| "log() base must not be 1".to_string())
+}
+
+// re(3 + 4i) = 3; a real number is its own real part
+fn re(args: &[Value], _: &Settings) -> Result<Value, String> {
+    Ok(match &args[0] {
+        Value::Complex(z) => Value::Real(z.re),
+        real => real.clone(),
+    })
+}
+
+// im(3 + 4i) = 4; real numbers have an imaginary part of exactly 0
+fn im(args: &[Value], _: &Settings) -> Result<Value, String> {
+    Ok(match &args[0] {
+        Value::Complex(z) => Value::Real(z.im),
+        Value::Decimal(_) => Value::Decimal(Decimal::zero()),
+        _ => Value::Rational(Rational::from(BigInt::zero())),
+    })
+}
+
+// The angle of the number from the positive real axis, in the session's
+// angle mode: arg(i) is 90 in `mode deg`, and arg(-1) is pi in radians
+fn arg(args: &[Value], settings: &Settings) -> Result<Value, String> {
+    let radians = args[0].to_complex().arg();
+    Ok(Value::Real(settings.angle.express(radians)))
+}
+
+fn conj(args: &[Value], _: &Settings) -> Result<Value, String> {
+    Ok(match &args[0] {
+        Value::Complex(z) => Value::Complex(z.conj()),
+        real => real.clone(),
+    })
+}
+
+// The matrix or vector a matrix function was given
+fn matrix_arg<'a>(name: &str, value: &'a Value) -> Result<&'a Matrix, CalcError> {
+    match value {
+        Value::Matrix(m) => Ok(m),
+        other => Err(CalcError::Domain {
+            message: format!("{}() needs a matrix (got {})", name, other),
+            span: Span::default(),
+        }),
+    }
+}
+
+fn vector_arg<'a>(name: &str, value: &'a Value) -> Result<&'a Matrix, CalcError> {
+    match value {
+        Value::Matrix(m) if m.is_vector() => Ok(m),
+        other => Err(CalcError::Domain {
+            message: format!(
+                "{}() needs two vectors (got {})",
+                name,
+                matrix::describe(other)
+            ),
+            span: Span::default(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{calc, show};
+
+    #[test]
+    fn domain_errors() {
+        // The real versions refuse; sqrt, ln and asin then widen to complex
+        assert_eq!(
+            sqrt(-1.0),
+            Err("sqrt() is undefined for negative numbers (-1)".to_string())
+        );
+        assert_eq!(
+            ln(0.0),
+            Err("ln() is only defined for positive numbers (got 0)".to_string())
+        );
+        assert_eq!(
+            asin(2.0),
+            Err("asin() is only defined between -1 and 1 (got 2)".to_string())
+        );
+        let ctx = DecimalContext::default();
+        assert!(sqrt_decimal(&Decimal::from(-1), ctx).is_err());
+        assert!(asin_decimal(&Decimal::from(2), ctx).is_err());
+        assert_eq!(calc("sqrt(-4)"), "2i");
+        assert_eq!(calc("ln(0)"), "ln(0) is undefined");
+        assert_eq!(calc("log(1, 5)"), "log() base must not be 1");
+        assert_eq!(
+            calc("tan(pi / 2)"),
+            "tan() is undefined where cos() is zero"
+        );
+        // The arguments as they were written, not as complex numbers
+        assert_eq!(calc("pow(0, -1)"), "pow(0, -1) divides by zero");
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(calc("round(2.5)"), "3");
+        assert_eq!(calc("round(-2.5)"), "-3");
+        assert_eq!(calc("round(-0.4)"), "0");
+        assert_eq!(calc("ceil(-0.4)"), "0");
+        assert_eq!(calc("floor(-2.5)"), "-3");
+        assert_eq!(calc("ceil(2.1)"), "3");
+        // Fractions round exactly
+        assert_eq!(calc("round(7/2)"), "4");
+        assert_eq!(calc("floor(-7/2)"), "-4");
+        // Decimal mode follows the session's rounding mode
+        let mut ctx = Context::new();
+        ctx.settings.numbers = NumberMode::Decimal;
+        assert_eq!(show(&ctx, "round(2.5)"), "2");
+        ctx.settings.decimal.rounding = decimal::RoundingMode::HalfUp;
+        assert_eq!(show(&ctx, "round(2.5)"), "3");
+    }
+
+    #[test]
+    fn min_max_and_hypot() {
+        assert_eq!(calc("min(3, 1, 2)"), "1");
+        assert_eq!(calc("max(2, 1/3, -5)"), "2");
+        assert_eq!(calc("min(1/3, 0.3)"), "0.3");
+        assert_eq!(calc("max(7)"), "7");
+        assert_eq!(
+            calc("max()"),
+            "max() takes at least 1 argument(s) but 0 were given"
+        );
+        assert_eq!(calc("hypot(3, 4)"), "5");
+        assert_eq!(calc("hypot(5, 12)"), "13");
+        let mut ctx = Context::new();
+        ctx.settings.numbers = NumberMode::Decimal;
+        assert_eq!(
+            show(&ctx, "hypot(1, 1)"),
+            "1.414213562373095048801688724209698"
+        );
+        assert_eq!(show(&ctx, "min(0.1, 0.2)"), "0.1");
+    }
+
+    #[test]
+    fn inverse_trig_in_degrees() {
+        let mut ctx = Context::new();
+        ctx.settings.angle = AngleMode::Deg;
+        assert_eq!(show(&ctx, "asin(0.5)"), "30");
+        assert_eq!(show(&ctx, "acos(0.5)"), "60");
+        assert_eq!(show(&ctx, "atan(1)"), "45");
+        // Radians keep every digit, so 2 asin(1) is still pi
+        assert_eq!(calc("2 * asin(1) - pi"), "0");
+    }
+}
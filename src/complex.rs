@@ -0,0 +1,353 @@
+// Complex numbers, so sqrt(-4) is 2i and ln(-1) is pi*i instead of an error.
+// Both parts are f64, in decimal mode too: complex results trade the
+// decimal precision for having an answer at all.
+//
+// Functions use the principal branch, the same one as most calculators:
+// sqrt(-4) is 2i (not -2i) and arg() is between -pi and pi.
+
+use std::f64::consts::FRAC_PI_2;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::angle::AngleMode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+// How complex results are shown: "3 + 4i" or "5 ∠ 53.13deg"
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ComplexForm {
+    #[default]
+    Rect,
+    Polar,
+}
+
+impl ComplexForm {
+    pub fn parse(name: &str) -> Option<ComplexForm> {
+        match name {
+            "rect" => Some(ComplexForm::Rect),
+            "polar" => Some(ComplexForm::Polar),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ComplexForm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ComplexForm::Rect => "rect",
+            ComplexForm::Polar => "polar",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Complex {
+    pub const I: Complex = Complex { re: 0.0, im: 1.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn is_finite(self) -> bool {
+        self.re.is_finite() && self.im.is_finite()
+    }
+
+    pub fn conj(self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+
+    // The distance from zero, |3 + 4i| = 5
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    // The angle from the positive real axis in radians, from -pi to pi
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn from_polar(r: f64, theta: f64) -> Complex {
+        Complex::new(r * theta.cos(), r * theta.sin())
+    }
+
+    // Like `tidy_trig` for real results: e^(pi*i) comes out as
+    // -1 + 1.2e-16i because pi isn't exact, so a part that is only a few
+    // rounding errors compared to the other one is taken to be zero
+    pub fn tidy(self) -> Complex {
+        let noise = 4.0 * f64::EPSILON * self.abs();
+        let snap = |part: f64| if part.abs() <= noise { 0.0 } else { part };
+        Complex::new(snap(self.re), snap(self.im))
+    }
+
+    pub fn exp(self) -> Complex {
+        Complex::from_polar(self.re.exp(), self.im)
+    }
+
+    // None for ln(0)
+    pub fn ln(self) -> Option<Complex> {
+        if self.re == 0.0 && self.im == 0.0 {
+            return None;
+        }
+        Some(Complex::new(self.abs().ln(), self.arg()))
+    }
+
+    pub fn sqrt(self) -> Complex {
+        // Computed from |z| rather than as exp(ln(z) / 2),
+        // so sqrt(-4) is exactly 2i
+        let r = self.abs();
+        let re = ((r + self.re) / 2.0).sqrt();
+        let im = ((r - self.re) / 2.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    // None for 0 to a power whose real part is negative
+    pub fn pow(self, exp: Complex) -> Option<Complex> {
+        // Whole powers by repeated multiplication, so i^2 is exactly -1
+        if exp.im == 0.0 && exp.re.fract() == 0.0 && exp.re.abs() <= 64.0 {
+            let mut result = Complex::new(1.0, 0.0);
+            for _ in 0..exp.re.abs() as u32 {
+                result = result * self;
+            }
+            return if exp.re < 0.0 {
+                Complex::new(1.0, 0.0).checked_div(result)
+            } else {
+                Some(result)
+            };
+        }
+        if self.re == 0.0 && self.im == 0.0 {
+            return if exp.re > 0.0 { Some(self) } else { None };
+        }
+        Some((exp * self.ln()?).exp())
+    }
+
+    // None when dividing by zero
+    pub fn checked_div(self, other: Complex) -> Option<Complex> {
+        if other.re == 0.0 && other.im == 0.0 {
+            None
+        } else {
+            Some(self / other)
+        }
+    }
+
+    pub fn sin(self) -> Complex {
+        Complex::new(
+            self.re.sin() * self.im.cosh(),
+            self.re.cos() * self.im.sinh(),
+        )
+    }
+
+    pub fn cos(self) -> Complex {
+        Complex::new(
+            self.re.cos() * self.im.cosh(),
+            -self.re.sin() * self.im.sinh(),
+        )
+    }
+
+    pub fn tan(self) -> Option<Complex> {
+        self.sin().checked_div(self.cos())
+    }
+
+    // asin(z) = -i ln(iz + sqrt(1 - z^2)); the log's argument is never zero
+    pub fn asin(self) -> Complex {
+        let one = Complex::new(1.0, 0.0);
+        let inner = Complex::I * self + (one - self * self).sqrt();
+        -Complex::I * inner.ln().unwrap_or_default()
+    }
+
+    pub fn acos(self) -> Complex {
+        Complex::new(FRAC_PI_2, 0.0) - self.asin()
+    }
+
+    // atan(z) = i/2 ln((i + z) / (i - z)); None at the poles z = i and z = -i
+    pub fn atan(self) -> Option<Complex> {
+        let ratio = (Complex::I + self).checked_div(Complex::I - self)?;
+        Some(Complex::new(0.0, 0.5) * ratio.ln()?)
+    }
+
+    // "5 ∠ 53.13010235415598deg", with the angle in the given unit and
+    // each number written by `part`
+    pub fn to_polar_string(self, angle: AngleMode, part: impl Fn(f64) -> String) -> String {
+        format!(
+            "{} ∠ {}{}",
+            part(self.abs()),
+            part(angle.express(self.arg())),
+            angle
+        )
+    }
+
+    // "3 - 4i", with each part written by `part`, e.g. in a chosen notation
+    pub fn to_string_with(self, part: impl Fn(f64) -> String) -> String {
+        let im = if self.im.abs() == 1.0 {
+            "i".to_string()
+        } else {
+            format!("{}i", part(self.im.abs()))
+        };
+        if self.re == 0.0 {
+            let sign = if self.im < 0.0 { "-" } else { "" };
+            format!("{}{}", sign, im)
+        } else {
+            let sign = if self.im < 0.0 { '-' } else { '+' };
+            format!("{} {} {}", part(self.re), sign, im)
+        }
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(x: f64) -> Self {
+        Complex::new(x, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+// Use `checked_div` unless `other` is known to be nonzero
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let denom = other.re * other.re + other.im * other.im;
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / denom,
+            (self.im * other.re - self.re * other.im) / denom,
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+// "3 + 4i", "1 - i", "2i" or "-i"
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_string_with(|x| x.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc;
+    use crate::eval::Context;
+
+    #[test]
+    fn arithmetic() {
+        let a = Complex::new(1.0, 2.0);
+        let b = Complex::new(3.0, -1.0);
+        assert_eq!(a + b, Complex::new(4.0, 1.0));
+        assert_eq!(a - b, Complex::new(-2.0, 3.0));
+        assert_eq!(a * b, Complex::new(5.0, 5.0));
+        assert_eq!((a * b).checked_div(b), Some(a));
+        assert_eq!(a.checked_div(Complex::default()), None);
+        assert_eq!(-a, Complex::new(-1.0, -2.0));
+        assert_eq!(calc("(1+2i)/(3-4i)"), "-0.2 + 0.4i");
+        assert_eq!(calc("(1+i) - (1+i)"), "0");
+        assert_eq!(calc("1/(0i)"), "Cannot divide by zero");
+    }
+
+    #[test]
+    fn elementary_functions() {
+        assert_eq!(Complex::from(-4.0).sqrt(), Complex::new(0.0, 2.0));
+        assert_eq!(Complex::new(0.0, -4.0).sqrt().im, -2.0f64.sqrt());
+        assert_eq!(
+            Complex::I.pow(Complex::from(2.0)),
+            Some(Complex::from(-1.0))
+        );
+        assert_eq!(Complex::default().pow(Complex::from(-1.0)), None);
+        assert_eq!(Complex::default().ln(), None);
+        assert_eq!(Complex::I.atan(), None);
+        assert_eq!(calc("sqrt(-4)"), "2i");
+        assert_eq!(calc("exp(i*pi)"), "-1");
+        assert_eq!(calc("ln(-1)"), "3.141592653589793i");
+        assert_eq!(calc("i^i"), "0.20787957635076193");
+        assert_eq!(calc("(2i)^2"), "-4");
+        assert_eq!(calc("sin(i)"), "1.1752011936438014i");
+        assert_eq!(calc("cos(i)"), "1.5430806348152437");
+    }
+
+    #[test]
+    fn parts() {
+        assert_eq!(calc("re(3+4i)"), "3");
+        assert_eq!(calc("im(3+4i)"), "4");
+        assert_eq!(calc("conj(3+4i)"), "3 - 4i");
+        assert_eq!(calc("abs(3+4i)"), "5");
+        assert_eq!(calc("arg(-1)"), "3.141592653589793");
+        assert_eq!(calc("arg(-i)"), "-1.5707963267948966");
+        assert_eq!(Complex::new(3.0, 4.0).arg(), 4f64.atan2(3.0));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Complex::new(3.0, 4.0).to_string(), "3 + 4i");
+        assert_eq!(Complex::new(1.0, -1.0).to_string(), "1 - i");
+        assert_eq!(Complex::new(0.0, 2.0).to_string(), "2i");
+        assert_eq!(Complex::new(0.0, -1.0).to_string(), "-i");
+        let part = |x: f64| x.to_string();
+        assert_eq!(
+            Complex::new(1.0, 1.0).to_polar_string(AngleMode::Deg, part),
+            "1.4142135623730951 ∠ 45deg"
+        );
+        assert_eq!(
+            Complex::new(0.0, 3.0).to_polar_string(AngleMode::Rad, part),
+            "3 ∠ 1.5707963267948966rad"
+        );
+        assert_eq!(
+            Complex::new(-5.0, 0.0).to_polar_string(AngleMode::Grad, part),
+            "5 ∠ 200grad"
+        );
+    }
+
+    #[test]
+    fn polar_mode() {
+        let mut ctx = Context::new();
+        ctx.settings.complex = ComplexForm::Polar;
+        let show = |ctx: &Context, input: &str| ctx.settings.format(&ctx.evaluate(input).unwrap());
+        assert_eq!(
+            show(&ctx, "1 + i"),
+            "1.4142135623730951 ∠ 0.7853981633974483rad"
+        );
+        // Real results stay as they are
+        assert_eq!(show(&ctx, "-2"), "-2");
+        ctx.settings.angle = AngleMode::Deg;
+        assert_eq!(show(&ctx, "3i"), "3 ∠ 90deg");
+    }
+
+    #[test]
+    fn tidy_drops_rounding_noise() {
+        assert_eq!(Complex::new(-1.0, 1.2e-16).tidy(), Complex::from(-1.0));
+        assert_eq!(Complex::new(1e-10, 1.0).tidy(), Complex::new(1e-10, 1.0));
+    }
+}
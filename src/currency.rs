@@ -0,0 +1,258 @@
+// Exchange rates read from a local file, so `120 EUR in USD` works offline.
+// Nothing is fetched: whoever maintains the file decides how fresh it is,
+// and the file's timestamp is shown next to results so nobody has to guess.
+//
+// Two formats are understood. CSV, one "key,value" pair per line:
+//
+//     base,EUR
+//     timestamp,2026-10-14 16:00 CET
+//     USD,1.0843
+//     GBP,0.8571
+//
+// or JSON, in the shape most rate services publish:
+//
+//     {"base": "EUR", "timestamp": "2026-10-14 16:00 CET",
+//      "rates": {"USD": 1.0843, "GBP": 0.8571}}
+//
+// A rate is how much of that currency one unit of the base currency buys.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::bigint::BigInt;
+use crate::decimal::Decimal;
+use crate::json::Json;
+use crate::rational::Rational;
+
+// Currencies are written as their ISO 4217 code, like EUR or JPY
+pub fn is_code(name: &str) -> bool {
+    name.len() == 3 && name.bytes().all(|b| b.is_ascii_uppercase())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Rates {
+    // Where the table was loaded from, for messages
+    pub source: String,
+    pub base: String,
+    pub timestamp: Option<String>,
+    // Units of each currency per one unit of the base, the base included
+    rates: BTreeMap<String, Rational>,
+}
+
+impl Rates {
+    // Reads a rate file, as JSON if the name ends in .json and CSV otherwise
+    pub fn load(path: &Path) -> Result<Rates, String> {
+        let source = path.display().to_string();
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", source, e))?;
+        let is_json = path.extension().is_some_and(|ext| ext == "json");
+        let parsed = if is_json {
+            Rates::parse_json(&text)
+        } else {
+            Rates::parse_csv(&text)
+        };
+        let mut rates = parsed.map_err(|message| format!("{}: {}", source, message))?;
+        rates.source = source;
+        Ok(rates)
+    }
+
+    // Blank lines, lines starting with '#' and a "currency,rate" header are skipped
+    pub fn parse_csv(text: &str) -> Result<Rates, String> {
+        let mut base = None;
+        let mut timestamp = None;
+        let mut rates = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once(',') else {
+                return Err(format!(
+                    "line {}: expected 'code,rate' (got '{}')",
+                    number + 1,
+                    line
+                ));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            match key.to_ascii_lowercase().as_str() {
+                "base" => base = Some(value.to_string()),
+                "timestamp" | "date" => timestamp = Some(value.to_string()),
+                "currency" | "code" => {}
+                _ => rates.push((key.to_string(), value.to_string())),
+            }
+        }
+        Rates::build(base, timestamp, rates)
+    }
+
+    pub fn parse_json(text: &str) -> Result<Rates, String> {
+        let json = Json::parse(text)?;
+        let text_of = |key: &str| match json.get(key) {
+            Some(Json::String(text) | Json::Number(text)) => Some(text.clone()),
+            _ => None,
+        };
+        let base = text_of("base");
+        let timestamp = text_of("timestamp").or_else(|| text_of("date"));
+        let Some(Json::Object(entries)) = json.get("rates") else {
+            return Err("expected a \"rates\" object".to_string());
+        };
+        let mut rates = Vec::new();
+        for (code, rate) in entries {
+            let Json::Number(rate) = rate else {
+                return Err(format!("the rate for {} is not a number", code));
+            };
+            rates.push((code.clone(), rate.clone()));
+        }
+        Rates::build(base, timestamp, rates)
+    }
+
+    fn build(
+        base: Option<String>,
+        timestamp: Option<String>,
+        entries: Vec<(String, String)>,
+    ) -> Result<Rates, String> {
+        let base = base.ok_or("no base currency given (add a 'base' entry)")?;
+        if !is_code(&base) {
+            return Err(format!("'{}' is not a currency code like EUR", base));
+        }
+        let mut rates = BTreeMap::new();
+        rates.insert(base.clone(), Rational::from(BigInt::one()));
+        for (code, text) in entries {
+            if !is_code(&code) {
+                return Err(format!("'{}' is not a currency code like EUR", code));
+            }
+            let rate = Decimal::parse(&text)
+                .map(|rate| Rational::from(&rate))
+                .filter(|rate| !rate.is_zero() && !rate.is_negative())
+                .ok_or_else(|| {
+                    format!(
+                        "the rate for {} must be a positive number (got '{}')",
+                        code, text
+                    )
+                })?;
+            rates.insert(code, rate);
+        }
+        Ok(Rates {
+            source: String::new(),
+            base,
+            timestamp,
+            rates,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rates.len()
+    }
+
+    pub fn contains(&self, code: &str) -> bool {
+        self.rates.contains_key(code)
+    }
+
+    // The currency codes with a rate, the base included
+    pub fn codes(&self) -> impl Iterator<Item = &str> {
+        self.rates.keys().map(String::as_str)
+    }
+
+    // What one unit of `code` is worth in the base currency
+    pub fn value_of(&self, code: &str) -> Option<Rational> {
+        self.rates.get(code)?.recip()
+    }
+
+    // "32 currencies against EUR, as of 2026-10-14 16:00 CET (rates.csv)"
+    pub fn describe(&self) -> String {
+        format!(
+            "{} currencies against {}, {} ({})",
+            self.len(),
+            self.base,
+            self.as_of(),
+            self.source
+        )
+    }
+
+    // "as of 2026-10-14 16:00 CET", for showing next to converted amounts
+    pub fn as_of(&self) -> String {
+        match &self.timestamp {
+            Some(timestamp) => format!("as of {}", timestamp),
+            None => "with no timestamp".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Context;
+
+    const CSV: &str = "# rates for testing\n\
+                       currency,rate\n\
+                       base,EUR\n\
+                       timestamp,2026-10-14 16:00 CET\n\
+                       \n\
+                       USD,1.25\n\
+                       GBP, 0.8\n";
+
+    fn rational(text: &str) -> Rational {
+        Rational::from(&Decimal::parse(text).unwrap())
+    }
+
+    #[test]
+    fn csv_files() {
+        let rates = Rates::parse_csv(CSV).unwrap();
+        assert_eq!(rates.base, "EUR");
+        assert_eq!(rates.as_of(), "as of 2026-10-14 16:00 CET");
+        assert_eq!(rates.len(), 3);
+        assert_eq!(rates.value_of("EUR"), Some(rational("1")));
+        assert_eq!(rates.value_of("USD"), Some(rational("0.8")));
+        assert_eq!(rates.value_of("GBP"), Some(rational("1.25")));
+        assert_eq!(rates.value_of("JPY"), None);
+    }
+
+    #[test]
+    fn json_files() {
+        let text = r#"{"base": "EUR", "date": "2026-10-14", "rates": {"USD": 1.25, "JPY": 160}}"#;
+        let rates = Rates::parse_json(text).unwrap();
+        assert_eq!(rates.as_of(), "as of 2026-10-14");
+        assert!(rates.contains("JPY"));
+        assert_eq!(rates.value_of("USD"), Some(rational("0.8")));
+    }
+
+    #[test]
+    fn bad_rate_files() {
+        assert_eq!(
+            Rates::parse_csv("USD,1.25").unwrap_err(),
+            "no base currency given (add a 'base' entry)"
+        );
+        assert_eq!(
+            Rates::parse_csv("base,EUR\nUSD 1.25").unwrap_err(),
+            "line 2: expected 'code,rate' (got 'USD 1.25')"
+        );
+        assert_eq!(
+            Rates::parse_csv("base,EUR\nUSD,-1").unwrap_err(),
+            "the rate for USD must be a positive number (got '-1')"
+        );
+        assert_eq!(
+            Rates::parse_csv("base,EUR\nusd,1").unwrap_err(),
+            "'usd' is not a currency code like EUR"
+        );
+        assert_eq!(
+            Rates::parse_json(r#"{"base": "EUR", "rates": {"USD": "1.25"}}"#).unwrap_err(),
+            "the rate for USD is not a number"
+        );
+    }
+
+    #[test]
+    fn converting_between_currencies() {
+        let mut ctx = Context::new();
+        ctx.rates = Rates::parse_csv(CSV).unwrap();
+        assert_eq!(
+            ctx.evaluate("100 USD in GBP").unwrap().to_string(),
+            "64 GBP"
+        );
+        assert!(ctx.evaluate("1 USD + 1 m").is_err());
+        assert!(Context::new().evaluate("1 USD").is_err());
+    }
+}
@@ -0,0 +1,885 @@
+// Decimal numbers with a configurable number of significant digits, used by
+// `mode decimal`. Here 0.1 + 0.2 is exactly 0.3, unlike with f64.
+//
+// A Decimal is an integer coefficient times a power of ten: 1.25 is stored
+// as 125 x 10^-2. Each operation computes its result exactly (or with a few
+// guard digits) and then rounds it to the session's precision.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Neg;
+
+use crate::bigint::BigInt;
+
+// 34 digits is what IEEE 754 decimal128 carries
+pub const DEFAULT_PRECISION: usize = 34;
+pub const MAX_PRECISION: usize = 1000;
+
+// Extra digits carried through multi-step functions like ln() or sin(),
+// so the final rounding sees the correct digits
+pub const GUARD_DIGITS: usize = 10;
+
+// Results whose decimal exponent goes beyond this count as overflow.
+// It keeps exponents far away from i64 limits and digit counts sane.
+const MAX_EXPONENT: i64 = 1_000_000_000_000_000;
+
+// How results are cut down to the session's precision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    // Ties go to the even neighbour: 2.5 -> 2, 3.5 -> 4 ("banker's rounding")
+    #[default]
+    HalfEven,
+    // Ties go away from zero: 2.5 -> 3, -2.5 -> -3
+    HalfUp,
+    // Extra digits are dropped: 2.9 -> 2, -2.9 -> -2
+    Truncate,
+}
+
+impl RoundingMode {
+    pub fn parse(name: &str) -> Option<RoundingMode> {
+        match name {
+            "half-even" => Some(RoundingMode::HalfEven),
+            "half-up" => Some(RoundingMode::HalfUp),
+            "truncate" => Some(RoundingMode::Truncate),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RoundingMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RoundingMode::HalfEven => "half-even",
+            RoundingMode::HalfUp => "half-up",
+            RoundingMode::Truncate => "truncate",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// How many significant digits to keep, and how to round away the rest
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecimalContext {
+    pub precision: usize,
+    pub rounding: RoundingMode,
+}
+
+impl Default for DecimalContext {
+    fn default() -> Self {
+        DecimalContext {
+            precision: DEFAULT_PRECISION,
+            rounding: RoundingMode::default(),
+        }
+    }
+}
+
+impl DecimalContext {
+    // A context for intermediate steps: `extra` more digits, rounded to nearest
+    pub fn extended(self, extra: usize) -> Self {
+        DecimalContext {
+            precision: self.precision + extra,
+            rounding: RoundingMode::HalfEven,
+        }
+    }
+}
+
+// Which way to round a value that falls between two representable ones:
+// the session's rounding modes plus the fixed directions floor() and ceil() need
+#[derive(Debug, Clone, Copy)]
+pub enum Round {
+    HalfEven,
+    HalfUp,
+    Down,
+    Floor,
+    Ceiling,
+}
+
+impl From<RoundingMode> for Round {
+    fn from(mode: RoundingMode) -> Self {
+        match mode {
+            RoundingMode::HalfEven => Round::HalfEven,
+            RoundingMode::HalfUp => Round::HalfUp,
+            RoundingMode::Truncate => Round::Down,
+        }
+    }
+}
+
+// coeff x 10^exp
+#[derive(Debug, Clone, Default)]
+pub struct Decimal {
+    coeff: BigInt,
+    exp: i64,
+}
+
+impl Decimal {
+    fn new(coeff: BigInt, exp: i64) -> Self {
+        Decimal { coeff, exp }
+    }
+
+    pub fn zero() -> Self {
+        Decimal::default()
+    }
+
+    pub fn one() -> Self {
+        Decimal::from(1)
+    }
+
+    // Parses number literals like "12", "-0.25", ".5" or "6.02e23", exactly
+    pub fn parse(text: &str) -> Option<Self> {
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(i) => (&text[..i], text[i + 1..].parse::<i64>().ok()?),
+            None => (text, 0),
+        };
+        if exponent.abs() > MAX_EXPONENT {
+            return None;
+        }
+        let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if frac_part.starts_with(['+', '-']) {
+            return None;
+        }
+        let coeff = BigInt::parse(&format!("{}{}", int_part, frac_part))?;
+        Some(Decimal::new(coeff, exponent - frac_part.len() as i64))
+    }
+
+    // The exact decimal equivalent of what an f64 prints as, so 0.1 becomes 0.1
+    pub fn from_f64(x: f64) -> Option<Self> {
+        if !x.is_finite() {
+            return None;
+        }
+        Decimal::parse(&format!("{:e}", x))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        format!("{}e{}", self.coeff, self.exp)
+            .parse()
+            .unwrap_or(f64::NAN)
+    }
+
+    // The value is coefficient() x 10^exponent()
+    pub fn coefficient(&self) -> &BigInt {
+        &self.coeff
+    }
+
+    pub fn exponent(&self) -> i64 {
+        self.exp
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coeff.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.coeff.is_negative()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.exp >= 0 || self.round_to_exp(0, Round::Down) == *self
+    }
+
+    // True for an integer whose last digit is odd
+    pub fn is_odd(&self) -> bool {
+        let n = self.normalized();
+        n.exp == 0 && !n.coeff.is_even()
+    }
+
+    // The power of ten of the leading digit: 1234.5 is 1.2345e3, so 3
+    pub fn adjusted(&self) -> i64 {
+        self.exp + self.coeff.num_digits() as i64 - 1
+    }
+
+    // False once a result is too large (or too small) to keep computing with
+    pub fn in_range(&self) -> bool {
+        self.is_zero() || self.adjusted().abs() <= MAX_EXPONENT
+    }
+
+    pub fn abs(&self) -> Decimal {
+        Decimal::new(self.coeff.abs(), self.exp)
+    }
+
+    // Rounds to the context's number of significant digits
+    pub fn round(&self, ctx: DecimalContext) -> Decimal {
+        let digits = self.coeff.num_digits();
+        if digits <= ctx.precision {
+            return self.clone();
+        }
+        let exp = self.exp + (digits - ctx.precision) as i64;
+        self.round_to_exp(exp, ctx.rounding.into())
+    }
+
+    // Rounds to a whole number, e.g. for floor() and ceil()
+    pub fn round_integer(&self, round: Round) -> Decimal {
+        self.round_to_exp(0, round)
+    }
+
+    // Rounds so the last digit kept is the 10^exp digit
+    pub fn round_to_exp(&self, exp: i64, round: Round) -> Decimal {
+        if self.exp >= exp {
+            return self.clone();
+        }
+        let drop = (exp - self.exp) as usize;
+        let digits = self.coeff.num_digits();
+        // When every digit is dropped, only the sign and "not zero" matter;
+        // a single 1 two places below the cut stands in for them.
+        let (coeff, drop) = if drop > digits + 1 {
+            (BigInt::from(self.coeff.signum() as i64), 2)
+        } else {
+            (self.coeff.clone(), drop)
+        };
+        Decimal::new(round_coeff(&coeff, drop, round), exp)
+    }
+
+    // The same value with trailing zeros removed from the coefficient
+    fn normalized(&self) -> Decimal {
+        if self.is_zero() {
+            return Decimal::zero();
+        }
+        let ten = BigInt::from(10u64);
+        let mut result = self.clone();
+        loop {
+            let (q, r) = result.coeff.div_rem(&ten);
+            if !r.is_zero() {
+                return result;
+            }
+            result = Decimal::new(q, result.exp + 1);
+        }
+    }
+
+    pub fn add(&self, other: &Decimal, ctx: DecimalContext) -> Decimal {
+        if other.is_zero() {
+            return self.round(ctx);
+        }
+        if self.is_zero() {
+            return other.round(ctx);
+        }
+        let (big, small) = if self.adjusted() >= other.adjusted() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        // Digits far below the rounding point of the larger operand can only
+        // nudge the rounding, so a tiny stand-in avoids aligning e.g. 1e900 and 1
+        let floor = big.adjusted() - ctx.precision as i64 - 2;
+        let stand_in;
+        let small = if small.adjusted() < floor {
+            stand_in = Decimal::new(BigInt::from(small.coeff.signum() as i64), floor - 1);
+            &stand_in
+        } else {
+            small
+        };
+        let exp = big.exp.min(small.exp);
+        let a = big.coeff.mul_pow10((big.exp - exp) as usize);
+        let b = small.coeff.mul_pow10((small.exp - exp) as usize);
+        Decimal::new(&a + &b, exp).round(ctx)
+    }
+
+    pub fn sub(&self, other: &Decimal, ctx: DecimalContext) -> Decimal {
+        self.add(&-other, ctx)
+    }
+
+    pub fn mul(&self, other: &Decimal, ctx: DecimalContext) -> Decimal {
+        Decimal::new(&self.coeff * &other.coeff, self.exp + other.exp).round(ctx)
+    }
+
+    // `other` must not be zero; the caller reports that as an error
+    pub fn div(&self, other: &Decimal, ctx: DecimalContext) -> Decimal {
+        if self.is_zero() {
+            return Decimal::zero();
+        }
+        // Scale the dividend so the integer quotient has more digits than we keep
+        let shift =
+            (ctx.precision + 2 + other.coeff.num_digits()).saturating_sub(self.coeff.num_digits());
+        let (mut quotient, remainder) = self.coeff.mul_pow10(shift).div_rem(&other.coeff);
+        let mut exp = self.exp - other.exp - shift as i64;
+        if !remainder.is_zero() {
+            // An extra nonzero digit marks the quotient as inexact, so a
+            // result like 0.4999...|1 can't be mistaken for an exact tie
+            let sticky = BigInt::from(quotient.signum() as i64);
+            quotient = &quotient.mul_pow10(1) + &sticky;
+            exp -= 1;
+        }
+        Decimal::new(quotient, exp).round(ctx)
+    }
+
+    // floor(self / other), computed exactly before rounding to `ctx`.
+    // `other` must not be zero. None if the operands are so far apart in
+    // size that lining them up would take an unreasonable number of digits.
+    pub fn div_floor(&self, other: &Decimal, ctx: DecimalContext) -> Option<Decimal> {
+        let (a, b, _) = self.aligned(other)?;
+        Some(Decimal::new(a.div_floor(&b), 0).round(ctx))
+    }
+
+    // The remainder of floored division, with the sign of `other`, for
+    // both % and mod(): -7 mod 3 = 2. Same conditions as `div_floor`.
+    pub fn modulo(&self, other: &Decimal, ctx: DecimalContext) -> Option<Decimal> {
+        let (a, b, exp) = self.aligned(other)?;
+        let remainder = &a - &(&b * &a.div_floor(&b));
+        Some(Decimal::new(remainder, exp).round(ctx))
+    }
+
+    // Both coefficients scaled to the smaller exponent, plus that exponent
+    fn aligned(&self, other: &Decimal) -> Option<(BigInt, BigInt, i64)> {
+        let exp = self.exp.min(other.exp);
+        let (a_shift, b_shift) = (self.exp - exp, other.exp - exp);
+        if a_shift.max(b_shift) > 100_000 {
+            return None;
+        }
+        let a = self.coeff.mul_pow10(a_shift as usize);
+        let b = other.coeff.mul_pow10(b_shift as usize);
+        Some((a, b, exp))
+    }
+}
+
+// Drops the last `drop` digits of `coeff`, rounding in the given direction
+fn round_coeff(coeff: &BigInt, drop: usize, round: Round) -> BigInt {
+    if drop == 0 {
+        return coeff.clone();
+    }
+    let divisor = BigInt::pow10(drop);
+    let (quotient, remainder) = coeff.div_rem(&divisor);
+    if remainder.is_zero() {
+        return quotient;
+    }
+    let away_from_zero = match round {
+        Round::Down => false,
+        Round::Floor => coeff.is_negative(),
+        Round::Ceiling => !coeff.is_negative(),
+        Round::HalfEven | Round::HalfUp => {
+            let twice = &remainder.abs() * &BigInt::from(2u64);
+            match twice.cmp(&divisor) {
+                Ordering::Greater => true,
+                Ordering::Less => false,
+                Ordering::Equal => matches!(round, Round::HalfUp) || !quotient.is_even(),
+            }
+        }
+    };
+    if !away_from_zero {
+        return quotient;
+    }
+    let step = if coeff.is_negative() {
+        BigInt::from(-1i64)
+    } else {
+        BigInt::one()
+    };
+    &quotient + &step
+}
+
+impl From<i64> for Decimal {
+    fn from(n: i64) -> Self {
+        Decimal::new(BigInt::from(n), 0)
+    }
+}
+
+impl From<BigInt> for Decimal {
+    fn from(n: BigInt) -> Self {
+        Decimal::new(n, 0)
+    }
+}
+
+impl Neg for &Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal::new(-&self.coeff, self.exp)
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        -&self
+    }
+}
+
+// Compares values, not representations: 1.50 == 1.5
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let sign = self.coeff.signum().cmp(&other.coeff.signum());
+        if sign != Ordering::Equal || self.is_zero() {
+            return sign;
+        }
+        let magnitude = match self.adjusted().cmp(&other.adjusted()) {
+            Ordering::Equal => {
+                let exp = self.exp.min(other.exp);
+                let a = self.coeff.abs().mul_pow10((self.exp - exp) as usize);
+                let b = other.coeff.abs().mul_pow10((other.exp - exp) as usize);
+                a.cmp(&b)
+            }
+            order => order,
+        };
+        if self.is_negative() {
+            magnitude.reverse()
+        } else {
+            magnitude
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+// Plain notation for everyday magnitudes, otherwise scientific notation that
+// can be typed back in: 0.000125, 1250000, 1.5e-12, 6.02214076e23
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let n = self.normalized();
+        let digits = n.coeff.abs().to_string();
+        let sign = if n.is_negative() { "-" } else { "" };
+        let adjusted = n.adjusted();
+        let text = if n.exp >= 0 && adjusted < 50 {
+            format!("{}{}{}", sign, digits, "0".repeat(n.exp as usize))
+        } else if n.exp < 0 && adjusted >= -7 {
+            // How many digits come before the decimal point
+            let point = digits.len() as i64 + n.exp;
+            if point > 0 {
+                let (int_part, frac_part) = digits.split_at(point as usize);
+                format!("{}{}.{}", sign, int_part, frac_part)
+            } else {
+                format!("{}0.{}{}", sign, "0".repeat(-point as usize), digits)
+            }
+        } else {
+            let (first, rest) = digits.split_at(1);
+            if rest.is_empty() {
+                format!("{}{}e{}", sign, first, adjusted)
+            } else {
+                format!("{}{}.{}e{}", sign, first, rest, adjusted)
+            }
+        };
+        f.pad(&text)
+    }
+}
+
+// ---- Functions ----
+//
+// Each one works with GUARD_DIGITS extra digits and rounds once at the end.
+
+// True once `term` is too small to change `sum` at this precision,
+// which is when a series can stop
+fn negligible(term: &Decimal, sum: &Decimal, precision: usize) -> bool {
+    term.is_zero() || (!sum.is_zero() && term.adjusted() < sum.adjusted() - precision as i64 - 2)
+}
+
+// How many digits `n` has
+fn digit_count(n: u64) -> usize {
+    n.to_string().len()
+}
+
+// x must not be negative
+pub fn sqrt(x: &Decimal, ctx: DecimalContext) -> Decimal {
+    if x.is_zero() {
+        return Decimal::zero();
+    }
+    // Take the integer square root of coeff x 10^shift, with shift chosen so
+    // the root has enough digits and the remaining exponent is even
+    let digits = x.coeff.num_digits() as i64;
+    let mut shift = (2 * (ctx.precision as i64 + 2) - digits).max(0);
+    if (x.exp - shift).rem_euclid(2) != 0 {
+        shift += 1;
+    }
+    let n = x.coeff.mul_pow10(shift as usize);
+    let mut root = n.sqrt();
+    let mut exp = (x.exp - shift) / 2;
+    if &root * &root != n {
+        // Inexact: add a sticky digit, as in `div`
+        root = &root.mul_pow10(1) + &BigInt::one();
+        exp -= 1;
+    }
+    Decimal::new(root, exp).round(ctx)
+}
+
+pub fn exp(x: &Decimal, ctx: DecimalContext) -> Decimal {
+    if x.is_zero() {
+        return Decimal::one();
+    }
+    // e^(10^16) is far beyond MAX_EXPONENT; return something past it so
+    // the caller reports an overflow (or zero, for a huge negative x)
+    if x.adjusted() >= 16 {
+        return if x.is_negative() {
+            Decimal::zero()
+        } else {
+            Decimal::new(BigInt::one(), MAX_EXPONENT + 1)
+        };
+    }
+    // e^x = (e^(x / 2^k))^(2^k): halve x until the series converges quickly,
+    // then square the result back up. Each squaring costs a little accuracy.
+    let magnitude = x.to_f64().abs();
+    let halvings = if magnitude > 0.5 {
+        (magnitude / 0.5).log2().ceil() as usize
+    } else {
+        0
+    };
+    let work = ctx.extended(GUARD_DIGITS + halvings);
+    let divisor = Decimal::new(BigInt::from(2u64).pow(halvings as u32), 0);
+    let y = x.div(&divisor, work);
+
+    let mut sum = Decimal::one();
+    let mut term = Decimal::one();
+    let mut n = 1;
+    loop {
+        term = term.mul(&y, work).div(&Decimal::from(n), work);
+        if negligible(&term, &sum, work.precision) {
+            break;
+        }
+        sum = sum.add(&term, work);
+        n += 1;
+    }
+    for _ in 0..halvings {
+        sum = sum.mul(&sum, work);
+    }
+    sum.round(ctx)
+}
+
+// x must be positive
+pub fn ln(x: &Decimal, ctx: DecimalContext) -> Decimal {
+    let one = Decimal::one();
+    if *x == one {
+        return Decimal::zero();
+    }
+    let work = ctx.extended(GUARD_DIGITS);
+    // Close to 1 the series is already fast, and splitting off powers of
+    // 2 and 10 would cancel away the digits we want
+    if x.sub(&one, work).abs() < Decimal::new(BigInt::from(5u64), -1) {
+        return ln_near_one(x, work).round(ctx);
+    }
+    // x = y * 2^b * 10^a with y close to 1, so ln(x) = ln(y) + b ln(2) + a ln(10)
+    let a = x.adjusted();
+    let scaled = Decimal::new(x.coeff.clone(), x.exp - a);
+    let b = scaled.to_f64().log2().round() as i64;
+    let y = scaled.div(&Decimal::from(1 << b), work);
+    let work = work.extended(digit_count(a.unsigned_abs()));
+    let result = ln_near_one(&y, work)
+        .add(&ln2(work).mul(&Decimal::from(b), work), work)
+        .add(&ln10(work).mul(&Decimal::from(a), work), work);
+    result.round(ctx)
+}
+
+// ln(y) = 2 atanh((y - 1) / (y + 1)), which converges quickly for y near 1
+fn ln_near_one(y: &Decimal, work: DecimalContext) -> Decimal {
+    let one = Decimal::one();
+    let z = y.sub(&one, work).div(&y.add(&one, work), work);
+    atanh(&z, work).mul(&Decimal::from(2), work)
+}
+
+// ln(2) = 2 atanh(1/3)
+fn ln2(work: DecimalContext) -> Decimal {
+    let third = Decimal::one().div(&Decimal::from(3), work);
+    atanh(&third, work).mul(&Decimal::from(2), work)
+}
+
+// ln(10) = 3 ln(2) + ln(1.25), and ln(1.25) = 2 atanh(1/9)
+fn ln10(work: DecimalContext) -> Decimal {
+    let ninth = Decimal::one().div(&Decimal::from(9), work);
+    let ln_five_quarters = atanh(&ninth, work).mul(&Decimal::from(2), work);
+    ln2(work)
+        .mul(&Decimal::from(3), work)
+        .add(&ln_five_quarters, work)
+}
+
+// z + z^3/3 + z^5/5 + ...
+fn atanh(z: &Decimal, work: DecimalContext) -> Decimal {
+    let z2 = z.mul(z, work);
+    let mut sum = z.clone();
+    let mut power = z.clone();
+    let mut n = 3;
+    loop {
+        power = power.mul(&z2, work);
+        let term = power.div(&Decimal::from(n), work);
+        if negligible(&term, &sum, work.precision) {
+            return sum;
+        }
+        sum = sum.add(&term, work);
+        n += 2;
+    }
+}
+
+// z - z^3/3 + z^5/5 - ...
+fn atan_series(z: &Decimal, work: DecimalContext) -> Decimal {
+    let z2 = z.mul(z, work);
+    let mut sum = z.clone();
+    let mut power = z.clone();
+    let mut n = 3;
+    loop {
+        power = -power.mul(&z2, work);
+        let term = power.div(&Decimal::from(n), work);
+        if negligible(&term, &sum, work.precision) {
+            return sum;
+        }
+        sum = sum.add(&term, work);
+        n += 2;
+    }
+}
+
+pub fn pi(ctx: DecimalContext) -> Decimal {
+    let work = ctx.extended(GUARD_DIGITS);
+    // Machin's formula: pi = 16 atan(1/5) - 4 atan(1/239)
+    let one = Decimal::one();
+    let a = atan_series(&one.div(&Decimal::from(5), work), work);
+    let b = atan_series(&one.div(&Decimal::from(239), work), work);
+    a.mul(&Decimal::from(16), work)
+        .sub(&b.mul(&Decimal::from(4), work), work)
+        .round(ctx)
+}
+
+// Result in radians
+pub fn atan(x: &Decimal, ctx: DecimalContext) -> Decimal {
+    let work = ctx.extended(GUARD_DIGITS);
+    let one = Decimal::one();
+    if x.abs() <= one {
+        return atan_small(x, work).round(ctx);
+    }
+    // atan(x) = +-pi/2 - atan(1/x)
+    let half_pi = pi(work).div(&Decimal::from(2), work);
+    let half_pi = if x.is_negative() { -half_pi } else { half_pi };
+    half_pi
+        .sub(&atan_small(&one.div(x, work), work), work)
+        .round(ctx)
+}
+
+// For |x| <= 1. Halving the angle three times with
+// atan(x) = 2 atan(x / (1 + sqrt(1 + x^2))) gets |x| below 0.1,
+// where the series converges quickly.
+fn atan_small(x: &Decimal, work: DecimalContext) -> Decimal {
+    let one = Decimal::one();
+    let mut y = x.clone();
+    for _ in 0..3 {
+        let root = sqrt(&one.add(&y.mul(&y, work), work), work);
+        y = y.div(&one.add(&root, work), work);
+    }
+    atan_series(&y, work).mul(&Decimal::from(8), work)
+}
+
+// x in radians
+pub fn sin(x: &Decimal, ctx: DecimalContext) -> Decimal {
+    sin_cos(x, ctx, true)
+}
+
+// x in radians
+pub fn cos(x: &Decimal, ctx: DecimalContext) -> Decimal {
+    sin_cos(x, ctx, false)
+}
+
+fn sin_cos(x: &Decimal, ctx: DecimalContext, sine: bool) -> Decimal {
+    // Subtracting whole turns from a large x needs as many extra digits
+    // as x has before the decimal point
+    let work = ctx.extended(GUARD_DIGITS + x.adjusted().max(0) as usize);
+    let two_pi = pi(work).mul(&Decimal::from(2), work);
+    let turns = x.div(&two_pi, work).round_integer(Round::HalfEven);
+    let r = x.sub(&turns.mul(&two_pi, work), work);
+    let r2 = r.mul(&r, work);
+
+    // sin: r - r^3/3! + r^5/5! - ...    cos: 1 - r^2/2! + r^4/4! - ...
+    let (mut sum, mut n) = if sine {
+        (r.clone(), 1)
+    } else {
+        (Decimal::one(), 0)
+    };
+    let mut term = sum.clone();
+    loop {
+        let step = Decimal::from((n + 1) * (n + 2));
+        term = -term.mul(&r2, work).div(&step, work);
+        if negligible(&term, &sum, work.precision) {
+            return sum.round(ctx);
+        }
+        sum = sum.add(&term, work);
+        n += 2;
+    }
+}
+
+// x^y. The caller rules out 0^negative and negative^fraction.
+pub fn pow(x: &Decimal, y: &Decimal, ctx: DecimalContext) -> Decimal {
+    let one = Decimal::one();
+    if y.is_zero() {
+        return one;
+    }
+    if x.is_zero() {
+        return Decimal::zero();
+    }
+    let negative = x.is_negative() && y.is_odd();
+    if x.abs() == one {
+        return if negative { -one } else { one };
+    }
+    // Whether |x^y| is larger than 1 (or smaller), for when it's out of range
+    let grows = (x.abs() > one) != y.is_negative();
+    let out_of_range = || {
+        if grows {
+            let huge = Decimal::new(BigInt::one(), MAX_EXPONENT + 1);
+            if negative { -huge } else { huge }
+        } else {
+            Decimal::zero()
+        }
+    };
+    if y.adjusted() >= 16 {
+        return out_of_range();
+    }
+
+    // Integer exponents: repeated squaring gives exact results like 1.1^2 = 1.21
+    if y.is_integer() && y.adjusted() < 7 {
+        let n = y.to_f64() as i64;
+        if (x.adjusted().unsigned_abs() + 1).saturating_mul(n.unsigned_abs()) > MAX_EXPONENT as u64
+        {
+            return out_of_range();
+        }
+        let work = ctx.extended(GUARD_DIGITS + digit_count(n.unsigned_abs()));
+        let mut result = one.clone();
+        let mut base = x.clone();
+        let mut e = n.unsigned_abs();
+        while e > 0 {
+            if e & 1 == 1 {
+                result = result.mul(&base, work);
+            }
+            e >>= 1;
+            if e > 0 {
+                base = base.mul(&base, work);
+            }
+        }
+        if n < 0 {
+            result = one.div(&result, work);
+        }
+        return result.round(ctx);
+    }
+
+    // Otherwise x^y = e^(y ln|x|); the exponent needs extra digits for
+    // every digit it has before the decimal point
+    let extra = (y.adjusted() + 1).max(0) as usize + digit_count(x.adjusted().unsigned_abs() + 1);
+    let work = ctx.extended(GUARD_DIGITS + extra);
+    let magnitude = exp(&ln(&x.abs(), work).mul(y, work), work);
+    let result = if negative { -magnitude } else { magnitude };
+    result.round(ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(text: &str) -> Decimal {
+        Decimal::parse(text).unwrap()
+    }
+
+    fn digits(precision: usize, rounding: RoundingMode) -> DecimalContext {
+        DecimalContext {
+            precision,
+            rounding,
+        }
+    }
+
+    // Each value rounded to one significant digit by each mode
+    #[test]
+    fn rounding_modes() {
+        let cases = [
+            ("2.5", "2", "3", "2"),
+            ("3.5", "4", "4", "3"),
+            ("-2.5", "-2", "-3", "-2"),
+            ("2.51", "3", "3", "2"),
+            ("2.49", "2", "2", "2"),
+            ("-2.9", "-3", "-3", "-2"),
+        ];
+        for (value, half_even, half_up, truncate) in cases {
+            let round = |mode| d(value).round(digits(1, mode)).to_string();
+            assert_eq!(
+                round(RoundingMode::HalfEven),
+                half_even,
+                "{} half-even",
+                value
+            );
+            assert_eq!(round(RoundingMode::HalfUp), half_up, "{} half-up", value);
+            assert_eq!(
+                round(RoundingMode::Truncate),
+                truncate,
+                "{} truncate",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn floor_and_ceiling() {
+        assert_eq!(d("-2.1").round_integer(Round::Floor), d("-3"));
+        assert_eq!(d("-2.1").round_integer(Round::Ceiling), d("-2"));
+        assert_eq!(d("2.1").round_integer(Round::Ceiling), d("3"));
+        // Every digit is below the cut, but the value still isn't zero
+        assert_eq!(d("0.0004").round_integer(Round::Ceiling), d("1"));
+        assert_eq!(d("-0.0004").round_integer(Round::Floor), d("-1"));
+        assert_eq!(d("0.0004").round_integer(Round::HalfUp), d("0"));
+    }
+
+    #[test]
+    fn division() {
+        let divide = |a: &str, b: &str, ctx| d(a).div(&d(b), ctx).to_string();
+        let half_even = digits(5, RoundingMode::HalfEven);
+        assert_eq!(divide("1", "3", half_even), "0.33333");
+        assert_eq!(divide("2", "3", half_even), "0.66667");
+        assert_eq!(
+            divide("2", "3", digits(5, RoundingMode::Truncate)),
+            "0.66666"
+        );
+        assert_eq!(
+            divide("-2", "3", digits(5, RoundingMode::HalfUp)),
+            "-0.66667"
+        );
+        assert_eq!(divide("10", "4", half_even), "2.5");
+        // An exact tie goes to the even digit, but a quotient just past one
+        // must round up even though its first dropped digits are 5000...
+        assert_eq!(divide("1", "8", digits(2, RoundingMode::HalfEven)), "0.12");
+        assert_eq!(divide("1", "8", digits(2, RoundingMode::HalfUp)), "0.13");
+        assert_eq!(
+            divide(
+                "750000000001",
+                "3000000000000",
+                digits(1, RoundingMode::HalfEven)
+            ),
+            "0.3"
+        );
+        assert_eq!(
+            divide("1", "7", digits(40, RoundingMode::HalfEven)),
+            "0.1428571428571428571428571428571428571429"
+        );
+    }
+
+    #[test]
+    fn remainders() {
+        let ctx = DecimalContext::default();
+        assert_eq!(d("-7").div_floor(&d("3"), ctx), Some(d("-3")));
+        assert_eq!(d("-7").modulo(&d("3"), ctx), Some(d("2")));
+        assert_eq!(d("7.5").modulo(&d("2"), ctx), Some(d("1.5")));
+        assert_eq!(d("7.5").modulo(&d("-2"), ctx), Some(d("-0.5")));
+        // Lining up 1e200000 and 1 would take 200,000 digits
+        assert_eq!(d("1e200000").modulo(&d("1"), ctx), None);
+    }
+
+    #[test]
+    fn from_f64_keeps_the_shortest_digits() {
+        let text = |x: f64| Decimal::from_f64(x).map(|d| d.to_string());
+        assert_eq!(text(0.1), Some("0.1".to_string()));
+        assert_eq!(text(1.0 / 3.0), Some("0.3333333333333333".to_string()));
+        assert_eq!(text(-2.5), Some("-2.5".to_string()));
+        assert_eq!(text(1e300), Some("1e300".to_string()));
+        assert_eq!(text(5e-324), Some("5e-324".to_string()));
+        assert_eq!(text(-0.0), Some("0".to_string()));
+        assert_eq!(text(f64::NAN), None);
+        assert_eq!(text(f64::INFINITY), None);
+    }
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(d("0.000125").to_string(), "0.000125");
+        assert_eq!(d("1.25e6").to_string(), "1250000");
+        assert_eq!(d("1.5e-12").to_string(), "1.5e-12");
+        assert_eq!(d("6.02214076e23").to_string(), "602214076000000000000000");
+        assert_eq!(d("1e60").to_string(), "1e60");
+        assert_eq!(d(".5").to_string(), "0.5");
+        assert_eq!(d("2.50").to_string(), "2.5");
+        assert_eq!(Decimal::parse("1.-5"), None);
+        assert_eq!(Decimal::parse("1e9999999999999999999"), None);
+    }
+}
@@ -0,0 +1,316 @@
+// Every way a calculation can fail, as data instead of a panic.
+// Like a JS `class CalcError extends Error`, but the compiler makes callers
+// handle each case, and each variant carries the span it refers to.
+
+use std::fmt;
+
+// A range of character columns in the input line, `start..end` (0-based)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    // The smallest span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcError {
+    InvalidNumber {
+        text: String,
+        span: Span,
+    },
+    UnknownOperator {
+        found: char,
+        span: Span,
+    },
+    UnexpectedToken {
+        found: String,
+        expected: Option<String>,
+        span: Span,
+    },
+    UnexpectedEnd {
+        expected: String,
+        span: Span,
+    },
+    UnclosedParen {
+        span: Span,
+    },
+    DivisionByZero {
+        span: Span,
+    },
+    Overflow {
+        span: Span,
+    },
+    Underflow {
+        span: Span,
+    },
+    Domain {
+        message: String,
+        span: Span,
+    },
+    UndefinedVariable {
+        name: String,
+        span: Span,
+    },
+    UndefinedFunction {
+        name: String,
+        span: Span,
+    },
+    WrongArgCount {
+        name: String,
+        expected: String,
+        found: usize,
+        span: Span,
+    },
+    RecursionLimit {
+        name: String,
+        limit: usize,
+        span: Span,
+    },
+    // Brackets or operators nested past what the parser allows; the span
+    // is the innermost '(' or operator that went over
+    NestingLimit {
+        limit: usize,
+        span: Span,
+    },
+    NoHistory {
+        reference: String,
+        span: Span,
+    },
+    Reserved {
+        name: String,
+        span: Span,
+    },
+    DuplicateParam {
+        name: String,
+        span: Span,
+    },
+    UnknownUnit {
+        name: String,
+        span: Span,
+    },
+    // A currency code the rate table has no rate for
+    UnknownCurrency {
+        code: String,
+        span: Span,
+    },
+    // Both sides are already described, e.g. "km (length)" and "s (time)"
+    UnitMismatch {
+        left: String,
+        right: String,
+        span: Span,
+    },
+    // Vectors or matrices that don't fit together, described like
+    // "2x3 matrix" and "3-element vector"; `operation` is a verb like "add"
+    ShapeMismatch {
+        operation: String,
+        left: String,
+        right: String,
+        span: Span,
+    },
+    // A matrix with no inverse, or equations with no solution or
+    // infinitely many; the message says which
+    Singular {
+        message: String,
+        span: Span,
+    },
+    // Stopped from outside before it finished; see Context::interrupt
+    Interrupted {
+        span: Span,
+    },
+    // An RPN operator or function with too few values on the stack
+    StackUnderflow {
+        word: String,
+        needed: usize,
+        found: usize,
+        span: Span,
+    },
+}
+
+impl CalcError {
+    pub fn span(&self) -> Span {
+        let mut copy = self.clone();
+        *copy.span_mut()
+    }
+
+    // A stable name for the kind of error, for scripts reading --json output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CalcError::InvalidNumber { .. } => "invalid_number",
+            CalcError::UnknownOperator { .. } => "unknown_operator",
+            CalcError::UnexpectedToken { .. } => "unexpected_token",
+            CalcError::UnexpectedEnd { .. } => "unexpected_end",
+            CalcError::UnclosedParen { .. } => "unclosed_paren",
+            CalcError::DivisionByZero { .. } => "division_by_zero",
+            CalcError::Overflow { .. } => "overflow",
+            CalcError::Underflow { .. } => "underflow",
+            CalcError::Domain { .. } => "domain",
+            CalcError::UndefinedVariable { .. } => "undefined_variable",
+            CalcError::UndefinedFunction { .. } => "undefined_function",
+            CalcError::WrongArgCount { .. } => "wrong_arg_count",
+            CalcError::RecursionLimit { .. } => "recursion_limit",
+            CalcError::NestingLimit { .. } => "nesting_limit",
+            CalcError::NoHistory { .. } => "no_history",
+            CalcError::Reserved { .. } => "reserved",
+            CalcError::DuplicateParam { .. } => "duplicate_param",
+            CalcError::UnknownUnit { .. } => "unknown_unit",
+            CalcError::UnknownCurrency { .. } => "unknown_currency",
+            CalcError::UnitMismatch { .. } => "unit_mismatch",
+            CalcError::ShapeMismatch { .. } => "shape_mismatch",
+            CalcError::Singular { .. } => "singular",
+            CalcError::Interrupted { .. } => "interrupted",
+            CalcError::StackUnderflow { .. } => "stack_underflow",
+        }
+    }
+
+    // The same error, re-pointed at `span`
+    pub fn at(mut self, span: Span) -> Self {
+        *self.span_mut() = span;
+        self
+    }
+
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            CalcError::InvalidNumber { span, .. }
+            | CalcError::UnknownOperator { span, .. }
+            | CalcError::UnexpectedToken { span, .. }
+            | CalcError::UnexpectedEnd { span, .. }
+            | CalcError::UnclosedParen { span }
+            | CalcError::DivisionByZero { span }
+            | CalcError::Overflow { span }
+            | CalcError::Underflow { span }
+            | CalcError::Domain { span, .. }
+            | CalcError::UndefinedVariable { span, .. }
+            | CalcError::UndefinedFunction { span, .. }
+            | CalcError::WrongArgCount { span, .. }
+            | CalcError::RecursionLimit { span, .. }
+            | CalcError::NestingLimit { span, .. }
+            | CalcError::NoHistory { span, .. }
+            | CalcError::Reserved { span, .. }
+            | CalcError::DuplicateParam { span, .. }
+            | CalcError::UnknownUnit { span, .. }
+            | CalcError::UnknownCurrency { span, .. }
+            | CalcError::UnitMismatch { span, .. }
+            | CalcError::ShapeMismatch { span, .. }
+            | CalcError::Singular { span, .. }
+            | CalcError::Interrupted { span }
+            | CalcError::StackUnderflow { span, .. } => span,
+        }
+    }
+
+    // Formats the error with the input line and a caret marker under the problem:
+    //
+    //   Error: Cannot divide by zero
+    //     | 1 / (2 - 2)
+    //     |     ^^^^^^^
+    pub fn render(&self, input: &str) -> String {
+        let span = self.span();
+        let width = span.end.saturating_sub(span.start).max(1);
+        format!(
+            "Error: {}\n  | {}\n  | {}{}",
+            self,
+            input,
+            " ".repeat(span.start),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalcError::InvalidNumber { text, .. } => write!(f, "Invalid number '{}'", text),
+            CalcError::UnknownOperator { found, .. } => write!(f, "Unknown operator '{}'", found),
+            CalcError::UnexpectedToken {
+                found,
+                expected: Some(expected),
+                ..
+            } => {
+                write!(f, "Expected {} but found {}", expected, found)
+            }
+            CalcError::UnexpectedToken {
+                found,
+                expected: None,
+                ..
+            } => {
+                write!(f, "Unexpected {}", found)
+            }
+            CalcError::UnexpectedEnd { expected, .. } => {
+                write!(f, "Expected {} but the input ended", expected)
+            }
+            CalcError::UnclosedParen { .. } => write!(f, "This '(' is never closed"),
+            CalcError::DivisionByZero { .. } => write!(f, "Cannot divide by zero"),
+            CalcError::Overflow { .. } => write!(f, "Result is too large (overflow)"),
+            CalcError::Underflow { .. } => {
+                write!(f, "Number is too small to tell from 0 (underflow)")
+            }
+            CalcError::Domain { message, .. } | CalcError::Singular { message, .. } => {
+                write!(f, "{}", message)
+            }
+            CalcError::UndefinedVariable { name, .. } => write!(f, "Undefined variable '{}'", name),
+            CalcError::UndefinedFunction { name, .. } => write!(f, "Undefined function '{}'", name),
+            CalcError::WrongArgCount {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "{}() takes {} argument(s) but {} were given",
+                name, expected, found
+            ),
+            CalcError::RecursionLimit { name, limit, .. } => {
+                write!(
+                    f,
+                    "Recursion limit of {} levels exceeded in {}()",
+                    limit, name
+                )
+            }
+            CalcError::NestingLimit { limit, .. } => {
+                write!(f, "Nested more than {} levels deep", limit)
+            }
+            CalcError::NoHistory { reference, .. } => {
+                write!(f, "No result in history for '{}'", reference)
+            }
+            CalcError::Reserved { name, .. } => {
+                write!(f, "'{}' is reserved and can't be redefined", name)
+            }
+            CalcError::DuplicateParam { name, .. } => write!(f, "Duplicate parameter '{}'", name),
+            CalcError::UnknownUnit { name, .. } => write!(f, "Unknown unit '{}'", name),
+            CalcError::UnknownCurrency { code, .. } => {
+                write!(f, "No exchange rate for {} in the rate table", code)
+            }
+            CalcError::UnitMismatch { left, right, .. } => {
+                write!(f, "Incompatible units: {} and {}", left, right)
+            }
+            CalcError::ShapeMismatch {
+                operation,
+                left,
+                right,
+                ..
+            } => write!(f, "Can't {} a {} and a {}", operation, left, right),
+            CalcError::Interrupted { .. } => write!(f, "Interrupted"),
+            CalcError::StackUnderflow {
+                word,
+                needed,
+                found,
+                ..
+            } => write!(
+                f,
+                "'{}' needs {} value(s) on the stack but there are {}",
+                word, needed, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CalcError {}
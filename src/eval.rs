@@ -0,0 +1,2065 @@
+// The evaluator walks the AST recursively and computes a value.
+// Each node type maps to one arm of the `match` below.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::angle::AngleMode;
+use crate::bigint::BigInt;
+use crate::builtins;
+use crate::complex::{Complex, ComplexForm};
+use crate::currency::Rates;
+use crate::decimal::{self, Decimal, DecimalContext, RoundingMode};
+use crate::error::{CalcError, Span};
+use crate::format::{Notation, NumberFormat};
+use crate::integer::{self, IntType, Radix};
+use crate::lexer;
+use crate::matrix;
+use crate::parser::{self, BinOp, BitOp, Expr, ExprKind, Statement};
+use crate::rational::Rational;
+use crate::units::{Quantity, UnitExpr};
+use crate::value::{NumberMode, Value};
+
+// Names that always mean something and can't be assigned to
+const RESERVED: &[&str] = &["ans", "in", "to", "xor"];
+
+// How deeply evaluation may recurse before we give up. Every bracket,
+// call, sign or power being worked out is one level, and so is each
+// user-function call, so `f(x) = f(x)` and `g(x) = abs(abs(g(x)))` both
+// stop here instead of overflowing the stack and crashing. A level takes
+// about 12 KiB of stack in a debug build, and 400 of them fit in the
+// 8 MiB of the main thread with room to spare.
+const MAX_DEPTH: usize = 400;
+
+// 20000! already has 77,338 digits; anything bigger is almost certainly a typo
+const MAX_FACTORIAL: u32 = 20_000;
+
+// 1 << 100000 has 30,103 digits, the same order as the factorial limit
+const MAX_SHIFT: i64 = 100_000;
+
+// A user-defined function like `f(x, y) = x^2 + y`
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Expr,
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}({}) = {}",
+            self.name,
+            self.params.join(", "),
+            self.body
+        )
+    }
+}
+
+// The bindings visible while evaluating one function body.
+// Scoping is lexical: a body sees its own parameters and the global
+// variables, but never the parameters of whoever called it.
+struct Frame<'a> {
+    locals: &'a [(String, Value)],
+    // Inside solve(), the locals that are its unknowns
+    unknowns: &'a [String],
+    // The user function whose body this is, for the recursion limit
+    function: Option<&'a str>,
+    // How many levels of evaluation are in progress; see MAX_DEPTH
+    depth: usize,
+}
+
+impl Frame<'_> {
+    const TOP: Frame<'static> = Frame {
+        locals: &[],
+        unknowns: &[],
+        function: None,
+        depth: 0,
+    };
+}
+
+// One line of the session: what was typed and what it produced.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub input: String,
+    pub value: Value,
+}
+
+// What running a statement produced, so the REPL can print it appropriately.
+#[derive(Debug)]
+pub enum Outcome {
+    Value(Value),
+    Assigned(String, Value),
+    Defined(Function),
+    // The unknowns of `solve(2x + y = 3, ...)` and their values. Nothing
+    // is assigned; see `solution_vector` for what goes in the history.
+    Solved(Vec<(String, Value)>),
+}
+
+// Session-wide switches that change how expressions are evaluated and shown
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Settings {
+    pub angle: AngleMode,
+    pub numbers: NumberMode,
+    // Precision and rounding used in decimal mode
+    pub decimal: DecimalContext,
+    // Show 7/2 as "3 1/2"
+    pub mixed_fractions: bool,
+    pub complex: ComplexForm,
+    // In integer mode, wrap around on overflow instead of failing
+    pub wrap: bool,
+    // The base whole numbers are shown in
+    pub radix: Radix,
+    // Read input as RPN (`3 4 +`) instead of infix
+    pub rpn: bool,
+    // Notation, thousands separators and decimal mark for results
+    pub output: NumberFormat,
+}
+
+impl Settings {
+    // A value as the user asked to see it
+    pub fn format(&self, value: &Value) -> String {
+        if let Value::Matrix(m) = value {
+            return m.layout(|elem| self.format(elem), self.output.mark.list_separator());
+        }
+        if self.radix != Radix::Decimal
+            && let Some(n) = value.as_whole()
+        {
+            // Negative numbers show their bits in integer mode, so -1 in i8 is 0xff
+            return match self.numbers {
+                NumberMode::Integer(int) => self.radix.format(&BigInt::from(int.pattern(&n))),
+                _ => self.radix.format(&n),
+            };
+        }
+        self.output.localize(&self.layout(value))
+    }
+
+    // The value in the chosen notation, with a plain '.' and no separators yet
+    fn layout(&self, value: &Value) -> String {
+        if let Some(text) = self.notation(value) {
+            return text;
+        }
+        match value {
+            Value::Rational(r) if self.mixed_fractions => r.to_mixed_string(),
+            Value::Complex(z) if self.complex == ComplexForm::Polar => {
+                z.to_polar_string(self.angle, |x| self.layout(&Value::Real(x)))
+            }
+            Value::Complex(z) => z.to_string_with(|x| self.layout(&Value::Real(x))),
+            // 5.3 km rather than 53/10 km: units and fractions don't mix well
+            Value::Quantity(q) => {
+                let value = match &q.value {
+                    Value::Rational(r) if !r.is_integer() => q
+                        .value
+                        .to_float()
+                        .map_or_else(|_| q.value.clone(), Value::Real),
+                    other => other.clone(),
+                };
+                match value {
+                    Value::Complex(_) => format!("({}) {}", self.layout(&value), q.unit),
+                    _ => format!("{} {}", self.layout(&value), q.unit),
+                }
+            }
+            _ => value.to_string(),
+        }
+    }
+
+    // A real number in fix, sig, sci or eng notation; None in auto notation,
+    // for other kinds of value, and in integer mode, where every result is
+    // a whole number anyway
+    fn notation(&self, value: &Value) -> Option<String> {
+        let notation = self.output.notation;
+        if notation == Notation::Auto || matches!(self.numbers, NumberMode::Integer(_)) {
+            return None;
+        }
+        let d = match value {
+            Value::Real(x) => Decimal::from_f64(*x)?,
+            Value::Decimal(d) => d.clone(),
+            Value::Rational(r) => {
+                // Work out enough digits for the notation, plus guard digits
+                // so the display rounding is the only one that matters
+                let rough = r.to_decimal(DecimalContext {
+                    precision: 3,
+                    ..self.decimal
+                });
+                let magnitude = if rough.is_zero() { 0 } else { rough.adjusted() };
+                r.to_decimal(DecimalContext {
+                    precision: notation.digits_needed(magnitude) + decimal::GUARD_DIGITS,
+                    rounding: RoundingMode::HalfEven,
+                })
+            }
+            _ => return None,
+        };
+        notation.layout(&d, self.decimal.rounding)
+    }
+}
+
+// Everything the evaluator needs to remember between lines.
+// `history[0]` is `$1`, `history[1]` is `$2`, and so on.
+// BTreeMap (instead of HashMap) keeps variables sorted for `vars`.
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    pub history: Vec<Entry>,
+    pub vars: BTreeMap<String, Value>,
+    pub funcs: BTreeMap<String, Function>,
+    pub settings: Settings,
+    // Exchange rates for currency units; empty until a rate file is loaded
+    pub rates: Rates,
+    // The RPN stack, bottom first; see rpn.rs
+    pub stack: Vec<Value>,
+    // Setting this from another thread makes a running evaluation fail
+    // with `Interrupted`, like the REPL's preview when a key is pressed
+    pub interrupt: Option<Arc<AtomicBool>>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The most recent result, available as `ans`
+    pub fn ans(&self) -> Option<&Value> {
+        self.history.last().map(|entry| &entry.value)
+    }
+
+    pub fn record(&mut self, input: &str, value: Value) -> usize {
+        self.history.push(Entry {
+            input: input.to_string(),
+            value,
+        });
+        self.history.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    // Removes a variable and/or function, returning false if neither existed
+    pub fn unset(&mut self, name: &str) -> bool {
+        let had_var = self.vars.remove(name).is_some();
+        let had_func = self.funcs.remove(name).is_some();
+        had_var || had_func
+    }
+
+    // Parses and runs one input line: an expression, assignment or definition
+    pub fn run(&mut self, input: &str) -> Result<Outcome, CalcError> {
+        let tokens = lexer::tokenize(input)?;
+        let statement = parser::parse(&tokens)?;
+        self.execute(&statement)
+    }
+
+    // Evaluates one expression against this context without changing it
+    pub fn evaluate(&self, input: &str) -> Result<Value, CalcError> {
+        let tokens = lexer::tokenize(input)?;
+        let expr = parser::parse_expression(&tokens)?;
+        self.eval(&expr)
+    }
+
+    pub fn execute(&mut self, statement: &Statement) -> Result<Outcome, CalcError> {
+        match statement {
+            Statement::Expr(expr) => match equations(expr) {
+                Some(equations) => Ok(Outcome::Solved(self.solve(equations, expr.span)?)),
+                None => Ok(Outcome::Value(self.eval(expr)?)),
+            },
+            Statement::Assign {
+                name,
+                name_span,
+                value,
+            } => {
+                if RESERVED.contains(&name.as_str()) || builtins::constant(name).is_some() {
+                    return Err(CalcError::Reserved {
+                        name: name.clone(),
+                        span: *name_span,
+                    });
+                }
+                let value = self.eval(value)?;
+                self.vars.insert(name.clone(), value.clone());
+                Ok(Outcome::Assigned(name.clone(), value))
+            }
+            Statement::Define {
+                name,
+                name_span,
+                params,
+                body,
+            } => {
+                if builtins::lookup(name).is_some() {
+                    return Err(CalcError::Reserved {
+                        name: name.clone(),
+                        span: *name_span,
+                    });
+                }
+                if let Some(param) = params.iter().find(|p| RESERVED.contains(&p.as_str())) {
+                    return Err(CalcError::Reserved {
+                        name: param.clone(),
+                        span: *name_span,
+                    });
+                }
+                let function = Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                };
+                self.funcs.insert(name.clone(), function.clone());
+                Ok(Outcome::Defined(function))
+            }
+        }
+    }
+
+    pub fn eval(&self, expr: &Expr) -> Result<Value, CalcError> {
+        self.eval_in(expr, &Frame::TOP)
+    }
+
+    // Solves linear equations like 2x + y = 3 and x + 3y = 5. The unknowns
+    // are the names that aren't bound yet: variables, constants and `ans`
+    // keep their values, so with a = 2, a x + y = 3 is linear in x and y.
+    pub fn solve(&self, equations: &[Expr], span: Span) -> Result<Vec<(String, Value)>, CalcError> {
+        self.solve_in(equations, &Frame::TOP, span)
+    }
+
+    // `solve`, where a function's parameters are bound as well
+    fn solve_in(
+        &self,
+        equations: &[Expr],
+        frame: &Frame,
+        span: Span,
+    ) -> Result<Vec<(String, Value)>, CalcError> {
+        let bound = |name: &str| {
+            name == "ans"
+                || frame.locals.iter().any(|(local, _)| local == name)
+                || self.vars.contains_key(name)
+                || builtins::constant(name).is_some()
+        };
+        // Every name that isn't bound, whether on its own or after a number
+        let mut names: Vec<String> = Vec::new();
+        for equation in equations {
+            if !matches!(equation.kind, ExprKind::Equation(..)) {
+                return Err(CalcError::Domain {
+                    message: format!(
+                        "solve() takes equations like x + y = 3, or a matrix and a vector (got {})",
+                        equation
+                    ),
+                    span: equation.span,
+                });
+            }
+            for name in equation.names_and_units() {
+                if !bound(&name) && !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        // A name after a number, like the s of 2s, is a unit unless it also
+        // appears on its own
+        let bare: Vec<String> = equations.iter().flat_map(Expr::names).collect();
+        let bare: Vec<String> = names
+            .iter()
+            .filter(|name| bare.contains(name))
+            .cloned()
+            .collect();
+        let rows = |unknowns: &[String], units: &Units| {
+            equations
+                .iter()
+                .zip(&units.equations)
+                .map(|(equation, unit)| {
+                    self.linear_row(equation, unknowns, units, unit.as_ref(), frame)
+                })
+                .collect::<Result<Vec<_>, CalcError>>()
+        };
+        let plain = |unknowns: &[String]| Units {
+            unknowns: vec![None; unknowns.len()],
+            equations: vec![None; equations.len()],
+        };
+        let (unknowns, units, rows) = match rows(&bare, &plain(&bare)) {
+            Err(mismatch @ CalcError::UnitMismatch { .. }) => {
+                // x = 2 km is about some number of km. Failing that, the
+                // equations have no meaning with units, like 2m + 3s = 5,
+                // and only then are m and s unknowns too.
+                let units = self.units_of(equations, &bare, frame);
+                match rows(&bare, &units) {
+                    Ok(rows) if !bare.is_empty() => (bare, units, rows),
+                    _ if bare.len() < names.len() => {
+                        let units = plain(&names);
+                        match rows(&names, &units) {
+                            Ok(rows) => (names, units, rows),
+                            // With unknowns of its own, a unit stays a unit
+                            Err(_) if !bare.is_empty() => return Err(mismatch),
+                            Err(error) => return Err(error),
+                        }
+                    }
+                    _ => return Err(mismatch),
+                }
+            }
+            _ if bare.is_empty() => return Err(self.nothing_to_solve(equations, span)),
+            rows => {
+                let units = plain(&bare);
+                (bare, units, rows?)
+            }
+        };
+        let solution =
+            matrix::solve_system(self, rows, &unknowns).map_err(|error| error.at(span))?;
+        // In integer mode the exact answer still has to be a whole number that fits
+        unknowns
+            .into_iter()
+            .zip(solution)
+            .zip(units.unknowns)
+            .map(|((name, value), unit)| {
+                let value = self.fit(value).map_err(|error| error.at(span))?;
+                Ok(match unit {
+                    Some(unit) => (name, with_unit(value, unit)),
+                    None => (name, value),
+                })
+            })
+            .collect()
+    }
+
+    // The unit each equation is in, from whichever side works out with
+    // every unknown at 0, and for each unknown the unit of the first
+    // equation it's in: in x + y = 3 m both are some number of metres
+    fn units_of(&self, equations: &[Expr], unknowns: &[String], frame: &Frame) -> Units {
+        let zeros: Vec<(String, Value)> = unknowns
+            .iter()
+            .map(|name| (name.clone(), self.whole_value(&BigInt::zero())))
+            .chain(frame.locals.iter().cloned())
+            .collect();
+        let frame = Frame {
+            locals: &zeros,
+            unknowns,
+            ..*frame
+        };
+        let equation_units: Vec<Option<UnitExpr>> = equations
+            .iter()
+            .map(|equation| {
+                let ExprKind::Equation(lhs, rhs) = &equation.kind else {
+                    return None;
+                };
+                [lhs, rhs]
+                    .into_iter()
+                    .find_map(|side| match self.eval_in(side, &frame) {
+                        Ok(Value::Quantity(q)) => Some(q.unit),
+                        _ => None,
+                    })
+            })
+            .collect();
+        let unknown_units = unknowns
+            .iter()
+            .map(|name| {
+                equations
+                    .iter()
+                    .zip(&equation_units)
+                    .find(|(equation, unit)| unit.is_some() && equation.names().contains(name))
+                    .and_then(|(_, unit)| unit.clone())
+            })
+            .collect();
+        Units {
+            unknowns: unknown_units,
+            equations: equation_units,
+        }
+    }
+
+    // Why solve() found no unknowns: most likely one that was assigned
+    // earlier, like x = 5, or one named like a unit in equations that
+    // make sense with it as one, like the s of 2s = 3s
+    fn nothing_to_solve(&self, equations: &[Expr], span: Span) -> CalcError {
+        let names: Vec<String> = equations.iter().flat_map(Expr::names).collect();
+        if self.ans().is_none() && names.iter().any(|name| name == "ans") {
+            return CalcError::NoHistory {
+                reference: "ans".to_string(),
+                span,
+            };
+        }
+        let assigned = equations
+            .iter()
+            .flat_map(Expr::names)
+            .find(|name| self.vars.contains_key(name));
+        let unit = equations.iter().flat_map(Expr::quantity_units).next();
+        let message = match (assigned, unit) {
+            (Some(name), _) => format!(
+                "There is nothing to solve for: {} already has a value ('unset {}' to solve for it)",
+                name, name
+            ),
+            (None, Some(name)) => format!(
+                "There is nothing to solve for: {} after a number is a unit (write '* {}' to solve for it)",
+                name, name
+            ),
+            (None, None) => {
+                "There is nothing to solve for: the equations have no unknowns".to_string()
+            }
+        };
+        CalcError::Domain { message, span }
+    }
+
+    // The row [a1, ..., an | b] of an equation a1 x1 + ... + an xn = b. The
+    // coefficients come from trying the unknowns at 0 and at 1 one at a time;
+    // one more point checks that the equation really is linear. With units,
+    // each x is a number of its unit and the row is in the equation's `unit`.
+    fn linear_row(
+        &self,
+        equation: &Expr,
+        unknowns: &[String],
+        units: &Units,
+        unit: Option<&UnitExpr>,
+        frame: &Frame,
+    ) -> Result<Vec<Value>, CalcError> {
+        let ExprKind::Equation(lhs, rhs) = &equation.kind else {
+            unreachable!("checked by solve()");
+        };
+        let not_linear = || CalcError::Domain {
+            message: format!(
+                "solve() only handles linear equations, and {} isn't one",
+                equation
+            ),
+            span: equation.span,
+        };
+        // lhs - rhs with each unknown x_i set to at(i)
+        let difference = |at: &dyn Fn(usize) -> i64| {
+            let locals: Vec<(String, Value)> = (0..unknowns.len())
+                .map(|i| {
+                    let value = self.whole_value(&BigInt::from(at(i)));
+                    let value = match &units.unknowns[i] {
+                        Some(unit) => with_unit(value, unit.clone()),
+                        None => value,
+                    };
+                    (unknowns[i].clone(), value)
+                })
+                .chain(frame.locals.iter().cloned())
+                .collect();
+            let frame = Frame {
+                locals: &locals,
+                unknowns,
+                function: frame.function,
+                depth: frame.depth,
+            };
+            let sides = self
+                .eval_in(lhs, &frame)
+                .and_then(|a| Ok((a, self.eval_in(rhs, &frame)?)));
+            let difference = sides.and_then(|(a, b)| self.operate(BinOp::Sub, a, b));
+            let difference = match unit {
+                Some(unit) => difference.and_then(|d| {
+                    let one = with_unit(self.whole_value(&BigInt::one()), unit.clone());
+                    self.operate(BinOp::Div, d, one)
+                }),
+                None => difference,
+            };
+            match difference {
+                Ok(Value::Matrix(m)) => Err(CalcError::Domain {
+                    message: format!(
+                        "solve() needs equations between numbers (got a {} in {})",
+                        m.shape(),
+                        equation
+                    ),
+                    span: equation.span,
+                }),
+                Ok(value) => Ok(value),
+                // 1/x = 2 can't be tried at x = 0
+                Err(CalcError::DivisionByZero { .. }) => Err(not_linear()),
+                Err(error) => Err(error.at(equation.span)),
+            }
+        };
+        let constant = difference(&|_| 0)?;
+        let mut row = Vec::with_capacity(unknowns.len() + 1);
+        for j in 0..unknowns.len() {
+            let at = difference(&|i| i64::from(i == j))?;
+            row.push(self.operate(BinOp::Sub, at, constant.clone())?);
+        }
+        let check = |i: usize| i as i64 + 2;
+        let mut predicted = constant.clone();
+        for (i, coefficient) in row.iter().enumerate() {
+            let x = self.whole_value(&BigInt::from(check(i)));
+            let term = self.operate(BinOp::Mul, coefficient.clone(), x)?;
+            predicted = self.operate(BinOp::Add, predicted, term)?;
+        }
+        let actual = difference(&check)?;
+        let scale = matrix::magnitude(&actual).max(matrix::magnitude(&predicted));
+        let error = self.operate(BinOp::Sub, actual, predicted)?;
+        if !matrix::negligible(&error, matrix::tolerance(self, scale)) {
+            return Err(not_linear());
+        }
+        row.push(negate(constant));
+        Ok(row)
+    }
+
+    fn eval_in(&self, expr: &Expr, frame: &Frame) -> Result<Value, CalcError> {
+        if frame.depth >= MAX_DEPTH {
+            return Err(too_deep(frame, expr.span));
+        }
+        let frame = &Frame {
+            depth: frame.depth + 1,
+            ..*frame
+        };
+        // A chain like 1 + 2 + ... + n is as deep as it is long, so it is
+        // worked out with a loop: its first operand, then one link at a time
+        let (links, first) = expr.chain();
+        let mut value = self.step(first, || self.eval_node(first, frame))?;
+        for link in links.into_iter().rev() {
+            value = self.step(link, || self.eval_link(link, value, frame))?;
+        }
+        Ok(value)
+    }
+
+    // Works out one node, unless the user has interrupted
+    fn step(
+        &self,
+        expr: &Expr,
+        eval: impl FnOnce() -> Result<Value, CalcError>,
+    ) -> Result<Value, CalcError> {
+        self.check_interrupt()
+            .map_err(|error| error.at(expr.span))?;
+        let value = eval()?;
+        // In integer mode every intermediate result is a whole number that fits
+        self.fit(value).map_err(|error| error.at(expr.span))
+    }
+
+    // Fails once `interrupt` has been set; long loops call this now and then
+    pub fn check_interrupt(&self) -> Result<(), CalcError> {
+        match &self.interrupt {
+            Some(flag) if flag.load(Ordering::Relaxed) => Err(CalcError::Interrupted {
+                span: Span::default(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // The operations below work on values that are already worked out, so
+    // front ends that don't parse infix, like the RPN stack, can share them.
+    // Errors carry an empty span for the caller to fill in, and results
+    // still need `fit` in integer mode.
+
+    pub fn operate(&self, op: BinOp, a: Value, b: Value) -> Result<Value, CalcError> {
+        if matches!(a, Value::Matrix(_)) || matches!(b, Value::Matrix(_)) {
+            matrix::binary(self, op, a, b)
+        } else if a.as_quantity().is_some() || b.as_quantity().is_some() {
+            self.quantity_binary(op, a, b)
+        } else {
+            self.scalar_binary(op, &a, &b)
+        }
+    }
+
+    // ~x
+    pub fn bit_not(&self, value: &Value) -> Result<Value, CalcError> {
+        let n = whole(value, "~")?;
+        Ok(match self.settings.numbers {
+            NumberMode::Integer(int) => {
+                Value::Rational(Rational::from(int.from_pattern(!int.pattern(&n))))
+            }
+            // -x - 1, what flipping every bit means with unlimited sign bits
+            _ => self.whole_value(&(&-&n - &BigInt::one())),
+        })
+    }
+
+    pub fn factorial(&self, value: &Value) -> Result<Value, CalcError> {
+        factorial(value, self.settings.decimal)
+    }
+
+    // Calls a built-in or user-defined function; `span` is where errors point
+    pub fn call_values(&self, name: &str, args: &[Value], span: Span) -> Result<Value, CalcError> {
+        self.invoke(name, args, &Frame::TOP, span)
+    }
+
+    fn eval_node(&self, expr: &Expr, frame: &Frame) -> Result<Value, CalcError> {
+        match &expr.kind {
+            ExprKind::Number(text) => self.number(text).map_err(|error| error.at(expr.span)),
+            // An explicit unit overrides the mode: `30deg` is 0.5236 in rad mode
+            ExprKind::Angle(text, unit) => {
+                let angle = self.settings.angle;
+                let value = self.number(text).map_err(|error| error.at(expr.span))?;
+                Ok(match value {
+                    exact @ Value::Rational(_) if angle == *unit => exact,
+                    Value::Decimal(d) => {
+                        Value::Decimal(angle.convert_decimal(&d, *unit, self.settings.decimal))
+                    }
+                    // A plain float, or a fraction that has to become one
+                    other => {
+                        let x = other.to_float().map_err(|error| error.at(expr.span))?;
+                        Value::Real(angle.convert(x, *unit))
+                    }
+                })
+            }
+            ExprKind::Imaginary(text) => {
+                let coefficient = if text.is_empty() {
+                    1.0
+                } else {
+                    let value = self.number(text).map_err(|error| error.at(expr.span))?;
+                    value.to_f64()
+                };
+                // A decimal like 1e400 is fine until it has to be an f64
+                if !coefficient.is_finite() {
+                    return Err(CalcError::Overflow { span: expr.span });
+                }
+                if coefficient == 0.0 && nonzero_literal(text) {
+                    return Err(CalcError::Underflow { span: expr.span });
+                }
+                Ok(Value::complex(Complex::new(0.0, coefficient)))
+            }
+            ExprKind::Ident(name) => self.lookup(name, frame, expr.span),
+            ExprKind::HistoryRef(index) => index
+                .checked_sub(1)
+                .and_then(|i| self.history.get(i))
+                .map(|entry| entry.value.clone())
+                .ok_or_else(|| CalcError::NoHistory {
+                    reference: format!("${}", index),
+                    span: expr.span,
+                }),
+            ExprKind::Call(name, args) => match equations(expr) {
+                Some(equations) => self
+                    .solve_in(equations, frame, expr.span)
+                    .map(|solution| solution_vector(&solution)),
+                None => self.call(name, args, frame, expr.span),
+            },
+            ExprKind::Quantity(number, spec) => {
+                let value = self.eval_in(number, frame)?;
+                if spec.iter().any(|(name, _)| frame.unknowns.contains(name)) {
+                    return self.eval_unknowns(value, spec, frame, expr.span);
+                }
+                self.apply_unit(value, spec, expr.span)
+            }
+            ExprKind::Neg(operand) => {
+                // -128 fits in i8 although 128 doesn't, so a negated literal
+                // is only checked against integer mode once it has its sign
+                let value = match operand.kind {
+                    ExprKind::Number(_) => self.eval_node(operand, frame)?,
+                    _ => self.eval_in(operand, frame)?,
+                };
+                Ok(negate(value))
+            }
+            ExprKind::BitNot(operand) => {
+                let value = self.eval_in(operand, frame)?;
+                self.bit_not(&value).map_err(|error| error.at(expr.span))
+            }
+            ExprKind::Factorial(operand) => {
+                let value = self.eval_in(operand, frame)?;
+                self.factorial(&value).map_err(|error| error.at(expr.span))
+            }
+            ExprKind::Matrix(elements) => {
+                let values = elements
+                    .iter()
+                    .map(|element| self.eval_in(element, frame))
+                    .collect::<Result<Vec<Value>, CalcError>>()?;
+                matrix::from_elements(values).map_err(|error| error.at(expr.span))
+            }
+            ExprKind::Equation(..) => Err(CalcError::Domain {
+                message: format!("An equation like {} only works inside solve()", expr),
+                span: expr.span,
+            }),
+            ExprKind::Binary(..)
+            | ExprKind::Bitwise(..)
+            | ExprKind::Elementwise(..)
+            | ExprKind::Convert(..) => unreachable!("eval_in works out chains"),
+        }
+    }
+
+    // One link of a chain, given the value of everything to its left
+    fn eval_link(&self, link: &Expr, a: Value, frame: &Frame) -> Result<Value, CalcError> {
+        match &link.kind {
+            ExprKind::Binary(op, _, rhs) => {
+                let b = self.eval_in(rhs, frame)?;
+                self.operate(*op, a, b).map_err(|error| match error {
+                    // Point at the divisor rather than the whole expression
+                    CalcError::DivisionByZero { .. } => {
+                        CalcError::DivisionByZero { span: rhs.span }
+                    }
+                    other => other.at(link.span),
+                })
+            }
+            ExprKind::Bitwise(op, _, rhs) => {
+                let b = self.eval_in(rhs, frame)?;
+                self.bitwise(*op, &a, &b)
+                    .map_err(|error| error.at(link.span))
+            }
+            ExprKind::Elementwise(op, _, rhs) => {
+                let b = self.eval_in(rhs, frame)?;
+                matrix::elementwise(self, *op, a, b).map_err(|error| match error {
+                    CalcError::DivisionByZero { .. } => {
+                        CalcError::DivisionByZero { span: rhs.span }
+                    }
+                    other => other.at(link.span),
+                })
+            }
+            ExprKind::Convert(_, spec) => {
+                let target = self.resolve_unit(spec, link.span)?;
+                self.convert(a, &target)
+                    .map_err(|error| error.at(link.span))
+            }
+            _ => unreachable!("only chain links have a left operand"),
+        }
+    }
+
+    // An operation on two plain numbers, in the session's number mode
+    fn scalar_binary(&self, op: BinOp, a: &Value, b: &Value) -> Result<Value, CalcError> {
+        match (self.settings.numbers, a, b) {
+            (NumberMode::Integer(int), _, _) => self.integer_binary(int, op, a, b),
+            _ if a.is_complex() || b.is_complex() || complex_power(op, a, b) => {
+                complex_binary(op, a.to_complex(), b.to_complex())
+            }
+            (NumberMode::Float, Value::Rational(a), Value::Rational(b)) => {
+                rational_binary(op, a, b)
+            }
+            (NumberMode::Float, _, _) => binary(op, a.to_float()?, b.to_float()?).map(Value::Real),
+            (NumberMode::Decimal, _, _) => {
+                // Exact fractions carry guard digits into the operation,
+                // so a stored 7/3 times 3 rounds back to 7
+                let ctx = self.settings.decimal;
+                let work = ctx.extended(decimal::GUARD_DIGITS);
+                let (a, b) = (a.to_decimal(work), b.to_decimal(work));
+                decimal_binary(op, &a, &b, ctx).map(Value::Decimal)
+            }
+        }
+    }
+
+    // Arithmetic on fixed-width integers: division truncates like in Rust
+    // and C, and a result that doesn't fit is an error or wraps around
+    fn integer_binary(
+        &self,
+        int: IntType,
+        op: BinOp,
+        a: &Value,
+        b: &Value,
+    ) -> Result<Value, CalcError> {
+        let span = Span::default();
+        let label = int.to_string();
+        let (x, y) = (whole(a, &label)?, whole(b, &label)?);
+        if matches!(op, BinOp::Div | BinOp::FloorDiv | BinOp::Mod) && y.is_zero() {
+            return Err(CalcError::DivisionByZero { span });
+        }
+        let result = match op {
+            BinOp::Add => &x + &y,
+            BinOp::Sub => &x - &y,
+            BinOp::Mul => &x * &y,
+            BinOp::Div => x.div_rem(&y).0,
+            BinOp::FloorDiv => x.div_floor(&y),
+            BinOp::Mod => &x - &(&y * &x.div_floor(&y)),
+            BinOp::Pow => {
+                let exp = y
+                    .to_i64()
+                    .and_then(|exp| u32::try_from(exp).ok())
+                    .ok_or_else(|| CalcError::Domain {
+                        message: format!("{} powers need a whole exponent >= 0 (got {})", int, y),
+                        span,
+                    })?;
+                if self.settings.wrap {
+                    int.from_pattern(int.pattern(&x).wrapping_pow(exp))
+                } else if x.abs() > BigInt::one() && exp > int.bits {
+                    // Far too big to fit; no need to work it out first
+                    return Err(out_of_range(int, &format!("{}^{}", x, y)));
+                } else {
+                    x.pow(exp)
+                }
+            }
+        };
+        self.fit_whole(int, result)
+    }
+
+    // &, |, xor, << and >>. In integer mode they work on the type's bits;
+    // otherwise numbers act as if they had unlimited sign bits, like
+    // Python's integers, so -1 & 0xff is 0xff.
+    pub fn bitwise(&self, op: BitOp, a: &Value, b: &Value) -> Result<Value, CalcError> {
+        let span = Span::default();
+        let (x, y) = (whole(a, op.symbol())?, whole(b, op.symbol())?);
+        let shift = |limit: i64| match y.to_i64() {
+            Some(amount) if (0..limit).contains(&amount) => Ok(amount as u32),
+            // Like Rust's wrapping_shl, wrapping takes the amount modulo the width
+            Some(amount) if self.settings.wrap && limit <= 128 => {
+                Ok(amount.rem_euclid(limit) as u32)
+            }
+            _ => Err(CalcError::Domain {
+                message: format!("Shifts go from 0 to {} bits (got {})", limit - 1, y),
+                span,
+            }),
+        };
+        let result = match (self.settings.numbers, op) {
+            (NumberMode::Integer(int), BitOp::ShiftLeft) => {
+                // Bits shifted out of the top are lost, as in hardware
+                int.from_pattern(int.pattern(&x) << shift(int.bits as i64)?)
+            }
+            (NumberMode::Integer(int), BitOp::ShiftRight) => {
+                x.div_floor(&BigInt::from(1u128 << shift(int.bits as i64)?))
+            }
+            (NumberMode::Integer(int), _) => {
+                let (p, q) = (int.pattern(&x), int.pattern(&y));
+                int.from_pattern(match op {
+                    BitOp::And => p & q,
+                    BitOp::Or => p | q,
+                    _ => p ^ q,
+                })
+            }
+            (_, BitOp::ShiftLeft | BitOp::ShiftRight) => {
+                let scale = BigInt::from(2u64).pow(shift(MAX_SHIFT + 1)?);
+                if op == BitOp::ShiftLeft {
+                    &x * &scale
+                } else {
+                    x.div_floor(&scale)
+                }
+            }
+            (_, BitOp::And) => x.bitwise(&y, |p, q| p & q),
+            (_, BitOp::Or) => x.bitwise(&y, |p, q| p | q),
+            _ => x.bitwise(&y, |p, q| p ^ q),
+        };
+        Ok(self.whole_value(&result))
+    }
+
+    // A whole-number result in the session's kind of number
+    pub fn whole_value(&self, n: &BigInt) -> Value {
+        match self.settings.numbers {
+            NumberMode::Decimal => {
+                Value::Decimal(Decimal::from(n.clone()).round(self.settings.decimal))
+            }
+            _ => Value::Rational(Rational::from(n.clone())),
+        }
+    }
+
+    // Checks a value against integer mode; other modes take it as it is,
+    // except that the f64 -0 of round(-0.4) or -0.5 * 0 becomes plain 0.
+    // Units are left alone: they are unusual enough in integer mode.
+    pub fn fit(&self, value: Value) -> Result<Value, CalcError> {
+        match (self.settings.numbers, value) {
+            (_, Value::Real(0.0)) => Ok(Value::Real(0.0)),
+            (NumberMode::Integer(_), Value::Matrix(m)) => {
+                Ok(m.try_map(|elem| self.fit(elem))?.into())
+            }
+            (
+                NumberMode::Integer(int),
+                value @ (Value::Real(_)
+                | Value::Decimal(_)
+                | Value::Rational(_)
+                | Value::Complex(_)),
+            ) => {
+                let n = whole(&value, &int.to_string())?;
+                self.fit_whole(int, n)
+            }
+            (_, value) => Ok(value),
+        }
+    }
+
+    fn fit_whole(&self, int: IntType, n: BigInt) -> Result<Value, CalcError> {
+        let n = if int.contains(&n) {
+            n
+        } else if self.settings.wrap {
+            int.wrap(&n)
+        } else {
+            return Err(out_of_range(int, &n.to_string()));
+        };
+        Ok(Value::Rational(Rational::from(n)))
+    }
+
+    // An operation where at least one side has a unit. Conversion ratios
+    // are exact fractions, so they go through `scalar_binary` like any
+    // other number and keep results exact where the mode allows.
+    fn quantity_binary(&self, op: BinOp, a: Value, b: Value) -> Result<Value, CalcError> {
+        let span = Span::default();
+        let (x, x_unit) = split_unit(a);
+        let (y, y_unit) = split_unit(b);
+        match op {
+            // 5 km + 300 m is 5.3 km: the right side is converted to the left's unit
+            BinOp::Add | BinOp::Sub | BinOp::Mod => {
+                if x_unit.dimension() != y_unit.dimension() {
+                    return Err(CalcError::UnitMismatch {
+                        left: x_unit.describe(),
+                        right: y_unit.describe(),
+                        span,
+                    });
+                }
+                let ratio = self.ratio(&y_unit, &x_unit)?;
+                let y = self.scalar_binary(BinOp::Mul, &y, &Value::Rational(ratio))?;
+                let result = self.scalar_binary(op, &x, &y)?;
+                // 30 degC - 20 degC is a difference of 10 K, not a temperature of 10 degC
+                if op == BinOp::Sub && x_unit.offset().is_some() && y_unit.offset().is_some() {
+                    let kelvin = Value::Rational(self.factor(&x_unit)?);
+                    let result = self.scalar_binary(BinOp::Mul, &result, &kelvin)?;
+                    return Ok(with_unit(result, UnitExpr::kelvin()));
+                }
+                Ok(with_unit(result, x_unit))
+            }
+            // 100 km / 2 h is 50 km/h, and 1 h / 30 min is 2
+            BinOp::Mul | BinOp::Div | BinOp::FloorDiv => {
+                let times = if op == BinOp::Mul { 1 } else { -1 };
+                let mut unit = x_unit.combine(&y_unit, times);
+                let mut ratio = if times == 1 {
+                    &self.factor(&x_unit)? * &self.factor(&y_unit)?
+                } else {
+                    self.ratio(&x_unit, &y_unit)?
+                };
+                if unit.dimension().is_none() {
+                    unit = UnitExpr::default();
+                } else {
+                    ratio = ratio
+                        .checked_div(&self.factor(&unit)?)
+                        .expect("nonzero unit factor");
+                }
+                // Scaled before dividing, so 7 km // 500 m floors 14 rather than 7 // 500
+                let x = self.scalar_binary(BinOp::Mul, &x, &Value::Rational(ratio))?;
+                Ok(with_unit(self.scalar_binary(op, &x, &y)?, unit))
+            }
+            BinOp::Pow => {
+                if !y_unit.is_empty() {
+                    return Err(CalcError::Domain {
+                        message: format!("A power must be a plain number (got {} {})", y, y_unit),
+                        span,
+                    });
+                }
+                let Some(unit) = x_unit.powf(y.to_f64()) else {
+                    return Err(CalcError::Domain {
+                        message: format!("({})^{} has no whole-number unit", x_unit, y),
+                        span,
+                    });
+                };
+                Ok(with_unit(self.scalar_binary(op, &x, &y)?, unit))
+            }
+        }
+    }
+
+    // `value in unit`: 25 degC in degF is 77. Temperatures with an offset
+    // go through kelvin; everything else just scales.
+    pub fn convert(&self, value: Value, target: &UnitExpr) -> Result<Value, CalcError> {
+        // [1 km, 2 km] in m converts each element
+        if let Value::Matrix(m) = value {
+            return Ok(m.try_map(|elem| self.convert(elem, target))?.into());
+        }
+        let (x, unit) = split_unit(value);
+        if unit.dimension() != target.dimension() {
+            return Err(CalcError::UnitMismatch {
+                left: unit.describe(),
+                right: target.describe(),
+                span: Span::default(),
+            });
+        }
+        let zero = || Rational::from(BigInt::zero());
+        let ratio = self.ratio(&unit, target)?;
+        let from = Value::Rational(unit.offset().unwrap_or_else(zero));
+        let to = Value::Rational(target.offset().unwrap_or_else(zero));
+        let x = self.scalar_binary(BinOp::Add, &x, &from)?;
+        let x = self.scalar_binary(BinOp::Mul, &x, &Value::Rational(ratio))?;
+        let x = self.scalar_binary(BinOp::Sub, &x, &to)?;
+        Ok(with_unit(x, target.clone()))
+    }
+
+    // The size of a unit; only fails for a currency the rate table lacks
+    fn factor(&self, unit: &UnitExpr) -> Result<Rational, CalcError> {
+        unit.factor(&self.rates)
+            .map_err(|code| self.missing_rate(code))
+    }
+
+    // How many of `to` make one `from`
+    fn ratio(&self, from: &UnitExpr, to: &UnitExpr) -> Result<Rational, CalcError> {
+        Ok(self
+            .factor(from)?
+            .checked_div(&self.factor(to)?)
+            .expect("nonzero unit factor"))
+    }
+
+    fn missing_rate(&self, code: String) -> CalcError {
+        let span = Span::default();
+        if self.rates.is_empty() {
+            CalcError::Domain {
+                message: format!(
+                    "No exchange rates are loaded, so {} can't be used (see --rates or the 'rates' command)",
+                    code
+                ),
+                span,
+            }
+        } else {
+            CalcError::UnknownCurrency { code, span }
+        }
+    }
+
+    // A quantity in solve() that names one of its unknowns: with s and t
+    // unknown, 2s + 3t means 2 times s plus 3 times t, not seconds and tonnes
+    fn eval_unknowns(
+        &self,
+        mut value: Value,
+        spec: &[(String, i32)],
+        frame: &Frame,
+        span: Span,
+    ) -> Result<Value, CalcError> {
+        let (unknowns, units): (Vec<_>, Vec<_>) = spec
+            .iter()
+            .cloned()
+            .partition(|(name, _)| frame.unknowns.contains(name));
+        for (name, power) in unknowns {
+            let x = self.lookup(&name, frame, span)?;
+            let x = self.operate(
+                BinOp::Pow,
+                x,
+                self.whole_value(&BigInt::from(i64::from(power))),
+            )?;
+            value = self.operate(BinOp::Mul, value, x)?;
+        }
+        if units.is_empty() {
+            return Ok(value);
+        }
+        let unit = self.resolve_unit(&units, span)?;
+        Ok(Value::Quantity(Box::new(Quantity { value, unit })))
+    }
+
+    // A unit as the parser wrote it down; currencies must be in the rate table
+    // `value unit`, as in 5 km, and in RPN `5 km` too
+    pub fn apply_unit(
+        &self,
+        value: Value,
+        spec: &[(String, i32)],
+        span: Span,
+    ) -> Result<Value, CalcError> {
+        let unit = self.resolve_unit(spec, span)?;
+        match value {
+            // x km with x = 2 m, [1, 2] km or (2 + 3i) km: times one of the unit
+            Value::Quantity(_) | Value::Matrix(_) | Value::Complex(_) => {
+                let one = with_unit(self.whole_value(&BigInt::one()), unit);
+                self.operate(BinOp::Mul, value, one)
+                    .map_err(|error| error.at(span))
+            }
+            value => Ok(Value::Quantity(Box::new(Quantity { value, unit }))),
+        }
+    }
+
+    fn resolve_unit(&self, spec: &[(String, i32)], span: Span) -> Result<UnitExpr, CalcError> {
+        let unit = UnitExpr::lookup(spec).map_err(|name| CalcError::UnknownUnit { name, span })?;
+        if let Some(code) = unit
+            .currencies()
+            .into_iter()
+            .find(|code| !self.rates.contains(code))
+        {
+            return Err(self.missing_rate(code).at(span));
+        }
+        Ok(unit)
+    }
+
+    // Number literals are kept as text until now, so that in decimal
+    // mode 0.1 is exactly 0.1 rather than the nearest f64. Like any other
+    // result, a literal is rounded to the session's precision.
+    // In float mode whole numbers are exact, so 1/3 can stay a fraction,
+    // and a literal too big for an f64, like 1e400, is an overflow.
+    fn number(&self, text: &str) -> Result<Value, CalcError> {
+        if let Some(n) = integer::parse_literal(text) {
+            return Ok(self.whole_value(&n));
+        }
+        // The lexer only accepts text that parses both ways
+        Ok(match self.settings.numbers {
+            NumberMode::Float | NumberMode::Integer(_) => match BigInt::parse(text) {
+                Some(n) => Value::Rational(Rational::from(n)),
+                None => match text.parse::<f64>() {
+                    // 1e-400 is below the smallest f64, and would quietly be 0
+                    Ok(x) if x == 0.0 && nonzero_literal(text) => {
+                        return Err(CalcError::Underflow {
+                            span: Span::default(),
+                        });
+                    }
+                    Ok(x) if x.is_finite() => Value::Real(x),
+                    _ => {
+                        return Err(CalcError::Overflow {
+                            span: Span::default(),
+                        });
+                    }
+                },
+            },
+            NumberMode::Decimal => {
+                let exact = Decimal::parse(text).unwrap_or_default();
+                Value::Decimal(exact.round(self.settings.decimal))
+            }
+        })
+    }
+
+    fn call(
+        &self,
+        name: &str,
+        args: &[Expr],
+        frame: &Frame,
+        span: Span,
+    ) -> Result<Value, CalcError> {
+        // Arguments are evaluated in the caller's frame
+        let values = args
+            .iter()
+            .map(|arg| self.eval_in(arg, frame))
+            .collect::<Result<Vec<Value>, CalcError>>()?;
+        self.invoke(name, &values, frame, span)
+    }
+
+    fn invoke(
+        &self,
+        name: &str,
+        args: &[Value],
+        frame: &Frame,
+        span: Span,
+    ) -> Result<Value, CalcError> {
+        if let Some(builtin) = builtins::lookup(name) {
+            return builtin.call(args, self, span);
+        }
+        let Some(function) = self.funcs.get(name) else {
+            return Err(CalcError::UndefinedFunction {
+                name: name.to_string(),
+                span,
+            });
+        };
+        if args.len() != function.params.len() {
+            return Err(CalcError::WrongArgCount {
+                name: name.to_string(),
+                expected: function.params.len().to_string(),
+                found: args.len(),
+                span,
+            });
+        }
+        let locals: Vec<(String, Value)> = function
+            .params
+            .iter()
+            .cloned()
+            .zip(args.iter().cloned())
+            .collect();
+        let inner = Frame {
+            locals: &locals,
+            unknowns: &[],
+            function: Some(name),
+            depth: frame.depth + 1,
+        };
+        // Errors inside the body have spans from the definition line, which isn't
+        // on screen any more, so re-point them at this call.
+        self.eval_in(&function.body, &inner)
+            .map_err(|error| error.at(span))
+    }
+
+    fn lookup(&self, name: &str, frame: &Frame, span: Span) -> Result<Value, CalcError> {
+        if let Some((_, value)) = frame.locals.iter().find(|(param, _)| param == name) {
+            return Ok(value.clone());
+        }
+        if name == "ans" {
+            return self.ans().cloned().ok_or_else(|| CalcError::NoHistory {
+                reference: "ans".to_string(),
+                span,
+            });
+        }
+        self.vars
+            .get(name)
+            .cloned()
+            .or_else(|| builtins::constant(name).map(|c| c.value(&self.settings)))
+            // A bare unit is one of it, so `km` alone is 1 km and `2 * km` is 2 km
+            .or_else(|| {
+                let unit = self.resolve_unit(&[(name.to_string(), 1)], span).ok()?;
+                Some(with_unit(self.whole_value(&BigInt::one()), unit))
+            })
+            .ok_or_else(|| CalcError::UndefinedVariable {
+                name: name.to_string(),
+                span,
+            })
+    }
+}
+
+// The values of a solution in the order of its unknowns, which is what
+// solve() gives inside an expression and what a solved line adds to history
+pub fn solution_vector(solution: &[(String, Value)]) -> Value {
+    let values = solution.iter().map(|(_, value)| value.clone()).collect();
+    Value::from(matrix::Matrix::vector(values))
+}
+
+// The equations of a call like `solve(2x + y = 3, x + 3y = 5)`; None for
+// any other expression, including solve() on a matrix and a vector
+pub fn equations(expr: &Expr) -> Option<&[Expr]> {
+    match &expr.kind {
+        ExprKind::Call(name, args)
+            if name == "solve"
+                && args
+                    .iter()
+                    .any(|arg| matches!(arg.kind, ExprKind::Equation(..))) =>
+        {
+            Some(args)
+        }
+        _ => None,
+    }
+}
+
+// The error for going past MAX_DEPTH. Brackets alone can't get that deep
+// because the parser stops them first, so it is almost always a function that
+// calls itself.
+fn too_deep(frame: &Frame, span: Span) -> CalcError {
+    match frame.function {
+        Some(name) => CalcError::RecursionLimit {
+            name: name.to_string(),
+            limit: MAX_DEPTH,
+            span,
+        },
+        None => CalcError::NestingLimit {
+            limit: MAX_DEPTH,
+            span,
+        },
+    }
+}
+
+// The whole number `value` is, for an operator or mode that needs one
+fn whole(value: &Value, what: &str) -> Result<BigInt, CalcError> {
+    value.as_whole().ok_or_else(|| CalcError::Domain {
+        message: format!("{} only works with whole numbers (got {})", what, value),
+        span: Span::default(),
+    })
+}
+
+// Whether a number literal like 0.5e-3 has a digit other than 0 before its
+// exponent, so it only reads as 0 when it underflows
+fn nonzero_literal(text: &str) -> bool {
+    text.chars()
+        .take_while(|c| !matches!(c, 'e' | 'E'))
+        .any(|c| matches!(c, '1'..='9'))
+}
+
+fn out_of_range(int: IntType, result: &str) -> CalcError {
+    CalcError::Domain {
+        message: format!(
+            "{} doesn't fit in {} ({} to {}); use 'overflow wrap' to wrap around",
+            result,
+            int,
+            int.min(),
+            int.max()
+        ),
+        span: Span::default(),
+    }
+}
+
+pub fn negate(value: Value) -> Value {
+    match value {
+        Value::Real(x) => Value::Real(-x),
+        Value::Decimal(d) => Value::Decimal(-d),
+        Value::Rational(r) => Value::Rational(-&r),
+        Value::Complex(z) => Value::Complex(-z),
+        Value::Quantity(mut q) => {
+            q.value = negate(q.value);
+            Value::Quantity(q)
+        }
+        Value::Matrix(m) => m.map(negate).into(),
+    }
+}
+
+// A value's number and unit; plain numbers have an empty unit
+fn split_unit(value: Value) -> (Value, UnitExpr) {
+    match value {
+        Value::Quantity(q) => (q.value, q.unit),
+        other => (other, UnitExpr::default()),
+    }
+}
+
+// The opposite of `split_unit`: units that have cancelled out, like the
+// m^0 of (2 m)^0, leave a plain number
+fn with_unit(value: Value, unit: UnitExpr) -> Value {
+    if unit.dimension().is_none() {
+        value
+    } else {
+        Value::Quantity(Box::new(Quantity { value, unit }))
+    }
+}
+
+// The units solve() tries its unknowns in, None for plain numbers
+struct Units {
+    unknowns: Vec<Option<UnitExpr>>,
+    equations: Vec<Option<UnitExpr>>,
+}
+
+// The remainder of floored division, with the sign of `b` like `//`
+// rounds down: -7 % 3 is 2, so (a // b) * b + a % b is always a
+pub fn floored_rem(a: f64, b: f64) -> f64 {
+    let remainder = a % b;
+    if remainder != 0.0 && (remainder < 0.0) != (b < 0.0) {
+        remainder + b
+    } else {
+        remainder
+    }
+}
+
+// The span is filled in by the caller, which knows where the operands came from
+fn binary(op: BinOp, a: f64, b: f64) -> Result<f64, CalcError> {
+    let span = Span::default();
+    let result = match op {
+        BinOp::Add => a + b,
+        BinOp::Sub => a - b,
+        BinOp::Mul => a * b,
+        BinOp::Div => {
+            if b == 0.0 {
+                return Err(CalcError::DivisionByZero { span });
+            }
+            a / b
+        }
+        BinOp::FloorDiv => {
+            if b == 0.0 {
+                return Err(CalcError::DivisionByZero { span });
+            }
+            (a / b).floor()
+        }
+        BinOp::Mod => {
+            if b == 0.0 {
+                return Err(CalcError::DivisionByZero { span });
+            }
+            floored_rem(a, b)
+        }
+        BinOp::Pow => {
+            if a == 0.0 && b < 0.0 {
+                return Err(CalcError::DivisionByZero { span });
+            }
+            a.powf(b)
+        }
+    };
+    // Two finite inputs should never produce infinity; if they do, it overflowed
+    if result.is_infinite() && a.is_finite() && b.is_finite() {
+        return Err(CalcError::Overflow { span });
+    }
+    // Likewise a product, quotient or power of nonzero numbers is never 0,
+    // as 1e-200 * 1e-200 and (1/3)^10000000 would be
+    let nonzero = match op {
+        BinOp::Mul => a != 0.0 && b != 0.0,
+        BinOp::Div | BinOp::Pow => a != 0.0,
+        _ => false,
+    };
+    if result == 0.0 && nonzero {
+        return Err(CalcError::Underflow { span });
+    }
+    Ok(result)
+}
+
+// The decimal-mode version of `binary`; results are rounded to `ctx`
+fn decimal_binary(
+    op: BinOp,
+    a: &Decimal,
+    b: &Decimal,
+    ctx: DecimalContext,
+) -> Result<Decimal, CalcError> {
+    let span = Span::default();
+    let result = match op {
+        BinOp::Add => a.add(b, ctx),
+        BinOp::Sub => a.sub(b, ctx),
+        BinOp::Mul => a.mul(b, ctx),
+        BinOp::Div => {
+            if b.is_zero() {
+                return Err(CalcError::DivisionByZero { span });
+            }
+            a.div(b, ctx)
+        }
+        BinOp::FloorDiv => {
+            if b.is_zero() {
+                return Err(CalcError::DivisionByZero { span });
+            }
+            a.div_floor(b, ctx).ok_or(CalcError::Overflow { span })?
+        }
+        BinOp::Mod => {
+            if b.is_zero() {
+                return Err(CalcError::DivisionByZero { span });
+            }
+            a.modulo(b, ctx).ok_or(CalcError::Overflow { span })?
+        }
+        BinOp::Pow => {
+            if a.is_zero() && b.is_negative() {
+                return Err(CalcError::DivisionByZero { span });
+            }
+            decimal::pow(a, b, ctx)
+        }
+    };
+    if !result.in_range() {
+        return Err(decimal_range_error(&result, span));
+    }
+    // As with floats, 10^-(10^18) is an underflow rather than 0
+    let nonzero = match op {
+        BinOp::Mul => !a.is_zero() && !b.is_zero(),
+        BinOp::Div | BinOp::Pow => !a.is_zero(),
+        _ => false,
+    };
+    if result.is_zero() && nonzero {
+        return Err(CalcError::Underflow { span });
+    }
+    Ok(result)
+}
+
+// A decimal result past MAX_EXPONENT: an overflow if it is huge and an
+// underflow if it is too close to 0, as with f64s
+pub fn decimal_range_error(result: &Decimal, span: Span) -> CalcError {
+    if result.adjusted() < 0 {
+        CalcError::Underflow { span }
+    } else {
+        CalcError::Overflow { span }
+    }
+}
+
+// (-8)^(1/3) has no real answer, so it is worked out as a complex number
+fn complex_power(op: BinOp, base: &Value, exp: &Value) -> bool {
+    let whole_exp = match exp {
+        Value::Rational(r) => r.is_integer(),
+        Value::Decimal(d) => d.is_integer(),
+        other => other.to_f64().fract() == 0.0,
+    };
+    op == BinOp::Pow && base.to_f64() < 0.0 && !whole_exp
+}
+
+// The complex version of `binary`; a result with no imaginary part
+// comes back as a real number
+fn complex_binary(op: BinOp, a: Complex, b: Complex) -> Result<Value, CalcError> {
+    let span = Span::default();
+    let result = match op {
+        BinOp::Add => a + b,
+        BinOp::Sub => a - b,
+        BinOp::Mul => a * b,
+        BinOp::Div => a.checked_div(b).ok_or(CalcError::DivisionByZero { span })?,
+        BinOp::FloorDiv | BinOp::Mod => {
+            return Err(CalcError::Domain {
+                message: "// and % are only defined for real numbers".to_string(),
+                span,
+            });
+        }
+        BinOp::Pow => a.pow(b).ok_or(CalcError::DivisionByZero { span })?.tidy(),
+    };
+    if !result.is_finite() {
+        return Err(CalcError::Overflow { span });
+    }
+    Ok(Value::complex(result))
+}
+
+// The exact version of `binary`. Powers that can't stay exact, like 2^0.5
+// or 2^(1/2), fall back to f64.
+fn rational_binary(op: BinOp, a: &Rational, b: &Rational) -> Result<Value, CalcError> {
+    let span = Span::default();
+    let result = match op {
+        BinOp::Add => a + b,
+        BinOp::Sub => a - b,
+        BinOp::Mul => a * b,
+        BinOp::Div => a.checked_div(b).ok_or(CalcError::DivisionByZero { span })?,
+        BinOp::FloorDiv => a.div_floor(b).ok_or(CalcError::DivisionByZero { span })?,
+        BinOp::Mod => a.modulo(b).ok_or(CalcError::DivisionByZero { span })?,
+        BinOp::Pow => {
+            if a.is_zero() && b.is_negative() {
+                return Err(CalcError::DivisionByZero { span });
+            }
+            let exact = if b.is_integer() {
+                b.numer().to_i64().and_then(|n| a.pow(n))
+            } else {
+                None
+            };
+            match exact {
+                Some(result) => result,
+                None => {
+                    let (x, y) = (Value::Rational(a.clone()), Value::Rational(b.clone()));
+                    return binary(op, x.to_float()?, y.to_float()?).map(Value::Real);
+                }
+            }
+        }
+    };
+    Ok(Value::Rational(result))
+}
+
+// n! for a whole number n >= 0: exact for fractions and decimals,
+// an f64 product otherwise (which overflows past 170!)
+fn factorial(value: &Value, ctx: DecimalContext) -> Result<Value, CalcError> {
+    let span = Span::default();
+    let n = match value {
+        Value::Rational(r) if r.is_integer() && !r.is_negative() => r.numer().to_i64(),
+        Value::Decimal(d) if d.is_integer() && !d.is_negative() => Some(d.to_f64() as i64),
+        Value::Real(x) if x.fract() == 0.0 && *x >= 0.0 => Some(*x as i64),
+        _ => {
+            return Err(CalcError::Domain {
+                message: format!("factorial needs a whole number >= 0 (got {})", value),
+                span,
+            });
+        }
+    };
+    let n = match n.and_then(|n| u32::try_from(n).ok()) {
+        Some(n) if n <= MAX_FACTORIAL => n,
+        _ => return Err(CalcError::Overflow { span }),
+    };
+    match value {
+        Value::Real(_) => {
+            let result = (2..=n).fold(1.0, |product, k| product * k as f64);
+            if result.is_infinite() {
+                return Err(CalcError::Overflow { span });
+            }
+            Ok(Value::Real(result))
+        }
+        Value::Decimal(_) => Ok(Value::Decimal(
+            Decimal::from(BigInt::factorial(n)).round(ctx),
+        )),
+        // A fraction; complex values were turned away above
+        _ => Ok(Value::Rational(Rational::from(BigInt::factorial(n)))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::show;
+
+    fn run(ctx: &mut Context, input: &str) -> Result<f64, String> {
+        match ctx.run(input).map_err(|err| err.to_string())? {
+            Outcome::Value(value) | Outcome::Assigned(_, value) => Ok(value.to_f64()),
+            Outcome::Defined(function) => panic!("unexpected definition of {}", function.name),
+            Outcome::Solved(_) => panic!("unexpected solution"),
+        }
+    }
+
+    // The unknowns and their values, as text
+    fn solve(ctx: &mut Context, input: &str) -> Result<Vec<(String, String)>, String> {
+        match ctx.run(input).map_err(|err| err.to_string())? {
+            Outcome::Solved(solution) => Ok(solution
+                .into_iter()
+                .map(|(name, value)| (name, value.to_string()))
+                .collect()),
+            other => panic!("expected a solution, got {:?}", other),
+        }
+    }
+
+    fn define(ctx: &mut Context, input: &str) {
+        assert!(matches!(ctx.run(input), Ok(Outcome::Defined(_))));
+    }
+
+    fn calc(input: &str) -> Result<f64, String> {
+        run(&mut Context::new(), input)
+    }
+
+    #[test]
+    fn history_references() {
+        let mut ctx = Context::new();
+        assert_eq!(
+            run(&mut ctx, "ans + 1"),
+            Err("No result in history for 'ans'".to_string())
+        );
+        // Results are numbered from 1 as the front ends record them
+        for (n, input) in ["2 + 3", "ans * 10", "$1 - 1"].into_iter().enumerate() {
+            let value = ctx.evaluate(input).unwrap();
+            assert_eq!(ctx.record(input, value), n + 1);
+        }
+        assert_eq!(run(&mut ctx, "ans"), Ok(4.0));
+        assert_eq!(run(&mut ctx, "$2 + $3"), Ok(54.0));
+        assert_eq!(ctx.history[1].input, "ans * 10");
+        // Assignments and definitions aren't results
+        run(&mut ctx, "x = 7").unwrap();
+        assert_eq!(run(&mut ctx, "ans"), Ok(4.0));
+        assert_eq!(
+            run(&mut ctx, "$4"),
+            Err("No result in history for '$4'".to_string())
+        );
+        assert_eq!(
+            run(&mut ctx, "$0"),
+            Err("No result in history for '$0'".to_string())
+        );
+        ctx.clear();
+        assert!(run(&mut ctx, "$1").is_err());
+        assert!(run(&mut ctx, "ans").is_err());
+        assert_eq!(ctx.record("1", Value::from(1.0)), 1);
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(calc("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(calc("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(calc("10 - 4 - 3"), Ok(3.0));
+        assert_eq!(calc("16 / 4 / 2"), Ok(2.0));
+        assert_eq!(calc("2 * 3 ^ 2"), Ok(18.0));
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(calc("2 ^ 3 ^ 2"), Ok(512.0));
+        assert_eq!(calc("(2 ^ 3) ^ 2"), Ok(64.0));
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(calc("-2 ^ 2"), Ok(-4.0));
+        assert_eq!(calc("2 ^ -1"), Ok(0.5));
+        assert_eq!(calc("--3"), Ok(3.0));
+        assert_eq!(calc("(3 + 4) * 2 ^ 3 / -1.5"), Ok(-112.0 / 3.0));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(calc("1 / 0"), Err("Cannot divide by zero".to_string()));
+        assert!(calc("1 + 2 )").is_err());
+        assert!(calc("(1 + 2").is_err());
+    }
+
+    #[test]
+    fn errors_point_at_their_cause() {
+        let ctx = Context::new();
+        assert_eq!(
+            ctx.evaluate("1 / (2 - 2)"),
+            Err(CalcError::DivisionByZero {
+                span: Span::new(4, 11)
+            })
+        );
+        assert_eq!(
+            crate::parse("2 * (3 + 4").unwrap_err(),
+            CalcError::UnclosedParen {
+                span: Span::new(4, 5)
+            }
+        );
+    }
+
+    #[test]
+    fn literals_beyond_floats() {
+        let ctx = Context::new();
+        assert_eq!(
+            ctx.evaluate("2 + 1e400"),
+            Err(CalcError::Overflow {
+                span: Span::new(4, 9)
+            })
+        );
+        assert_eq!(
+            ctx.evaluate("2 + 1e-400"),
+            Err(CalcError::Underflow {
+                span: Span::new(4, 10)
+            })
+        );
+        assert_eq!(calc("0.0e-400"), Ok(0.0));
+        assert_eq!(calc("1e-300"), Ok(1e-300));
+    }
+
+    #[test]
+    fn big_fractions_meeting_floats() {
+        let overflow = Err("Result is too large (overflow)".to_string());
+        let underflow = Err("Number is too small to tell from 0 (underflow)".to_string());
+        assert_eq!(calc("10^400 * 1.5"), overflow);
+        assert_eq!(calc("10^400 - 10^400 * 1.0"), overflow);
+        assert_eq!(calc("ln(10^400)"), overflow);
+        assert_eq!(calc("sin(10^400)"), overflow);
+        assert_eq!(calc("(20000!)^100"), overflow);
+        assert!(Context::new().evaluate("[10^400, 1] * 1.5").is_err());
+        assert_eq!(calc("(1/3)^10000000"), underflow);
+        assert_eq!(calc("1/10^400 * 1.0"), underflow);
+        assert_eq!(calc("1e-200 * 1e-200"), underflow);
+        // Within range they still meet as usual
+        assert_eq!(calc("10^300 * 1.5"), Ok(1.5e300));
+        assert_eq!(calc("ln(10^300)"), Ok(1e300f64.ln()));
+        assert_eq!(calc("0 * 1.5"), Ok(0.0));
+    }
+
+    #[test]
+    fn function_results_underflow_like_literals() {
+        let underflow = "Number is too small to tell from 0 (underflow)";
+        let ctx = Context::new();
+        assert_eq!(show(&ctx, "exp(-1000)"), underflow);
+        assert_eq!(show(&ctx, "pow(1e-200, 2)"), underflow);
+        assert_eq!(show(&ctx, "exp(-1000 + i)"), underflow);
+        assert_eq!(show(&ctx, "pow(0, 2)"), "0");
+        assert_eq!(calc("exp(-700)"), Ok((-700f64).exp()));
+        let mut ctx = Context::new();
+        ctx.settings.numbers = NumberMode::Decimal;
+        assert_eq!(show(&ctx, "exp(-1e17)"), underflow);
+        assert_eq!(
+            show(&ctx, "10^-600000000000000000 * 10^-600000000000000000"),
+            underflow
+        );
+        assert_eq!(
+            show(&ctx, "10^600000000000000000 * 10^600000000000000000"),
+            "Result is too large (overflow)"
+        );
+    }
+
+    #[test]
+    fn long_chains() {
+        assert_eq!(calc(&vec!["1"; 10_000].join(" + ")), Ok(10_000.0));
+        assert_eq!(calc(&vec!["2"; 102].join(" * ")), Ok(2f64.powi(102)));
+        let sum = crate::parse(&vec!["x"; 10_000].join(" - ")).unwrap();
+        assert_eq!(sum.clone(), sum);
+        assert_eq!(sum.names(), ["x"]);
+    }
+
+    #[test]
+    fn nesting_limit() {
+        // As in `recursion_limit`, 100 levels need more than a test
+        // thread's 2 MiB of stack in a debug build
+        let deep = std::thread::Builder::new().stack_size(8 << 20);
+        let results = deep.spawn(|| {
+            let nested = |open: &str, close: &str, levels| {
+                calc(&format!("{}1{}", open.repeat(levels), close.repeat(levels)))
+            };
+            [
+                nested("(", ")", 100),
+                nested("abs(", ")", 100),
+                nested("(", ")", 101),
+                nested("abs(", ")", 101),
+                nested("-", "", 101),
+                nested("2 ^ ", "", 101),
+            ]
+        });
+        let too_deep = Err("Nested more than 100 levels deep".to_string());
+        assert_eq!(
+            results.unwrap().join().unwrap(),
+            [
+                Ok(1.0),
+                Ok(1.0),
+                too_deep.clone(),
+                too_deep.clone(),
+                too_deep.clone(),
+                too_deep
+            ]
+        );
+    }
+
+    #[test]
+    fn variables() {
+        let mut ctx = Context::new();
+        assert_eq!(run(&mut ctx, "x = 2 + 3"), Ok(5.0));
+        assert_eq!(run(&mut ctx, "rate_2 = x * 2"), Ok(10.0));
+        assert_eq!(run(&mut ctx, "x ^ 2 + rate_2"), Ok(35.0));
+        assert!(ctx.unset("x"));
+        assert!(!ctx.unset("x"));
+        assert_eq!(
+            run(&mut ctx, "x"),
+            Err("Undefined variable 'x'".to_string())
+        );
+        assert!(run(&mut ctx, "ans = 1").is_err());
+    }
+
+    #[test]
+    fn user_functions() {
+        let mut ctx = Context::new();
+        define(&mut ctx, "f(x, y) = x ^ 2 + y");
+        assert_eq!(run(&mut ctx, "f(3, 1)"), Ok(10.0));
+        define(&mut ctx, "g(x) = f(x, x)");
+        assert_eq!(run(&mut ctx, "g(2)"), Ok(6.0));
+        assert_eq!(
+            run(&mut ctx, "f(1)"),
+            Err("f() takes 2 argument(s) but 1 were given".to_string())
+        );
+        assert_eq!(
+            run(&mut ctx, "h(1)"),
+            Err("Undefined function 'h'".to_string())
+        );
+    }
+
+    #[test]
+    fn scoping_is_lexical() {
+        let mut ctx = Context::new();
+        run(&mut ctx, "y = 10").unwrap();
+        define(&mut ctx, "inner(x) = x + y");
+        define(&mut ctx, "outer(y) = inner(1)");
+        // `inner` sees the global y, not outer's parameter
+        assert_eq!(run(&mut ctx, "outer(100)"), Ok(11.0));
+    }
+
+    #[test]
+    fn recursion_limit() {
+        // Test threads get 2 MiB of stack, too little for MAX_DEPTH levels
+        // in a debug build; the calculator runs on the main thread with 8 MiB
+        let deep = std::thread::Builder::new().stack_size(8 << 20);
+        let results = deep.spawn(|| {
+            let mut ctx = Context::new();
+            define(&mut ctx, "f(x) = f(x + 1)");
+            // Each call nests a few levels, and the nesting counts too
+            define(&mut ctx, "g(x) = abs(abs(abs(g(x))))");
+            let nested = format!("h(x) = {}-h(x){}", "abs(".repeat(98), ")".repeat(98));
+            define(&mut ctx, &nested);
+            define(&mut ctx, "k(x) = 2 ^ -(1 + [k(x)])");
+            ["f(0)", "g(1)", "h(1)", "k(1)"].map(|call| run(&mut ctx, call))
+        });
+        let limit = |name: &str| {
+            Err(format!(
+                "Recursion limit of 400 levels exceeded in {}()",
+                name
+            ))
+        };
+        assert_eq!(
+            results.unwrap().join().unwrap(),
+            [limit("f"), limit("g"), limit("h"), limit("k")]
+        );
+    }
+
+    #[test]
+    fn factorials_stay_exact() {
+        let ctx = Context::new();
+        assert_eq!(show(&ctx, "5!"), "120");
+        assert_eq!(show(&ctx, "30!"), "265252859812191058636308480000000");
+        assert_eq!(show(&ctx, "3!!"), "720");
+        assert_eq!(
+            show(&ctx, "(-1)!"),
+            "factorial needs a whole number >= 0 (got -1)"
+        );
+        assert_eq!(
+            show(&ctx, "2.5!"),
+            "factorial needs a whole number >= 0 (got 2.5)"
+        );
+        assert_eq!(show(&ctx, "20001!"), "Result is too large (overflow)");
+    }
+
+    #[test]
+    fn floor_division_pow_and_mod() {
+        let ctx = Context::new();
+        assert_eq!(show(&ctx, "7 // 2"), "3");
+        assert_eq!(show(&ctx, "-7 // 2"), "-4");
+        assert_eq!(show(&ctx, "7.5 // 2"), "3");
+        assert_eq!(show(&ctx, "1 // 0"), "Cannot divide by zero");
+        assert_eq!(show(&ctx, "pow(3, 40)"), "12157665459056928801");
+        assert_eq!(show(&ctx, "pow(1/2, 3)"), "1/8");
+        // % and mod() both take the sign of the divisor
+        assert_eq!(show(&ctx, "mod(-7, 3)"), "2");
+        assert_eq!(show(&ctx, "mod(7, -3)"), "-2");
+        assert_eq!(show(&ctx, "-7 % 3"), "2");
+        assert_eq!(show(&ctx, "mod(7.5, 2)"), "1.5");
+    }
+
+    #[test]
+    fn floor_division_and_remainder_agree() {
+        // (a // b) * b + a % b is a, in every number mode
+        let mut ctx = Context::new();
+        let modes = [
+            NumberMode::Float,
+            NumberMode::Decimal,
+            NumberMode::Integer(IntType::parse("i32").unwrap()),
+        ];
+        for mode in modes {
+            ctx.settings.numbers = mode;
+            for (a, b) in [(-7, 2), (7, -2), (-7, -2), (-6, 3), (-7, 3)] {
+                let input = format!("({a} // {b}) * {b} + {a} % {b}");
+                assert_eq!(show(&ctx, &input), a.to_string(), "{} in {:?}", input, mode);
+            }
+            assert_eq!(show(&ctx, "-7 % 3"), "2");
+            assert_eq!(show(&ctx, "7 % -3"), "-2");
+        }
+        // Exact fractions, and floats
+        ctx.settings.numbers = NumberMode::Float;
+        assert_eq!(show(&ctx, "-7/2 % 1"), "1/2");
+        assert_eq!(show(&ctx, "(-7/2 // 1) + -7/2 % 1"), "-7/2");
+        assert_eq!(show(&ctx, "-7.5 % 2 + sin(0)"), "0.5");
+        assert_eq!(show(&ctx, "7.5 % -2 + sin(0)"), "-0.5");
+    }
+
+    #[test]
+    fn bitwise_on_big_numbers() {
+        let ctx = Context::new();
+        assert_eq!(show(&ctx, "2^200 & 1"), "0");
+        assert_eq!(show(&ctx, "(2^200 + 5) & 7"), "5");
+        assert_eq!(show(&ctx, "-1 & 0xff"), "255");
+        assert_eq!(
+            show(&ctx, "-(2^130) | 1"),
+            "-1361129467683753853853498429727072845823"
+        );
+        assert_eq!(show(&ctx, "~(2^200) + 2^200"), "-1");
+    }
+
+    #[test]
+    fn grouping_long_numbers() {
+        let mut ctx = Context::new();
+        assert_eq!(show(&ctx, "2^64"), "18446744073709551616");
+        ctx.settings.output.group_long = true;
+        assert_eq!(show(&ctx, "2^64"), "18,446,744,073,709,551,616");
+        assert_eq!(show(&ctx, "2^40"), "1099511627776");
+        ctx.settings.output.group = true;
+        assert_eq!(show(&ctx, "2^40"), "1,099,511,627,776");
+    }
+
+    #[test]
+    fn solving_named_equations() {
+        let mut ctx = Context::new();
+        let pair = |name: &str, value: &str| (name.to_string(), value.to_string());
+        assert_eq!(
+            solve(&mut ctx, "solve(2x + y = 3, x + 3y = 5)"),
+            Ok(vec![pair("x", "4/5"), pair("y", "7/5")])
+        );
+        // Nothing is assigned, and names that already have a value are known
+        assert_eq!(
+            run(&mut ctx, "x"),
+            Err("Undefined variable 'x'".to_string())
+        );
+        run(&mut ctx, "a = 1").unwrap();
+        assert_eq!(
+            solve(&mut ctx, "solve(a + y = 3)"),
+            Ok(vec![pair("y", "2")])
+        );
+        assert_eq!(
+            solve(&mut ctx, "solve(x + y = 1, x + y = 2)"),
+            Err("The equations contradict each other, so there is no solution".to_string())
+        );
+        assert_eq!(
+            solve(&mut ctx, "solve(x + y = 1, 2x + 2y = 2)"),
+            Err(
+                "There are infinitely many solutions: y can be anything, and x = 1 - y".to_string()
+            )
+        );
+        assert_eq!(
+            solve(&mut ctx, "solve(x * x = 4)"),
+            Err("solve() only handles linear equations, and x * x = 4 isn't one".to_string())
+        );
+        assert_eq!(
+            solve(&mut ctx, "solve(pi = 3)"),
+            Err("There is nothing to solve for: the equations have no unknowns".to_string())
+        );
+    }
+
+    #[test]
+    fn solving_for_names_that_are_units() {
+        let mut ctx = Context::new();
+        let pair = |name: &str, value: &str| (name.to_string(), value.to_string());
+        // 2s is 2 times the unknown s here, not 2 seconds
+        assert_eq!(
+            solve(&mut ctx, "solve(2s + t = 3, s + 3t = 5)"),
+            Ok(vec![pair("s", "4/5"), pair("t", "7/5")])
+        );
+        // Metres plus seconds has no meaning, so m and s are unknowns even
+        // where they are only written after numbers
+        assert_eq!(
+            solve(&mut ctx, "solve(2m + 3s = 5, 4m + s = 1)"),
+            Ok(vec![pair("m", "-1/5"), pair("s", "9/5")])
+        );
+        assert_eq!(solve(&mut ctx, "solve(2s = 4)"), Ok(vec![pair("s", "2")]));
+        assert_eq!(
+            solve(&mut ctx, "solve(2 m^2 + m = 3)"),
+            Err("solve() only handles linear equations, and 2 m^2 + m = 3 isn't one".to_string())
+        );
+        assert_eq!(
+            solve(&mut ctx, "solve(2s = 3s)"),
+            Err(
+                "There is nothing to solve for: s after a number is a unit (write '* s' to solve for it)"
+                    .to_string()
+            )
+        );
+        // Outside solve() it still is
+        assert_eq!(ctx.evaluate("2s in ms").unwrap().to_string(), "2000 ms");
+    }
+
+    #[test]
+    fn solving_with_units() {
+        let mut ctx = Context::new();
+        let pair = |name: &str, value: &str| (name.to_string(), value.to_string());
+        // The unknowns take the unit the other side needs
+        assert_eq!(
+            solve(&mut ctx, "solve(x = 2 km)"),
+            Ok(vec![pair("x", "2 km")])
+        );
+        assert_eq!(
+            solve(&mut ctx, "solve(2x = 4 m)"),
+            Ok(vec![pair("x", "2 m")])
+        );
+        assert_eq!(
+            solve(&mut ctx, "solve(x + 1 km = 3 km)"),
+            Ok(vec![pair("x", "2 km")])
+        );
+        assert_eq!(
+            solve(&mut ctx, "solve(x+y=3 m, x-y=1 m)"),
+            Ok(vec![pair("x", "2 m"), pair("y", "1 m")])
+        );
+        assert_eq!(
+            solve(&mut ctx, "solve(x * 2 s = 4 m)"),
+            Err("Incompatible units: s (time) and m (length)".to_string())
+        );
+        assert_eq!(
+            solve(&mut ctx, "solve(ans = 1)"),
+            Err("No result in history for 'ans'".to_string())
+        );
+    }
+
+    #[test]
+    fn solving_a_matrix_and_vector() {
+        let ctx = Context::new();
+        let solution = ctx.evaluate("solve([[2, 1], [1, 3]], [3, 5])");
+        assert_eq!(
+            solution.map(|value| value.to_string()),
+            Ok("[4/5, 7/5]".to_string())
+        );
+        assert!(ctx.evaluate("solve([[1, 2], [2, 4]], [1, 2])").is_err());
+        // Named equations inside an expression give the solution vector
+        let scaled = ctx.evaluate("2 * solve(2x + y = 3, x + 3y = 5)");
+        assert_eq!(
+            scaled.map(|value| value.to_string()),
+            Ok("[8/5, 14/5]".to_string())
+        );
+    }
+
+    #[test]
+    fn decimal_trig_of_large_angles() {
+        let mut ctx = Context::new();
+        ctx.settings.numbers = NumberMode::Decimal;
+        let sin = |x: &str| ctx.evaluate(&format!("sin({})", x)).unwrap().to_string();
+        assert_eq!(sin("1e32"), "0.3901970254333630491697613212893426");
+        assert_eq!(sin("1e40"), "-0.5696334009536363273080341815735687");
+        assert_eq!(sin("1e100"), "-0.3723761236612766882620866955531643");
+        // Multiples of pi still come out as 0, not as rounding noise
+        assert_eq!(sin("10pi"), "0");
+        assert_eq!(sin("1e6 * pi"), "0");
+    }
+}
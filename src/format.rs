@@ -0,0 +1,370 @@
+// How results are written out: the notation, thousands separators and the
+// decimal mark. Set with `format sci 6`, `format group on` and `format mark ,`
+// in the REPL, or --format, --group and --mark on the command line.
+//
+// The notations work on the exact decimal digits of a value, so `fix 2`
+// shows 2.675 as 2.68 rather than the 2.67 its nearest f64 would give, and
+// rounding follows the session's rounding mode.
+
+use std::fmt;
+
+use crate::bigint::BigInt;
+use crate::decimal::{self, Decimal, DecimalContext, RoundingMode};
+
+// With `group_long`, exact numbers with more digits than an f64 can hold
+// are grouped, so 30! prints as 265,252,859,812,191,058,636,308,480,000,000
+const GROUP_DIGITS_ABOVE: usize = 15;
+
+// Magnitudes from 1e-7 to 1e21 are written out in full by `sig`, the same
+// range f64 printing uses; beyond that they switch to e-notation
+const PLAIN_RANGE: std::ops::Range<i64> = -7..21;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Notation {
+    // Every digit the value has, in e-notation only when very large or small
+    #[default]
+    Auto,
+    // `fix 2`: exactly two decimals, 1234.50
+    Fixed(usize),
+    // `sig 3`: three significant digits, 1230 or 0.00123
+    Significant(usize),
+    // `sci 3`: three significant digits as d.dd e n, 1.23e3
+    Scientific(usize),
+    // `eng 3`: like sci, with the exponent a multiple of three, 12.3e3
+    Engineering(usize),
+}
+
+impl Notation {
+    // "auto", "fix 2", "sig 6", "sci 6" or "eng 3"; "sci6" and "sci:6" work
+    // too, for the command line. None if the digit count is missing or out
+    // of range (fix takes 0 and up, the others 1 and up).
+    pub fn parse(text: &str) -> Option<Notation> {
+        let text = text.trim();
+        let split = text
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(text.len());
+        let (name, digits) = text.split_at(split);
+        let digits = digits.trim_start_matches([' ', ':']);
+        if name == "auto" {
+            return digits.is_empty().then_some(Notation::Auto);
+        }
+        let digits: usize = digits.parse().ok()?;
+        let least = if name == "fix" { 0 } else { 1 };
+        if !(least..=decimal::MAX_PRECISION).contains(&digits) {
+            return None;
+        }
+        match name {
+            "fix" => Some(Notation::Fixed(digits)),
+            "sig" => Some(Notation::Significant(digits)),
+            "sci" => Some(Notation::Scientific(digits)),
+            "eng" => Some(Notation::Engineering(digits)),
+            _ => None,
+        }
+    }
+
+    // How many significant digits to work out before rounding for display,
+    // for a value whose leading digit is at 10^magnitude
+    pub fn digits_needed(self, magnitude: i64) -> usize {
+        match self {
+            Notation::Auto => decimal::DEFAULT_PRECISION,
+            Notation::Fixed(places) => (magnitude + places as i64 + 1).max(1) as usize,
+            Notation::Significant(digits)
+            | Notation::Scientific(digits)
+            | Notation::Engineering(digits) => digits,
+        }
+    }
+
+    // The number laid out in this notation; None for Auto, which leaves
+    // each kind of value to print itself
+    pub fn layout(self, d: &Decimal, rounding: RoundingMode) -> Option<String> {
+        let round = |digits| {
+            d.round(DecimalContext {
+                precision: digits,
+                rounding,
+            })
+        };
+        let text = match self {
+            Notation::Auto => return None,
+            Notation::Fixed(places) => {
+                plain(&d.round_to_exp(-(places as i64), rounding.into()), places)
+            }
+            Notation::Significant(digits) => {
+                let d = round(digits);
+                let magnitude = adjusted(&d);
+                if PLAIN_RANGE.contains(&magnitude) {
+                    let places = (digits as i64 - 1 - magnitude).max(0) as usize;
+                    plain(&d, places)
+                } else {
+                    scientific(&d, digits, 1)
+                }
+            }
+            Notation::Scientific(digits) => scientific(&round(digits), digits, 1),
+            Notation::Engineering(digits) => scientific(&round(digits), digits, 3),
+        };
+        Some(text)
+    }
+}
+
+impl fmt::Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Notation::Auto => write!(f, "auto (every digit)"),
+            Notation::Fixed(places) => write!(f, "fix {} ({} decimal places)", places, places),
+            Notation::Significant(digits) => {
+                write!(f, "sig {} ({} significant digits)", digits, digits)
+            }
+            Notation::Scientific(digits) => {
+                write!(
+                    f,
+                    "sci {} (scientific, {} significant digits)",
+                    digits, digits
+                )
+            }
+            Notation::Engineering(digits) => write!(
+                f,
+                "eng {} (engineering, {} significant digits)",
+                digits, digits
+            ),
+        }
+    }
+}
+
+// The character between the whole and fractional part. Thousands are
+// separated by the other one: 1,234.5 or 1.234,5.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DecimalMark {
+    #[default]
+    Point,
+    Comma,
+}
+
+impl DecimalMark {
+    // "." or ",", or a language code like "de" or "en_US" for its usual mark
+    pub fn parse(name: &str) -> Option<DecimalMark> {
+        let language = name.split(['_', '-', '.']).next().unwrap_or(name);
+        match (name, language.to_ascii_lowercase().as_str()) {
+            (".", _) | ("point", _) => Some(DecimalMark::Point),
+            (",", _) | ("comma", _) => Some(DecimalMark::Comma),
+            (_, "en" | "ja" | "zh" | "ko" | "he" | "th" | "hi") => Some(DecimalMark::Point),
+            (
+                _,
+                "de" | "fr" | "es" | "it" | "nl" | "pt" | "ru" | "pl" | "sv" | "da" | "nb" | "fi"
+                | "cs" | "tr" | "el" | "id" | "uk",
+            ) => Some(DecimalMark::Comma),
+            _ => None,
+        }
+    }
+
+    fn mark(self) -> char {
+        match self {
+            DecimalMark::Point => '.',
+            DecimalMark::Comma => ',',
+        }
+    }
+
+    fn separator(self) -> char {
+        match self {
+            DecimalMark::Point => ',',
+            DecimalMark::Comma => '.',
+        }
+    }
+
+    // What goes between the elements of a vector: [1.5, 2] or [1,5; 2]
+    pub fn list_separator(self) -> char {
+        match self {
+            DecimalMark::Point => ',',
+            DecimalMark::Comma => ';',
+        }
+    }
+}
+
+impl fmt::Display for DecimalMark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecimalMark::Point => write!(f, "point (1,234.5)"),
+            DecimalMark::Comma => write!(f, "comma (1.234,5)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NumberFormat {
+    pub notation: Notation,
+    // Thousands separators in every number
+    pub group: bool,
+    // Thousands separators in long exact numbers only. The REPL turns this
+    // on for people reading results; output meant for other programs, like
+    // `calculator "2^64" | ...`, keeps plain digits.
+    pub group_long: bool,
+    pub mark: DecimalMark,
+}
+
+impl NumberFormat {
+    // Puts in the decimal mark and thousands separators. Only digits before
+    // a decimal point are grouped, never those after it or in an exponent,
+    // so the text can come from any value's own formatting.
+    pub fn localize(&self, text: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut result = String::with_capacity(text.len() * 4 / 3);
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if !c.is_ascii_digit() {
+                result.push(if c == '.' { self.mark.mark() } else { c });
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let run = &chars[start..i];
+            // Digits right after a point or an 'e' (or "e-") aren't a whole part
+            let before = |back: usize| start.checked_sub(back).map(|at| chars[at]);
+            let fractional = before(1) == Some('.')
+                || before(1) == Some('e')
+                || (before(1) == Some('-') && before(2) == Some('e'));
+            let group_above = match (self.group, self.group_long) {
+                (true, _) => 3,
+                (false, true) => GROUP_DIGITS_ABOVE,
+                (false, false) => usize::MAX,
+            };
+            if fractional || run.len() <= group_above {
+                result.extend(run);
+                continue;
+            }
+            for (n, digit) in run.iter().enumerate() {
+                if n > 0 && (run.len() - n).is_multiple_of(3) {
+                    result.push(self.mark.separator());
+                }
+                result.push(*digit);
+            }
+        }
+        result
+    }
+}
+
+// The power of ten of the leading digit, taking zero as 10^0
+fn adjusted(d: &Decimal) -> i64 {
+    if d.is_zero() { 0 } else { d.adjusted() }
+}
+
+// -1234.5 with `places` = 2 as "-1234.50"; the value must already be
+// rounded to at most that many places
+fn plain(d: &Decimal, places: usize) -> String {
+    let shift = (d.exponent() + places as i64).max(0) as u32;
+    let scaled = &d.coefficient().abs() * &BigInt::from(10u64).pow(shift);
+    let digits = format!("{:0>width$}", scaled.to_string(), width = places + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - places);
+    let sign = if d.is_negative() && !d.is_zero() {
+        "-"
+    } else {
+        ""
+    };
+    if places == 0 {
+        format!("{}{}", sign, whole)
+    } else {
+        format!("{}{}.{}", sign, whole, fraction)
+    }
+}
+
+// `digits` significant digits as mantissa and exponent, with the exponent
+// a multiple of `step`: 1.23e4 with step 1, 12.3e3 with step 3
+fn scientific(d: &Decimal, digits: usize, step: i64) -> String {
+    let magnitude = adjusted(d);
+    let exponent = magnitude.div_euclid(step) * step;
+    let whole_digits = (magnitude - exponent + 1) as usize;
+    let mut mantissa = d.coefficient().abs().to_string();
+    if d.is_zero() {
+        mantissa.clear();
+    }
+    // The coefficient holds the leading digits, with one zero too many when
+    // rounding carried (999.96 becomes 1000 x 10^0); cut or pad it to size
+    let width = digits.max(whole_digits);
+    mantissa.truncate(width);
+    let mantissa = format!("{:0<width$}", mantissa, width = width);
+    let (whole, fraction) = mantissa.split_at(whole_digits);
+    let sign = if d.is_negative() && !d.is_zero() {
+        "-"
+    } else {
+        ""
+    };
+    if fraction.is_empty() {
+        format!("{}{}e{}", sign, whole, exponent)
+    } else {
+        format!("{}{}.{}e{}", sign, whole, fraction, exponent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(notation: &str, number: &str) -> String {
+        let notation = Notation::parse(notation).unwrap();
+        let d = Decimal::parse(number).unwrap();
+        notation.layout(&d, RoundingMode::HalfEven).unwrap()
+    }
+
+    #[test]
+    fn parsing_notations() {
+        assert_eq!(Notation::parse("auto"), Some(Notation::Auto));
+        assert_eq!(Notation::parse("fix 2"), Some(Notation::Fixed(2)));
+        assert_eq!(Notation::parse("fix 0"), Some(Notation::Fixed(0)));
+        assert_eq!(Notation::parse("sci6"), Some(Notation::Scientific(6)));
+        assert_eq!(Notation::parse("eng:3"), Some(Notation::Engineering(3)));
+        assert_eq!(Notation::parse("sig 0"), None);
+        assert_eq!(Notation::parse("sig"), None);
+        assert_eq!(Notation::parse("auto 3"), None);
+        assert_eq!(Notation::parse("hex 2"), None);
+    }
+
+    #[test]
+    fn fixed_and_significant() {
+        // The exact digits are rounded, not those of the nearest f64
+        assert_eq!(layout("fix 2", "2.675"), "2.68");
+        assert_eq!(layout("fix 2", "1234.5"), "1234.50");
+        assert_eq!(layout("fix 0", "-0.4"), "0");
+        assert_eq!(layout("sig 3", "1234.5"), "1230");
+        assert_eq!(layout("sig 3", "0.00123456"), "0.00123");
+        assert_eq!(layout("sig 2", "1e25"), "1.0e25");
+    }
+
+    #[test]
+    fn scientific_and_engineering() {
+        assert_eq!(layout("sci 3", "1234.5"), "1.23e3");
+        assert_eq!(layout("sci 3", "-0.00098765"), "-9.88e-4");
+        assert_eq!(layout("sci 3", "999.96"), "1.00e3");
+        assert_eq!(layout("eng 3", "12345"), "12.3e3");
+        assert_eq!(layout("eng 3", "0.00012345"), "123e-6");
+        assert_eq!(layout("eng 1", "0"), "0e0");
+    }
+
+    #[test]
+    fn marks_and_grouping() {
+        let comma = NumberFormat {
+            mark: DecimalMark::parse("de_DE").unwrap(),
+            group: true,
+            ..NumberFormat::default()
+        };
+        assert_eq!(comma.localize("1234567.891"), "1.234.567,891");
+        assert_eq!(comma.localize("1.5e-12345"), "1,5e-12345");
+        let plain = NumberFormat::default();
+        assert_eq!(plain.localize("1234567.891"), "1234567.891");
+        assert_eq!(
+            plain.localize("265252859812191058636308480000000"),
+            "265252859812191058636308480000000"
+        );
+        let long = NumberFormat {
+            group_long: true,
+            ..NumberFormat::default()
+        };
+        assert_eq!(long.localize("1234567.891"), "1234567.891");
+        assert_eq!(
+            long.localize("265252859812191058636308480000000"),
+            "265,252,859,812,191,058,636,308,480,000,000"
+        );
+        assert_eq!(DecimalMark::parse("en"), Some(DecimalMark::Point));
+        assert_eq!(DecimalMark::parse("xx"), None);
+    }
+}
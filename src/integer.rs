@@ -0,0 +1,249 @@
+// Programmer mode: fixed-width integers and output in other bases.
+//
+// `mode u8` makes every result a whole number from 0 to 255, like a u8 in
+// Rust or C. What happens when a result doesn't fit is a separate choice:
+// an error by default, or wrapping around like Rust's `wrapping_add` after
+// `overflow wrap`. Bitwise operators work on the bit pattern, so in i8
+// mode ~0 is -1 and in u8 mode it is 255.
+
+use std::fmt;
+
+use crate::bigint::BigInt;
+
+// A fixed-width integer type, i8 to i128 or u8 to u128
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+impl IntType {
+    pub fn parse(name: &str) -> Option<IntType> {
+        let (signed, bits) = match name.split_at_checked(1)? {
+            ("i", bits) => (true, bits),
+            ("u", bits) => (false, bits),
+            _ => return None,
+        };
+        match bits.parse() {
+            Ok(bits @ (8 | 16 | 32 | 64 | 128)) => Some(IntType { signed, bits }),
+            _ => None,
+        }
+    }
+
+    pub fn min(self) -> BigInt {
+        if self.signed {
+            -&BigInt::from(1u128 << (self.bits - 1))
+        } else {
+            BigInt::zero()
+        }
+    }
+
+    pub fn max(self) -> BigInt {
+        BigInt::from(self.mask() >> self.signed as u32)
+    }
+
+    pub fn contains(self, n: &BigInt) -> bool {
+        self.min() <= *n && *n <= self.max()
+    }
+
+    // All ones in the low `bits` bits
+    fn mask(self) -> u128 {
+        u128::MAX >> (128 - self.bits)
+    }
+
+    // How the type stores `n`, so -1 in i8 is 0xff
+    pub fn pattern(self, n: &BigInt) -> u128 {
+        n.to_u128_wrapping() & self.mask()
+    }
+
+    // The number a stored bit pattern stands for; the top bit is the sign
+    // for signed types
+    pub fn from_pattern(self, pattern: u128) -> BigInt {
+        let pattern = pattern & self.mask();
+        if self.signed {
+            // Move the sign bit to the top, then shift back with sign extension
+            let unused = 128 - self.bits;
+            BigInt::from(((pattern << unused) as i128) >> unused)
+        } else {
+            BigInt::from(pattern)
+        }
+    }
+
+    // `n` wrapped around into range, like `n as u8`
+    pub fn wrap(self, n: &BigInt) -> BigInt {
+        self.from_pattern(self.pattern(n))
+    }
+}
+
+impl fmt::Display for IntType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.signed { 'i' } else { 'u' };
+        write!(f, "{}{}", sign, self.bits)
+    }
+}
+
+// The base whole numbers are shown in
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Radix {
+    Binary,
+    Octal,
+    #[default]
+    Decimal,
+    Hex,
+}
+
+impl Radix {
+    pub fn parse(name: &str) -> Option<Radix> {
+        match name {
+            "2" | "bin" => Some(Radix::Binary),
+            "8" | "oct" => Some(Radix::Octal),
+            "10" | "dec" => Some(Radix::Decimal),
+            "16" | "hex" => Some(Radix::Hex),
+            _ => None,
+        }
+    }
+
+    pub fn radix(self) -> u32 {
+        match self {
+            Radix::Binary => 2,
+            Radix::Octal => 8,
+            Radix::Decimal => 10,
+            Radix::Hex => 16,
+        }
+    }
+
+    // The literal prefix, so results can be pasted back in
+    fn prefix(self) -> &'static str {
+        match self {
+            Radix::Binary => "0b",
+            Radix::Octal => "0o",
+            Radix::Decimal => "",
+            Radix::Hex => "0x",
+        }
+    }
+
+    // "0xff", "-0b101" or "0b1111_0000": long binary numbers are grouped
+    // in nibbles, which the lexer accepts back
+    pub fn format(self, n: &BigInt) -> String {
+        let digits = n.abs().to_str_radix(self.radix());
+        let digits = if self == Radix::Binary && digits.len() > 8 {
+            let mut grouped = String::with_capacity(digits.len() * 5 / 4);
+            for (i, digit) in digits.chars().enumerate() {
+                if i > 0 && (digits.len() - i).is_multiple_of(4) {
+                    grouped.push('_');
+                }
+                grouped.push(digit);
+            }
+            grouped
+        } else {
+            digits
+        };
+        let sign = if n.is_negative() { "-" } else { "" };
+        format!("{}{}{}", sign, self.prefix(), digits)
+    }
+}
+
+impl fmt::Display for Radix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Radix::Binary => "2 (binary)",
+            Radix::Octal => "8 (octal)",
+            Radix::Decimal => "10 (decimal)",
+            Radix::Hex => "16 (hex)",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// A literal like "0xff", "0o17" or "0b1010_1010"; None for anything else
+pub fn parse_literal(text: &str) -> Option<BigInt> {
+    let radix = match text.get(..2)? {
+        "0x" | "0X" => 16,
+        "0o" | "0O" => 8,
+        "0b" | "0B" => 2,
+        _ => return None,
+    };
+    let digits: String = text[2..].chars().filter(|&c| c != '_').collect();
+    BigInt::parse_radix(&digits, radix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Context;
+    use crate::value::NumberMode;
+
+    fn int(name: &str) -> IntType {
+        IntType::parse(name).unwrap()
+    }
+
+    fn big(n: i128) -> BigInt {
+        BigInt::from(n)
+    }
+
+    // Evaluates `input` in integer mode with the given type, showing results in `radix`
+    fn show(name: &str, wrap: bool, radix: Radix, input: &str) -> String {
+        let mut ctx = Context::new();
+        ctx.settings.numbers = NumberMode::Integer(int(name));
+        ctx.settings.wrap = wrap;
+        ctx.settings.radix = radix;
+        crate::show(&ctx, input)
+    }
+
+    #[test]
+    fn types() {
+        assert_eq!(int("i8").min(), big(-128));
+        assert_eq!(int("i8").max(), big(127));
+        assert_eq!(int("u16").max(), big(65535));
+        assert_eq!(int("u128").max(), BigInt::from(u128::MAX));
+        assert_eq!(int("i64").to_string(), "i64");
+        assert!(int("u8").contains(&big(255)));
+        assert!(!int("u8").contains(&big(-1)));
+        assert_eq!(IntType::parse("i7"), None);
+        assert_eq!(IntType::parse("f32"), None);
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(int("u8").wrap(&big(256)), big(0));
+        assert_eq!(int("u8").wrap(&big(-1)), big(255));
+        assert_eq!(int("i8").wrap(&big(128)), big(-128));
+        assert_eq!(int("i8").wrap(&big(-129)), big(127));
+        assert_eq!(int("i8").pattern(&big(-1)), 0xff);
+        assert_eq!(int("i128").wrap(&BigInt::from(u128::MAX)), big(-1));
+    }
+
+    #[test]
+    fn formats_and_literals() {
+        assert_eq!(Radix::Hex.format(&big(255)), "0xff");
+        assert_eq!(Radix::Binary.format(&big(-5)), "-0b101");
+        assert_eq!(Radix::Binary.format(&big(0x1f0)), "0b1_1111_0000");
+        assert_eq!(Radix::Octal.format(&big(8)), "0o10");
+        assert_eq!(parse_literal("0xFF"), Some(big(255)));
+        assert_eq!(parse_literal("0b1111_0000"), Some(big(240)));
+        assert_eq!(parse_literal("0o17"), Some(big(15)));
+        assert_eq!(parse_literal("0x"), None);
+        assert_eq!(parse_literal("12"), None);
+    }
+
+    #[test]
+    fn results_must_fit_unless_wrapping() {
+        assert_eq!(
+            show("u8", false, Radix::Decimal, "200 + 100"),
+            "300 doesn't fit in u8 (0 to 255); use 'overflow wrap' to wrap around"
+        );
+        assert_eq!(show("u8", true, Radix::Decimal, "200 + 100"), "44");
+        assert_eq!(show("i8", true, Radix::Decimal, "127 + 1"), "-128");
+        assert_eq!(show("u8", false, Radix::Decimal, "7 / 2"), "3");
+    }
+
+    #[test]
+    fn bitwise_operators_use_the_bit_pattern() {
+        assert_eq!(show("i8", false, Radix::Decimal, "~0"), "-1");
+        assert_eq!(show("u8", false, Radix::Decimal, "~0"), "255");
+        assert_eq!(show("i8", false, Radix::Hex, "-1"), "0xff");
+        assert_eq!(show("u16", false, Radix::Hex, "0xf0 | 0x0f"), "0xff");
+        // Like `wrapping_shl`, the shift amount wraps as well
+        assert_eq!(show("u8", true, Radix::Binary, "1 << 9"), "0b10");
+    }
+}
@@ -0,0 +1,277 @@
+// Just enough JSON to read rate files and write --json output without
+// pulling in a dependency. Numbers are kept as their text, so a rate like
+// 1.0843 reaches the exact number types without a detour through f64, and
+// a 40-digit result is written out in full.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    // Keys in the order they appear in the file
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    // Errors say where the problem is, e.g. "expected ':' at character 12"
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut reader = Reader {
+            chars: text.char_indices().peekable(),
+        };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        match reader.chars.next() {
+            None => Ok(value),
+            Some((at, c)) => Err(format!("unexpected '{}' at character {}", c, at + 1)),
+        }
+    }
+
+    // The value of `key` in an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+// On one line, with a space after each ':' and ',':
+// {"input": "2+2", "value": 4}
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(text) => write!(f, "{}", text),
+            Json::String(text) => write_string(f, text),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ": {}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Reader<'a> {
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl Reader<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn expected(&mut self, what: &str) -> String {
+        match self.chars.peek() {
+            Some(&(at, c)) => format!("expected {} at character {} (found '{}')", what, at + 1, c),
+            None => format!("expected {} but the text ended", what),
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if(|&(_, next)| next == c).is_some()
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        let Some(&(_, c)) = self.chars.peek() else {
+            return Err(self.expected("a value"));
+        };
+        match c {
+            '{' => self.object(),
+            '[' => self.array(),
+            '"' => self.string().map(Json::String),
+            '-' | '0'..='9' => Ok(self.number()),
+            _ => self.word(),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.chars.next();
+        let mut entries = Vec::new();
+        if self.eat('}') {
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek().is_none_or(|&(_, c)| c != '"') {
+                return Err(self.expected("a key in quotes"));
+            }
+            let key = self.string()?;
+            if !self.eat(':') {
+                return Err(self.expected("':'"));
+            }
+            entries.push((key, self.value()?));
+            if self.eat('}') {
+                return Ok(Json::Object(entries));
+            }
+            if !self.eat(',') {
+                return Err(self.expected("',' or '}'"));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.chars.next();
+        let mut items = Vec::new();
+        if self.eat(']') {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.eat(']') {
+                return Ok(Json::Array(items));
+            }
+            if !self.eat(',') {
+                return Err(self.expected("',' or ']'"));
+            }
+        }
+    }
+
+    // Called with the opening quote next
+    fn string(&mut self) -> Result<String, String> {
+        self.chars.next();
+        let mut text = String::new();
+        loop {
+            let Some((_, c)) = self.chars.next() else {
+                return Err("a string is never closed".to_string());
+            };
+            match c {
+                '"' => return Ok(text),
+                '\\' => {
+                    let escaped = match self.chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let hex: String = (0..4)
+                                .filter_map(|_| self.chars.next())
+                                .map(|(_, c)| c)
+                                .collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        Some(other) => other,
+                        None => return Err("a string is never closed".to_string()),
+                    };
+                    text.push(escaped);
+                }
+                _ => text.push(c),
+            }
+        }
+    }
+
+    // Checked later by whoever needs the number, like any other number text
+    fn number(&mut self) -> Json {
+        let mut text = String::new();
+        while let Some((_, c)) = self
+            .chars
+            .next_if(|&(_, c)| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            text.push(c);
+        }
+        Json::Number(text)
+    }
+
+    // true, false or null
+    fn word(&mut self) -> Result<Json, String> {
+        let mut word = String::new();
+        while let Some((_, c)) = self.chars.next_if(|(_, c)| c.is_ascii_alphabetic()) {
+            word.push(c);
+        }
+        match word.as_str() {
+            "true" => Ok(Json::Bool(true)),
+            "false" => Ok(Json::Bool(false)),
+            "null" => Ok(Json::Null),
+            _ => Err(self.expected("a value")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let json = Json::parse(r#" {"a": [1, -2.5e3, true, null], "b": "x\"yé"} "#).unwrap();
+        let numbers = ["1", "-2.5e3"].map(|n| Json::Number(n.to_string()));
+        assert_eq!(
+            json.get("a"),
+            Some(&Json::Array(vec![
+                numbers[0].clone(),
+                numbers[1].clone(),
+                Json::Bool(true),
+                Json::Null
+            ]))
+        );
+        assert_eq!(json.get("b"), Some(&Json::String("x\"y\u{e9}".to_string())));
+        assert_eq!(json.get("c"), None);
+    }
+
+    #[test]
+    fn errors_say_where() {
+        assert_eq!(
+            Json::parse(r#"{"a" 1}"#).unwrap_err(),
+            "expected ':' at character 6 (found '1')"
+        );
+        assert_eq!(
+            Json::parse("[1, 2").unwrap_err(),
+            "expected ',' or ']' but the text ended"
+        );
+        assert_eq!(
+            Json::parse("1 2").unwrap_err(),
+            "unexpected '2' at character 3"
+        );
+    }
+
+    #[test]
+    fn writing() {
+        let text = r#"{"a": [1, -2.5e3, true, null], "b": "x\"y\\\n\u0001é", "c": {}}"#;
+        assert_eq!(Json::parse(text).unwrap().to_string(), text);
+    }
+}
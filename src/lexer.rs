@@ -0,0 +1,261 @@
+// The lexer turns raw text like "(3 + 4) * 2" into a flat list of tokens.
+// Think of it as `input.split(...)` in JS, but aware of numbers and symbols.
+
+use crate::angle::AngleMode;
+use crate::decimal::Decimal;
+use crate::error::{CalcError, Span};
+use crate::integer;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    // Kept as written; the evaluator decides whether it becomes an f64 or a decimal
+    Number(String),
+    // A number written with an angle suffix, like `30deg` or `1.5rad`
+    Angle(String, AngleMode),
+    // An imaginary number like `2i`; the text is empty for a bare `i`
+    Imaginary(String),
+    Ident(String),
+    // `$3` refers to the third result in the session history
+    HistoryRef(usize),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    // `//`, floor division
+    SlashSlash,
+    Percent,
+    Caret,
+    // Postfix `!`, factorial
+    Bang,
+    // Bitwise and, or and not; `xor` is a word rather than a symbol
+    // because `^` is already the power operator
+    Amp,
+    Pipe,
+    Tilde,
+    // `<<` and `>>`
+    ShiftLeft,
+    ShiftRight,
+    // `.*`, `./` and `.^`, which work element by element on matrices
+    DotStar,
+    DotSlash,
+    DotCaret,
+    LParen,
+    RParen,
+    // Square brackets around vectors and matrices: [1, 2] or [[1, 2], [3, 4]]
+    LBracket,
+    RBracket,
+    Equals,
+    Comma,
+}
+
+// Every token remembers which columns it covers so errors can point at it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, CalcError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        // '#' starts a comment that runs to the end of the line
+        if c == '#' {
+            break;
+        }
+
+        // 0xff, 0o17 and 0b1010, with optional '_' separators like 0xdead_beef
+        if c == '0' && chars.get(i + 1).is_some_and(|c| "xXoObB".contains(*c)) {
+            let start = i;
+            i += 2;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            if integer::parse_literal(&text).is_none() {
+                return Err(CalcError::InvalidNumber {
+                    text,
+                    span: Span::new(start, i),
+                });
+            }
+            tokens.push(Token {
+                kind: TokenKind::Number(text),
+                span: Span::new(start, i),
+            });
+            continue;
+        }
+
+        // A '.' before '*', '/' or '^' is an element-wise operator, not a
+        // number; "2.*3" is still 2. times 3 since the number takes the dot
+        let dotted = match (c, chars.get(i + 1)) {
+            ('.', Some('*')) => Some(TokenKind::DotStar),
+            ('.', Some('/')) => Some(TokenKind::DotSlash),
+            ('.', Some('^')) => Some(TokenKind::DotCaret),
+            _ => None,
+        };
+        if let Some(kind) = dotted {
+            tokens.push(Token {
+                kind,
+                span: Span::new(i, i + 2),
+            });
+            i += 2;
+            continue;
+        }
+
+        if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            i = scan_number(&chars, i);
+            let text: String = chars[start..i].iter().collect();
+            if text.parse::<f64>().is_err() || Decimal::parse(&text).is_none() {
+                return Err(CalcError::InvalidNumber {
+                    text,
+                    span: Span::new(start, i),
+                });
+            }
+
+            // A unit glued to the number ("30deg") makes it an angle; the parser
+            // picks up the spaced form
+            let word_end = scan_word(&chars, i);
+            let word: String = chars[i..word_end].iter().collect();
+            let kind = match AngleMode::parse(&word) {
+                Some(unit) => {
+                    i = word_end;
+                    TokenKind::Angle(text, unit)
+                }
+                None if word == "i" => {
+                    i = word_end;
+                    TokenKind::Imaginary(text)
+                }
+                None => TokenKind::Number(text),
+            };
+            tokens.push(Token {
+                kind,
+                span: Span::new(start, i),
+            });
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            let start = i;
+            i = scan_word(&chars, i);
+            let name: String = chars[start..i].iter().collect();
+            // `i` on its own is the imaginary unit, so it can't name a variable
+            let kind = if name == "i" {
+                TokenKind::Imaginary(String::new())
+            } else {
+                TokenKind::Ident(name)
+            };
+            tokens.push(Token {
+                kind,
+                span: Span::new(start, i),
+            });
+            continue;
+        }
+
+        if c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits: String = chars[start + 1..i].iter().collect();
+            let index: usize = digits.parse().map_err(|_| CalcError::InvalidNumber {
+                text: chars[start..i].iter().collect(),
+                span: Span::new(start, i),
+            })?;
+            tokens.push(Token {
+                kind: TokenKind::HistoryRef(index),
+                span: Span::new(start, i),
+            });
+            continue;
+        }
+
+        let pair = match (c, chars.get(i + 1)) {
+            ('/', Some('/')) => Some(TokenKind::SlashSlash),
+            ('<', Some('<')) => Some(TokenKind::ShiftLeft),
+            ('>', Some('>')) => Some(TokenKind::ShiftRight),
+            _ => None,
+        };
+        if let Some(kind) = pair {
+            tokens.push(Token {
+                kind,
+                span: Span::new(i, i + 2),
+            });
+            i += 2;
+            continue;
+        }
+
+        let kind = match c {
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            '*' => TokenKind::Star,
+            '/' => TokenKind::Slash,
+            '%' => TokenKind::Percent,
+            '^' => TokenKind::Caret,
+            '!' => TokenKind::Bang,
+            '&' => TokenKind::Amp,
+            '|' => TokenKind::Pipe,
+            '~' => TokenKind::Tilde,
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            '[' => TokenKind::LBracket,
+            ']' => TokenKind::RBracket,
+            '=' => TokenKind::Equals,
+            ',' => TokenKind::Comma,
+            _ => {
+                return Err(CalcError::UnknownOperator {
+                    found: c,
+                    span: Span::new(i, i + 1),
+                });
+            }
+        };
+        tokens.push(Token {
+            kind,
+            span: Span::new(i, i + 1),
+        });
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+// Returns the index just past the identifier starting at `i` (or `i` if there is none)
+fn scan_word(chars: &[char], mut i: usize) -> usize {
+    if i < chars.len() && (chars[i].is_alphabetic() || chars[i] == '_') {
+        while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+            i += 1;
+        }
+    }
+    i
+}
+
+// Returns the index just past the number starting at `i`.
+// Accepts "12", "1.5", ".5" and exponents like "2e-3".
+fn scan_number(chars: &[char], mut i: usize) -> usize {
+    while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+        i += 1;
+    }
+    if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+        let mut j = i + 1;
+        if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+            j += 1;
+        }
+        // Only treat the 'e' as an exponent if digits follow it
+        if j < chars.len() && chars[j].is_ascii_digit() {
+            i = j;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+        }
+    }
+    i
+}
@@ -0,0 +1,73 @@
+// The calculator engine as a library, so other tools can embed it.
+// The `calculator` binary in src/bin/ is just one front end for it.
+//
+//     let value = cli_calculator::evaluate("(3 + 4) * 2")?;
+//
+//     let mut ctx = cli_calculator::Context::new();
+//     ctx.run("rate = 0.075")?;
+//     let total = ctx.evaluate("200 * (1 + rate)")?;
+
+pub mod angle;
+pub mod bigint;
+pub mod builtins;
+pub mod complex;
+pub mod currency;
+pub mod decimal;
+pub mod error;
+pub mod eval;
+pub mod format;
+pub mod integer;
+pub mod json;
+pub mod lexer;
+pub mod matrix;
+pub mod parser;
+pub mod rational;
+pub mod rpn;
+pub mod session;
+pub mod units;
+pub mod value;
+
+pub use angle::AngleMode;
+pub use complex::{Complex, ComplexForm};
+pub use currency::Rates;
+pub use decimal::{Decimal, DecimalContext, RoundingMode};
+pub use error::{CalcError, Span};
+pub use eval::{Context, Entry, Function, Outcome, Settings};
+pub use format::{DecimalMark, Notation, NumberFormat};
+pub use integer::{IntType, Radix};
+pub use matrix::Matrix;
+pub use parser::{BinOp, BitOp, Expr, ExprKind, Statement};
+pub use rational::Rational;
+pub use units::{Quantity, UnitExpr};
+pub use value::{NumberMode, Value};
+
+// Evaluates a single expression with a fresh context (no variables, radians)
+pub fn evaluate(input: &str) -> Result<Value, CalcError> {
+    Context::new().evaluate(input)
+}
+
+// What the calculator prints for `input`, the result or the error, so
+// tests in every module can compare against plain text
+#[cfg(test)]
+fn show(ctx: &Context, input: &str) -> String {
+    match ctx.evaluate(input) {
+        Ok(value) => ctx.settings.format(&value),
+        Err(error) => error.to_string(),
+    }
+}
+
+// The same with a fresh context
+#[cfg(test)]
+fn calc(input: &str) -> String {
+    show(&Context::new(), input)
+}
+
+// Parses an expression without evaluating it
+pub fn parse(input: &str) -> Result<Expr, CalcError> {
+    parser::parse_expression(&lexer::tokenize(input)?)
+}
+
+// Parses a full input line, which may also be an assignment or a function definition
+pub fn parse_statement(input: &str) -> Result<Statement, CalcError> {
+    parser::parse(&lexer::tokenize(input)?)
+}
@@ -0,0 +1,971 @@
+// Vectors and matrices, written [1, 2, 3] and [[1, 2], [3, 4]].
+//
+// A vector is a column of numbers that lies down when it has to: on the
+// left of a product it acts as a row, so both A * v and v * A work, and
+// between two vectors `*` is the dot product. Elements can be any kind of
+// number, and all arithmetic on them goes through `Context::operate`, so
+// [1/2, 1/3] stays exact, decimal mode keeps its digits and units work
+// inside a matrix as they do outside one.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::bigint::BigInt;
+use crate::decimal::GUARD_DIGITS;
+use crate::error::{CalcError, Span};
+use crate::eval::{self, Context, Settings};
+use crate::parser::BinOp;
+use crate::value::{NumberMode, Value};
+
+// A float pivot this much smaller than the largest element is rounding
+// noise left behind by a row that cancelled out, so it counts as zero
+const FLOAT_TOLERANCE: f64 = 1e-12;
+
+// identity(n) beyond this is almost certainly a typo
+const MAX_IDENTITY: usize = 1000;
+
+// Like the limit on exact powers of numbers: a matrix power whose exact
+// elements would pass this many digits is an overflow, so a typo like
+// [[1, 1], [1, 0]]^(10^9) fails instead of hanging the calculator
+const MAX_POW_DIGITS: usize = 100_000;
+
+// A product needs rows * cols * inner multiplications of exact numbers,
+// a few seconds' worth at 200x200, so identity(1000) * identity(1000)
+// is refused rather than left running for minutes
+const MAX_PRODUCT_TERMS: usize = 2_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    // Row after row
+    elems: Vec<Value>,
+    // A single column that shows as [1, 2, 3] and turns to fit a product
+    vector: bool,
+}
+
+impl Matrix {
+    pub fn vector(elems: Vec<Value>) -> Matrix {
+        Matrix {
+            rows: elems.len(),
+            cols: 1,
+            elems,
+            vector: true,
+        }
+    }
+
+    // `elems` holds `rows` rows of `cols` values each
+    pub fn new(rows: usize, cols: usize, elems: Vec<Value>) -> Matrix {
+        assert_eq!(elems.len(), rows * cols, "matrix elements don't fill it");
+        Matrix {
+            rows,
+            cols,
+            elems,
+            vector: false,
+        }
+    }
+
+    // The n x n identity, in the session's kind of number
+    pub fn identity(ctx: &Context, n: usize) -> Matrix {
+        let zero = ctx.whole_value(&BigInt::zero());
+        let one = ctx.whole_value(&BigInt::one());
+        let elems = (0..n * n)
+            .map(|i| {
+                if i % (n + 1) == 0 {
+                    one.clone()
+                } else {
+                    zero.clone()
+                }
+            })
+            .collect();
+        Matrix::new(n, n, elems)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn is_vector(&self) -> bool {
+        self.vector
+    }
+
+    pub fn elems(&self) -> &[Value] {
+        &self.elems
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> &Value {
+        &self.elems[row * self.cols + col]
+    }
+
+    pub fn row(&self, row: usize) -> &[Value] {
+        &self.elems[row * self.cols..(row + 1) * self.cols]
+    }
+
+    // "2x3 matrix" or "3-element vector", for messages
+    pub fn shape(&self) -> String {
+        if self.vector {
+            format!("{}-element vector", self.rows)
+        } else {
+            format!("{}x{} matrix", self.rows, self.cols)
+        }
+    }
+
+    pub fn map(self, f: impl FnMut(Value) -> Value) -> Matrix {
+        let elems = self.elems.into_iter().map(f).collect();
+        Matrix { elems, ..self }
+    }
+
+    pub fn try_map<E>(self, f: impl FnMut(Value) -> Result<Value, E>) -> Result<Matrix, E> {
+        let elems = self.elems.into_iter().map(f).collect::<Result<_, _>>()?;
+        Ok(Matrix { elems, ..self })
+    }
+
+    // Rows become columns. A vector turns into a one-row matrix, and a
+    // one-row matrix back into a vector.
+    pub fn transpose(&self) -> Matrix {
+        if self.rows == 1 && !self.vector {
+            return Matrix::vector(self.elems.clone());
+        }
+        let elems = (0..self.cols)
+            .flat_map(|col| (0..self.rows).map(move |row| (row, col)))
+            .map(|(row, col)| self.get(row, col).clone())
+            .collect();
+        Matrix::new(self.cols, self.rows, elems)
+    }
+
+    // Laid out for reading, with each element as `format` writes it: a
+    // vector on one line, and a matrix one row per line with the columns
+    // lined up on the right.
+    //
+    //   [[ 1, -2.5],
+    //    [10,    4]]
+    //
+    // `separator` goes between elements; it's ';' when ',' is the decimal mark.
+    pub fn layout(&self, format: impl Fn(&Value) -> String, separator: char) -> String {
+        let cells: Vec<String> = self.elems.iter().map(format).collect();
+        let between = format!("{} ", separator);
+        if self.vector {
+            return format!("[{}]", cells.join(&between));
+        }
+        let widths: Vec<usize> = (0..self.cols)
+            .map(|col| {
+                (0..self.rows)
+                    .map(|row| cells[row * self.cols + col].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let lines: Vec<String> = cells
+            .chunks(self.cols)
+            .map(|row| {
+                let padded: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, &width)| format!("{:>width$}", cell, width = width))
+                    .collect();
+                format!("[{}]", padded.join(&between))
+            })
+            .collect();
+        format!("[{}]", lines.join(&format!("{}\n ", separator)))
+    }
+}
+
+impl From<Matrix> for Value {
+    fn from(m: Matrix) -> Self {
+        Value::Matrix(Box::new(m))
+    }
+}
+
+// On one line, as it would be typed: [1, 2] or [[1, 2], [3, 4]]
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let row = |elems: &[Value]| {
+            let elems: Vec<String> = elems.iter().map(Value::to_string).collect();
+            format!("[{}]", elems.join(", "))
+        };
+        if self.vector {
+            return write!(f, "{}", row(&self.elems));
+        }
+        let rows: Vec<String> = self.elems.chunks(self.cols).map(row).collect();
+        write!(f, "[{}]", rows.join(", "))
+    }
+}
+
+// The value of a literal: numbers make a vector, and vectors of the same
+// length make the rows of a matrix
+pub fn from_elements(values: Vec<Value>) -> Result<Value, CalcError> {
+    let span = Span::default();
+    if !values.iter().any(|value| matches!(value, Value::Matrix(_))) {
+        return Ok(Matrix::vector(values).into());
+    }
+    let mut rows = Vec::with_capacity(values.len());
+    for value in values {
+        match value {
+            Value::Matrix(row) if row.vector => rows.push(row.elems),
+            other => {
+                return Err(CalcError::Domain {
+                    message: format!(
+                        "Each row of a matrix must be a vector (got {})",
+                        describe(&other)
+                    ),
+                    span,
+                });
+            }
+        }
+    }
+    let cols = rows[0].len();
+    if let Some(i) = rows.iter().position(|row| row.len() != cols) {
+        return Err(CalcError::Domain {
+            message: format!(
+                "Every row of a matrix needs the same length (row 1 has {}, row {} has {})",
+                cols,
+                i + 1,
+                rows[i].len()
+            ),
+            span,
+        });
+    }
+    Ok(Matrix::new(rows.len(), cols, rows.concat()).into())
+}
+
+// An arithmetic operator with a vector or matrix on at least one side.
+// Matrices add and multiply as in linear algebra; a plain number
+// multiplies or divides every element, but can't be added to them.
+pub fn binary(ctx: &Context, op: BinOp, a: Value, b: Value) -> Result<Value, CalcError> {
+    let span = Span::default();
+    match (a, b) {
+        (Value::Matrix(x), Value::Matrix(y)) => match op {
+            BinOp::Add | BinOp::Sub => zip(ctx, op, *x, &y),
+            BinOp::Mul if x.vector && y.vector => dot(ctx, &x, &y),
+            BinOp::Mul => product(ctx, &x, &y).map(Value::from),
+            _ => Err(matrix_on_right(op, &y)),
+        },
+        (Value::Matrix(x), y) => match op {
+            BinOp::Add | BinOp::Sub => Err(CalcError::ShapeMismatch {
+                operation: verb(op).to_string(),
+                left: x.shape(),
+                right: "number".to_string(),
+                span,
+            }),
+            BinOp::Pow => power(ctx, *x, &y).map(Value::from),
+            _ => Ok(x.try_map(|elem| ctx.operate(op, elem, y.clone()))?.into()),
+        },
+        (x, Value::Matrix(y)) => match op {
+            BinOp::Mul => Ok(y.try_map(|elem| ctx.operate(op, x.clone(), elem))?.into()),
+            BinOp::Add | BinOp::Sub => Err(CalcError::ShapeMismatch {
+                operation: verb(op).to_string(),
+                left: "number".to_string(),
+                right: y.shape(),
+                span,
+            }),
+            _ => Err(matrix_on_right(op, &y)),
+        },
+        (x, y) => ctx.operate(op, x, y),
+    }
+}
+
+// .*, ./ and .^: the operator between matching elements, or between
+// each element and a plain number
+pub fn elementwise(ctx: &Context, op: BinOp, a: Value, b: Value) -> Result<Value, CalcError> {
+    match (a, b) {
+        (Value::Matrix(x), Value::Matrix(y)) => zip(ctx, op, *x, &y),
+        (Value::Matrix(x), y) => Ok(x.try_map(|elem| ctx.operate(op, elem, y.clone()))?.into()),
+        (x, Value::Matrix(y)) => Ok(y.try_map(|elem| ctx.operate(op, x.clone(), elem))?.into()),
+        (x, y) => ctx.operate(op, x, y),
+    }
+}
+
+// The operator on each pair of elements in the same place
+fn zip(ctx: &Context, op: BinOp, x: Matrix, y: &Matrix) -> Result<Value, CalcError> {
+    if (x.rows, x.cols) != (y.rows, y.cols) {
+        return Err(mismatch(verb(op), &x, y));
+    }
+    let mut others = y.elems.iter();
+    let result = x.try_map(|elem| {
+        let other = others.next().expect("same number of elements");
+        ctx.operate(op, elem, other.clone())
+    })?;
+    Ok(result.into())
+}
+
+// The matrix product. A vector is a column on the right and a row on the
+// left, and the result is a vector whenever one of the two was.
+pub fn product(ctx: &Context, a: &Matrix, b: &Matrix) -> Result<Matrix, CalcError> {
+    let (rows, inner) = if a.vector {
+        (1, a.rows)
+    } else {
+        (a.rows, a.cols)
+    };
+    if inner != b.rows {
+        return Err(mismatch("multiply", a, b));
+    }
+    let terms = rows.saturating_mul(b.cols).saturating_mul(inner);
+    if terms > MAX_PRODUCT_TERMS {
+        return Err(CalcError::Domain {
+            message: format!(
+                "Multiplying a {} by a {} takes {} multiplications, more than the limit of {}",
+                a.shape(),
+                b.shape(),
+                terms,
+                MAX_PRODUCT_TERMS
+            ),
+            span: Span::default(),
+        });
+    }
+    let mut elems = Vec::with_capacity(rows * b.cols);
+    for row in 0..rows {
+        ctx.check_interrupt()?;
+        for col in 0..b.cols {
+            let pairs = (0..inner).map(|k| (&a.elems[row * inner + k], b.get(k, col)));
+            elems.push(sum_of_products(ctx, pairs)?);
+        }
+    }
+    Ok(if a.vector || b.vector {
+        Matrix::vector(elems)
+    } else {
+        Matrix::new(rows, b.cols, elems)
+    })
+}
+
+pub fn dot(ctx: &Context, a: &Matrix, b: &Matrix) -> Result<Value, CalcError> {
+    if a.elems.len() != b.elems.len() {
+        return Err(mismatch("take the dot product of", a, b));
+    }
+    sum_of_products(ctx, a.elems.iter().zip(&b.elems))
+}
+
+// The cross product of two 3-element vectors
+pub fn cross(ctx: &Context, a: &Matrix, b: &Matrix) -> Result<Matrix, CalcError> {
+    if a.elems.len() != 3 || b.elems.len() != 3 {
+        return Err(mismatch("take the cross product of", a, b));
+    }
+    let (x, y) = (&a.elems, &b.elems);
+    // Each element is x[i] * y[j] - x[j] * y[i] for the other two places
+    let elems = [(1, 2), (2, 0), (0, 1)]
+        .into_iter()
+        .map(|(i, j)| {
+            let first = ctx.operate(BinOp::Mul, x[i].clone(), y[j].clone())?;
+            let second = ctx.operate(BinOp::Mul, x[j].clone(), y[i].clone())?;
+            ctx.operate(BinOp::Sub, first, second)
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Matrix::vector(elems))
+}
+
+fn sum_of_products<'a>(
+    ctx: &Context,
+    pairs: impl Iterator<Item = (&'a Value, &'a Value)>,
+) -> Result<Value, CalcError> {
+    let mut sum: Option<Value> = None;
+    for (x, y) in pairs {
+        let term = ctx.operate(BinOp::Mul, x.clone(), y.clone())?;
+        sum = Some(match sum {
+            Some(sum) => ctx.operate(BinOp::Add, sum, term)?,
+            None => term,
+        });
+    }
+    Ok(sum.expect("matrices have at least one element"))
+}
+
+// A^n by repeated squaring; A^-n is the inverse to the nth power
+fn power(ctx: &Context, m: Matrix, exponent: &Value) -> Result<Matrix, CalcError> {
+    square(&m, "A power")?;
+    let n = exponent
+        .as_whole()
+        .and_then(|n| n.to_i64())
+        .ok_or_else(|| CalcError::Domain {
+            message: format!(
+                "A matrix power needs a whole-number exponent (got {})",
+                exponent
+            ),
+            span: Span::default(),
+        })?;
+    let mut base = if n < 0 { inverse(ctx, &m)? } else { m };
+    let mut result = Matrix::identity(ctx, base.rows);
+    let mut n = n.unsigned_abs();
+    while n > 0 {
+        if n & 1 == 1 {
+            result = power_step(ctx, &result, &base)?;
+        }
+        n >>= 1;
+        if n > 0 {
+            let before = max_digits(&base);
+            base = power_step(ctx, &base, &base)?;
+            // Elements that double in length when squared grow like an
+            // ordinary power, so base^n will have about n times the digits
+            let after = max_digits(&base);
+            if before > 1
+                && after >= 2 * before
+                && (after as u64).saturating_mul(n) > MAX_POW_DIGITS as u64
+            {
+                return Err(CalcError::Overflow {
+                    span: Span::default(),
+                });
+            }
+        }
+    }
+    Ok(result)
+}
+
+// One product of `power`, refused before it's worked out if its elements
+// could have more than MAX_POW_DIGITS digits
+fn power_step(ctx: &Context, a: &Matrix, b: &Matrix) -> Result<Matrix, CalcError> {
+    if max_digits(a) + max_digits(b) > MAX_POW_DIGITS {
+        return Err(CalcError::Overflow {
+            span: Span::default(),
+        });
+    }
+    product(ctx, a, b)
+}
+
+fn max_digits(m: &Matrix) -> usize {
+    m.elems.iter().map(exact_digits).max().unwrap_or(0)
+}
+
+// How many digits an exact fraction's numerator or denominator has; other
+// numbers have a fixed size and never hold a power up
+fn exact_digits(value: &Value) -> usize {
+    match value {
+        Value::Rational(r) => r.numer().num_digits().max(r.denom().num_digits()),
+        Value::Quantity(q) => exact_digits(&q.value),
+        _ => 0,
+    }
+}
+
+pub fn identity(ctx: &Context, size: &Value) -> Result<Matrix, CalcError> {
+    let n = size
+        .as_whole()
+        .and_then(|n| n.to_i64())
+        .and_then(|n| usize::try_from(n).ok())
+        .filter(|n| (1..=MAX_IDENTITY).contains(n))
+        .ok_or_else(|| CalcError::Domain {
+            message: format!(
+                "identity() needs a whole number from 1 to {} (got {})",
+                MAX_IDENTITY, size
+            ),
+            span: Span::default(),
+        })?;
+    Ok(Matrix::identity(ctx, n))
+}
+
+pub fn determinant(ctx: &Context, m: &Matrix) -> Result<Value, CalcError> {
+    square(m, "det()")?;
+    let work = working(ctx);
+    let echelon = Echelon::reduce(&work, rows(m), m.cols)?;
+    if echelon.pivots.len() < m.rows {
+        return Ok(ctx.whole_value(&BigInt::zero()));
+    }
+    // The product of the pivots, with the sign flipped by each row swap
+    let mut det = echelon.rows[0][0].clone();
+    for (i, row) in echelon.rows.iter().enumerate().skip(1) {
+        det = work.operate(BinOp::Mul, det, row[i].clone())?;
+    }
+    let det = if echelon.odd { eval::negate(det) } else { det };
+    Ok(finish(ctx, det))
+}
+
+pub fn inverse(ctx: &Context, m: &Matrix) -> Result<Matrix, CalcError> {
+    square(m, "inverse()")?;
+    let n = m.rows;
+    let work = working(ctx);
+    // Reducing [A | I] leaves [I | A^-1]
+    let identity = Matrix::identity(&work, n);
+    let augmented = (0..n)
+        .map(|i| [m.row(i), identity.row(i)].concat())
+        .collect();
+    let mut echelon = Echelon::reduce(&work, augmented, n)?;
+    if echelon.pivots.len() < n {
+        return Err(CalcError::Singular {
+            message: format!(
+                "{} is singular (its determinant is 0), so it has no inverse",
+                m
+            ),
+            span: Span::default(),
+        });
+    }
+    echelon.back_substitute(&work)?;
+    let elems = echelon
+        .rows
+        .into_iter()
+        .flat_map(|row| row.into_iter().skip(n))
+        .map(|elem| finish(ctx, elem))
+        .collect();
+    Ok(Matrix::new(n, n, elems))
+}
+
+// solve(A, b): the vector x with A x = b
+pub fn solve(ctx: &Context, a: &Matrix, b: &Matrix) -> Result<Matrix, CalcError> {
+    if a.vector || !b.vector {
+        return Err(CalcError::Domain {
+            message: format!(
+                "solve() needs a matrix and a vector, like solve([[2, 1], [1, 3]], [3, 5]) (got a {} and a {})",
+                a.shape(),
+                b.shape()
+            ),
+            span: Span::default(),
+        });
+    }
+    if a.rows != b.rows {
+        return Err(mismatch("solve with", a, b));
+    }
+    let augmented = (0..a.rows).map(|i| [a.row(i), b.row(i)].concat()).collect();
+    let names: Vec<String> = (1..=a.cols).map(|i| format!("x{}", i)).collect();
+    Ok(Matrix::vector(solve_system(ctx, augmented, &names)?))
+}
+
+// Solves the equations whose rows are [coefficients | right-hand side],
+// giving one value per unknown. `names` are the unknowns, for saying why
+// there is no solution or more than one.
+pub fn solve_system(
+    ctx: &Context,
+    rows: Vec<Vec<Value>>,
+    names: &[String],
+) -> Result<Vec<Value>, CalcError> {
+    let n = names.len();
+    let scale = rows.iter().flatten().map(magnitude).fold(0.0, f64::max);
+    let work = working(ctx);
+    let mut echelon = Echelon::reduce(&work, rows, n)?;
+    // The rows without a pivot now say 0 = c, which only holds for c = 0
+    let tolerance = tolerance(&work, scale);
+    let rank = echelon.pivots.len();
+    if echelon.rows[rank..]
+        .iter()
+        .any(|row| !negligible(&row[n], tolerance))
+    {
+        return Err(CalcError::Singular {
+            message: "The equations contradict each other, so there is no solution".to_string(),
+            span: Span::default(),
+        });
+    }
+    echelon.back_substitute(&work)?;
+    if rank < n {
+        return Err(underdetermined(ctx, &echelon, names));
+    }
+    // With a pivot in every column, row i is now x_i = c
+    Ok(echelon
+        .rows
+        .into_iter()
+        .take(n)
+        .map(|row| finish(ctx, row[n].clone()))
+        .collect())
+}
+
+// Describes every solution of a reduced system with free unknowns, like
+// "y can be anything, and x = 3/2 - 1/2 y"
+fn underdetermined(ctx: &Context, echelon: &Echelon, names: &[String]) -> CalcError {
+    let n = names.len();
+    let free: Vec<&str> = (0..n)
+        .filter(|col| !echelon.pivots.contains(col))
+        .map(|col| names[col].as_str())
+        .collect();
+    // Pivots are 1 now, so the other coefficients are on that scale
+    let tolerance = tolerance(ctx, 1.0);
+    let format = |value: &Value| ctx.settings.format(&finish(ctx, value.clone()));
+    let mut relations = Vec::new();
+    for (row, &col) in echelon.rows.iter().zip(&echelon.pivots) {
+        let mut rhs = String::new();
+        if !negligible(&row[n], tolerance) {
+            rhs = format(&row[n]);
+        }
+        // x + c y = d is x = d - c y
+        for free_col in (col + 1..n).filter(|c| !echelon.pivots.contains(c)) {
+            if negligible(&row[free_col], tolerance) {
+                continue;
+            }
+            let written = format(&row[free_col]);
+            let (sign, size) = match written.strip_prefix('-') {
+                Some(size) => ('+', size.to_string()),
+                None => ('-', written),
+            };
+            let term = match size.as_str() {
+                "1" => names[free_col].clone(),
+                _ if size.contains(['+', '-', ' ']) => format!("({}) {}", size, names[free_col]),
+                _ => format!("{} {}", size, names[free_col]),
+            };
+            rhs = match (rhs.is_empty(), sign) {
+                (true, '+') => term,
+                (true, _) => format!("-{}", term),
+                (false, _) => format!("{} {} {}", rhs, sign, term),
+            };
+        }
+        if rhs.is_empty() {
+            rhs.push('0');
+        }
+        relations.push(format!("{} = {}", names[col], rhs));
+    }
+    let mut message = format!(
+        "There are infinitely many solutions: {} can be anything",
+        join(&free)
+    );
+    if !relations.is_empty() {
+        let relations: Vec<&str> = relations.iter().map(String::as_str).collect();
+        message.push_str(&format!(", and {}", join(&relations)));
+    }
+    CalcError::Singular {
+        message,
+        span: Span::default(),
+    }
+}
+
+// "x", "x and y", "x, y and z"
+fn join(names: &[&str]) -> String {
+    match names {
+        [] => String::new(),
+        [only] => only.to_string(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+// Row reduction with partial pivoting, which det(), inverse() and solving
+// equations are built on. Each column's pivot is its largest remaining
+// element, so rounding errors don't grow from one row to the next.
+pub struct Echelon {
+    pub rows: Vec<Vec<Value>>,
+    // The column of each row's pivot, for the rows that have one; the rest
+    // of the rows are all (near) zero in the reduced columns
+    pub pivots: Vec<usize>,
+    // Whether the rows were swapped an odd number of times
+    pub odd: bool,
+}
+
+impl Echelon {
+    // Brings the first `width` columns of `rows` to row echelon form, doing
+    // the same to any columns after them, like the b of [A | b]
+    pub fn reduce(
+        ctx: &Context,
+        mut rows: Vec<Vec<Value>>,
+        width: usize,
+    ) -> Result<Echelon, CalcError> {
+        let scale = rows
+            .iter()
+            .flat_map(|row| &row[..width])
+            .map(magnitude)
+            .fold(0.0, f64::max);
+        let tolerance = tolerance(ctx, scale);
+        let mut pivots = Vec::new();
+        let mut odd = false;
+        for col in 0..width {
+            ctx.check_interrupt()?;
+            let top = pivots.len();
+            if top == rows.len() {
+                break;
+            }
+            let best = (top..rows.len())
+                .max_by(|&i, &j| magnitude(&rows[i][col]).total_cmp(&magnitude(&rows[j][col])))
+                .expect("rows left to pivot on");
+            if negligible(&rows[best][col], tolerance) {
+                continue;
+            }
+            if best != top {
+                rows.swap(best, top);
+                odd = !odd;
+            }
+            let pivot_row = rows[top].clone();
+            for row in &mut rows[top + 1..] {
+                if negligible(&row[col], 0.0) {
+                    continue;
+                }
+                let factor = ctx.operate(BinOp::Div, row[col].clone(), pivot_row[col].clone())?;
+                for c in col + 1..row.len() {
+                    let step = ctx.operate(BinOp::Mul, factor.clone(), pivot_row[c].clone())?;
+                    row[c] = ctx.operate(BinOp::Sub, row[c].clone(), step)?;
+                }
+                row[col] = zero_like(ctx, &row[col])?;
+            }
+            pivots.push(col);
+        }
+        Ok(Echelon { rows, pivots, odd })
+    }
+
+    // Finishes the job: every pivot becomes 1 and the rest of its column 0,
+    // so [A | B] ends up as [I | A^-1 B] when A is invertible
+    pub fn back_substitute(&mut self, ctx: &Context) -> Result<(), CalcError> {
+        for (i, &col) in self.pivots.iter().enumerate().rev() {
+            ctx.check_interrupt()?;
+            let pivot = self.rows[i][col].clone();
+            for c in col..self.rows[i].len() {
+                self.rows[i][c] =
+                    ctx.operate(BinOp::Div, self.rows[i][c].clone(), pivot.clone())?;
+            }
+            let pivot_row = self.rows[i].clone();
+            for row in &mut self.rows[..i] {
+                if negligible(&row[col], 0.0) {
+                    continue;
+                }
+                let factor = row[col].clone();
+                for c in col + 1..row.len() {
+                    let step = ctx.operate(BinOp::Mul, factor.clone(), pivot_row[c].clone())?;
+                    row[c] = ctx.operate(BinOp::Sub, row[c].clone(), step)?;
+                }
+                row[col] = zero_like(ctx, &row[col])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// How small a float or decimal element must be to count as zero, next to
+// the largest element of the matrix
+pub fn tolerance(ctx: &Context, scale: f64) -> f64 {
+    match ctx.settings.numbers {
+        // Decimal results carry noise in their last few digits only
+        NumberMode::Decimal => scale * 10f64.powi(3 - ctx.settings.decimal.precision as i32),
+        _ => scale * FLOAT_TOLERANCE,
+    }
+}
+
+// Exact fractions are zero only when they really are
+pub fn negligible(value: &Value, tolerance: f64) -> bool {
+    match value {
+        Value::Rational(r) => r.is_zero(),
+        Value::Quantity(q) => negligible(&q.value, tolerance),
+        other => magnitude(other) <= tolerance,
+    }
+}
+
+// The size of a number, ignoring its unit
+pub fn magnitude(value: &Value) -> f64 {
+    match value {
+        Value::Complex(z) => z.abs(),
+        Value::Quantity(q) => magnitude(&q.value),
+        other => other.to_f64().abs(),
+    }
+}
+
+// A zero of the same kind, and with the same unit, as `value`
+fn zero_like(ctx: &Context, value: &Value) -> Result<Value, CalcError> {
+    ctx.operate(BinOp::Sub, value.clone(), value.clone())
+}
+
+// The context elimination runs in. Decimal mode gets guard digits, so the
+// many steps round once at the end rather than each time. Integer division
+// truncates, which would wreck elimination, so integer mode works with
+// exact fractions and the caller's `fit` checks the result.
+pub fn working(ctx: &Context) -> Cow<'_, Context> {
+    let settings = match ctx.settings.numbers {
+        NumberMode::Float => return Cow::Borrowed(ctx),
+        NumberMode::Decimal => Settings {
+            decimal: ctx.settings.decimal.extended(GUARD_DIGITS),
+            ..ctx.settings
+        },
+        NumberMode::Integer(_) => Settings {
+            numbers: NumberMode::Float,
+            ..ctx.settings
+        },
+    };
+    Cow::Owned(Context {
+        settings,
+        rates: ctx.rates.clone(),
+        interrupt: ctx.interrupt.clone(),
+        ..Context::default()
+    })
+}
+
+// A result from the `working` context, rounded back to the session's digits
+pub fn finish(ctx: &Context, value: Value) -> Value {
+    match value {
+        Value::Decimal(d) => Value::Decimal(d.round(ctx.settings.decimal)),
+        Value::Quantity(mut q) => {
+            q.value = finish(ctx, q.value);
+            Value::Quantity(q)
+        }
+        other => other,
+    }
+}
+
+fn rows(m: &Matrix) -> Vec<Vec<Value>> {
+    m.elems.chunks(m.cols).map(<[Value]>::to_vec).collect()
+}
+
+fn square(m: &Matrix, what: &str) -> Result<(), CalcError> {
+    if m.vector || m.rows != m.cols {
+        return Err(CalcError::Domain {
+            message: format!("{} needs a square matrix (got a {})", what, m.shape()),
+            span: Span::default(),
+        });
+    }
+    Ok(())
+}
+
+fn verb(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "add",
+        BinOp::Sub => "subtract",
+        BinOp::Mul => "multiply",
+        BinOp::Div => "divide",
+        BinOp::Pow => "take powers of",
+        BinOp::FloorDiv | BinOp::Mod => "take the remainder of",
+    }
+}
+
+fn mismatch(operation: &str, a: &Matrix, b: &Matrix) -> CalcError {
+    CalcError::ShapeMismatch {
+        operation: operation.to_string(),
+        left: a.shape(),
+        right: b.shape(),
+        span: Span::default(),
+    }
+}
+
+// Dividing by a matrix, or any other operator that needs a plain number
+// on its right
+fn matrix_on_right(op: BinOp, m: &Matrix) -> CalcError {
+    let message = if op == BinOp::Div {
+        format!(
+            "Can't divide by a {}; multiply by its inverse() instead, or use ./ to divide element by element",
+            m.shape()
+        )
+    } else {
+        format!(
+            "'{}' needs a plain number on its right (got a {})",
+            op.symbol(),
+            m.shape()
+        )
+    };
+    CalcError::Domain {
+        message,
+        span: Span::default(),
+    }
+}
+
+// A value for a message: "a 2x2 matrix", or the number itself
+pub fn describe(value: &Value) -> String {
+    match value {
+        Value::Matrix(m) => format!("a {}", m.shape()),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(ctx: &Context, text: &str) -> Matrix {
+        match ctx.evaluate(text) {
+            Ok(Value::Matrix(m)) => *m,
+            other => panic!("{} is not a matrix: {:?}", text, other),
+        }
+    }
+
+    fn message(result: Result<impl fmt::Debug, CalcError>) -> String {
+        match result {
+            Err(error @ CalcError::Singular { .. }) => error.to_string(),
+            other => panic!("expected a singular matrix, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn exact_inverse_and_determinant() {
+        let ctx = Context::new();
+        let m = matrix(&ctx, "[[2, 1], [1, 3]]");
+        let expected = matrix(&ctx, "[[3/5, -1/5], [-1/5, 2/5]]");
+        assert_eq!(inverse(&ctx, &m).unwrap(), expected);
+        assert_eq!(determinant(&ctx, &m).unwrap().to_string(), "5");
+        // The zero pivot forces a row swap, which flips the sign
+        let swap = matrix(&ctx, "[[0, 1], [1, 0]]");
+        assert_eq!(determinant(&ctx, &swap).unwrap().to_string(), "-1");
+    }
+
+    #[test]
+    fn singular_matrices() {
+        let ctx = Context::new();
+        let m = matrix(&ctx, "[[1, 2], [2, 4]]");
+        assert_eq!(
+            message(inverse(&ctx, &m)),
+            "[[1, 2], [2, 4]] is singular (its determinant is 0), so it has no inverse"
+        );
+        assert_eq!(determinant(&ctx, &m).unwrap().to_string(), "0");
+        // 0.3 and 0.6 aren't exactly 3 x 0.1 and 3 x 0.2 as floats, but
+        // what's left of the second row is rounding noise
+        let float = matrix(&ctx, "[[0.1, 0.2], [0.3, 0.6]]");
+        assert!(matches!(
+            inverse(&ctx, &float),
+            Err(CalcError::Singular { .. })
+        ));
+        assert_eq!(Echelon::reduce(&ctx, rows(&float), 2).unwrap().pivots, [0]);
+    }
+
+    #[test]
+    fn solving_systems() {
+        let ctx = Context::new();
+        let solve_with = |a: &str, b: &str| {
+            solve(&ctx, &matrix(&ctx, a), &matrix(&ctx, b)).map(|x| x.to_string())
+        };
+        assert_eq!(
+            solve_with("[[2, 1], [1, 3]]", "[3, 5]").unwrap(),
+            "[4/5, 7/5]"
+        );
+        assert_eq!(solve_with("[[0, 1], [1, 0]]", "[2, 3]").unwrap(), "[3, 2]");
+        // More equations than unknowns is fine while they agree
+        assert_eq!(
+            solve_with("[[1, 0], [0, 1], [1, 1]]", "[1, 2, 3]").unwrap(),
+            "[1, 2]"
+        );
+        assert_eq!(
+            message(solve_with("[[1, 1], [2, 2]]", "[1, 3]")),
+            "The equations contradict each other, so there is no solution"
+        );
+    }
+
+    #[test]
+    fn rank_deficient_systems_describe_every_solution() {
+        let ctx = Context::new();
+        let solve_with = |a: &str, b: &str| solve(&ctx, &matrix(&ctx, a), &matrix(&ctx, b));
+        assert_eq!(
+            message(solve_with("[[1, 1, 1], [2, 2, 2]]", "[1, 2]")),
+            "There are infinitely many solutions: x2 and x3 can be anything, \
+             and x1 = 1 - x2 - x3"
+        );
+        assert_eq!(
+            message(solve_with("[[1, 1, 1], [2, 2, 2], [1, 2, 3]]", "[1, 2, 2]")),
+            "There are infinitely many solutions: x3 can be anything, \
+             and x1 = x3 and x2 = 1 - 2 x3"
+        );
+    }
+
+    #[test]
+    fn decimal_mode_rounds_to_its_precision() {
+        let mut ctx = Context::new();
+        ctx.settings.numbers = NumberMode::Decimal;
+        ctx.settings.decimal.precision = 5;
+        let inverse = inverse(&ctx, &matrix(&ctx, "[[3]]")).unwrap();
+        assert_eq!(inverse.get(0, 0).to_string(), "0.33333");
+    }
+
+    #[test]
+    fn powers() {
+        let ctx = Context::new();
+        let whole = |n: i64| ctx.whole_value(&BigInt::from(n));
+        let shear = matrix(&ctx, "[[1, 1], [0, 1]]");
+        let expected = matrix(&ctx, "[[1, 1000], [0, 1]]");
+        assert_eq!(power(&ctx, shear, &whole(1000)).unwrap(), expected);
+        let m = matrix(&ctx, "[[2, 1], [1, 3]]");
+        let inverse_squared = matrix(&ctx, "[[2/5, -1/5], [-1/5, 1/5]]");
+        assert_eq!(power(&ctx, m, &whole(-2)).unwrap(), inverse_squared);
+        // Fibonacci numbers: F(10^9) has about 209 million digits
+        let fibonacci = matrix(&ctx, "[[1, 1], [1, 0]]");
+        assert!(matches!(
+            power(&ctx, fibonacci, &whole(1_000_000_000)),
+            Err(CalcError::Overflow { .. })
+        ));
+    }
+
+    #[test]
+    fn oversized_products() {
+        let ctx = Context::new();
+        let started = std::time::Instant::now();
+        assert_eq!(
+            ctx.evaluate("identity(1000) * identity(1000)")
+                .unwrap_err()
+                .to_string(),
+            "Multiplying a 1000x1000 matrix by a 1000x1000 matrix takes 1000000000 \
+             multiplications, more than the limit of 2000000"
+        );
+        assert!(started.elapsed().as_secs() < 5);
+        assert!(ctx.evaluate("identity(50) * identity(50)").is_ok());
+    }
+}
@@ -0,0 +1,1091 @@
+// The parser turns the token list into a tree (AST) that encodes precedence.
+// "1 + 2 * 3" becomes Add(1, Mul(2, 3)), so evaluation order is explicit.
+//
+// Grammar, from lowest to highest precedence:
+//   stmt    := name '(' params ')' '=' expr | name '=' expr | expr
+//   expr    := bitor (('in' | 'to') unit)*    // 5 km + 300 m in mi converts the sum
+//   bitor   := bitxor ('|' bitxor)*
+//   bitxor  := bitand ('xor' bitand)*
+//   bitand  := shift ('&' shift)*
+//   shift   := sum (('<<' | '>>') sum)*       // as in C and Python: 1 << 2 + 1 is 1 << 3
+//   sum     := term (('+' | '-') term)*
+//   term    := unary (('*' | '/' | '//' | '%' | '.*' | './') unary)*
+//                                          // number '/' number unit is a fraction: 1/3 m
+//   unary   := '-' unary | '~' unary | power
+//   power   := postfix (('^' | '.^') unary)?  // right-associative: 2^3^2 = 2^(3^2)
+//            | number power                // with a name right after it: 2x^2 = 2 * x^2
+//   postfix := primary '!'* unit?          // so -3! = -(3!) and 2^3! = 2^(3!); x km, (1/3) m
+//   primary := number | number angle-unit | number? 'i' | name
+//            | name '(' args ')' | '$' digits | '(' expr ')'
+//            | '[' expr (',' expr)* ']'   // a vector, or a matrix when the elements are vectors
+//   args    := arg (',' arg)*
+//   arg     := expr ('=' expr)?            // an equation, for solve(2x + y = 3, x - y = 0)
+
+use std::fmt;
+
+use crate::angle::AngleMode;
+use crate::error::{CalcError, Span};
+use crate::lexer::{Token, TokenKind};
+use crate::units;
+
+// A unit as written, as names with powers: km/h is [("km", 1), ("h", -1)]
+pub type UnitSpec = Vec<(String, i32)>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    // `//`: divide and round down, 7 // 2 = 3
+    FloorDiv,
+    Mod,
+    Pow,
+}
+
+// Operators on the bits of whole numbers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+// A node of the tree plus the columns of the input it came from,
+// so the evaluator can point at e.g. the exact divisor that was zero.
+// Clone, PartialEq and Drop are written out below to walk chains with a
+// loop; see `Expr::chain`.
+#[derive(Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    // The literal as written, e.g. "0.10" or "6.02e23"
+    Number(String),
+    Angle(String, AngleMode),
+    // `2i`, or just `i` when the text is empty
+    Imaginary(String),
+    Ident(String),
+    HistoryRef(usize),
+    Call(String, Vec<Expr>),
+    // A number with a unit, like `5 km` or `9.81 m/s^2`
+    Quantity(Box<Expr>, UnitSpec),
+    // `expr in unit` or `expr to unit`
+    Convert(Box<Expr>, UnitSpec),
+    Neg(Box<Expr>),
+    // `~x`, bitwise not
+    BitNot(Box<Expr>),
+    Factorial(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Bitwise(BitOp, Box<Expr>, Box<Expr>),
+    // `.*`, `./` and `.^`: Mul, Div or Pow on matching elements
+    Elementwise(BinOp, Box<Expr>, Box<Expr>),
+    // `[1, 2, 3]`, or `[[1, 2], [3, 4]]` when the elements are rows
+    Matrix(Vec<Expr>),
+    // `lhs = rhs`, only meaningful as an argument of solve()
+    Equation(Box<Expr>, Box<Expr>),
+}
+
+// A full input line: a bare expression, `name = expression`
+// or a function definition like `f(x, y) = x^2 + y`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Expr(Expr),
+    Assign {
+        name: String,
+        name_span: Span,
+        value: Expr,
+    },
+    Define {
+        name: String,
+        name_span: Span,
+        params: Vec<String>,
+        body: Expr,
+    },
+}
+
+// How deeply brackets, calls, signs and exponents may nest. Parsing and
+// evaluating recurse once per level, so without a limit a few thousand '('
+// would overflow the stack and crash. Chains like 1 + 2 + 3 don't count:
+// they are parsed and walked with loops, however long they are.
+const MAX_NESTING: usize = 100;
+
+pub fn parse(tokens: &[Token]) -> Result<Statement, CalcError> {
+    Parser::new(tokens).finish(Parser::statement)
+}
+
+// Like `parse`, but only accepts a bare expression (no `x = ...` or `f(x) = ...`)
+pub fn parse_expression(tokens: &[Token]) -> Result<Expr, CalcError> {
+    Parser::new(tokens).finish(Parser::expr)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    end: usize,
+    // How many of the rules that recurse are in progress; see `nested`
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: &'a [Token]) -> Self {
+        // Where "the end of the input" is, for errors about missing tokens
+        let end = tokens.last().map_or(0, |t| t.span.end);
+        Parser {
+            tokens,
+            pos: 0,
+            end,
+            depth: 0,
+        }
+    }
+
+    // Runs `rule` one level deeper, for the '(' '[', sign or '^' at `open`
+    fn nested<T>(
+        &mut self,
+        open: Span,
+        rule: impl FnOnce(&mut Self) -> Result<T, CalcError>,
+    ) -> Result<T, CalcError> {
+        if self.depth >= MAX_NESTING {
+            return Err(CalcError::NestingLimit {
+                limit: MAX_NESTING,
+                span: open,
+            });
+        }
+        self.depth += 1;
+        let result = rule(self);
+        self.depth -= 1;
+        result
+    }
+
+    // Runs one grammar rule and checks that it consumed every token
+    fn finish<T>(mut self, rule: fn(&mut Self) -> Result<T, CalcError>) -> Result<T, CalcError> {
+        let result = rule(&mut self)?;
+        // Anything left over means the input had trailing junk like "1 + 2 )"
+        if let Some(token) = self.peek() {
+            return Err(CalcError::UnexpectedToken {
+                found: describe(&token.kind),
+                expected: None,
+                span: token.span,
+            });
+        }
+        Ok(result)
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    // Consumes the next token if it matches `kind`
+    fn eat(&mut self, kind: &TokenKind) -> Option<&'a Token> {
+        let token = self.peek().filter(|t| &t.kind == kind)?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn expect(&mut self, kind: &TokenKind) -> Result<&'a Token, CalcError> {
+        match self.next() {
+            Some(token) if &token.kind == kind => Ok(token),
+            Some(token) => Err(CalcError::UnexpectedToken {
+                found: describe(&token.kind),
+                expected: Some(describe(kind)),
+                span: token.span,
+            }),
+            None => Err(self.unexpected_end(&describe(kind))),
+        }
+    }
+
+    fn unexpected_end(&self, expected: &str) -> CalcError {
+        CalcError::UnexpectedEnd {
+            expected: expected.to_string(),
+            span: Span::new(self.end, self.end + 1),
+        }
+    }
+
+    fn statement(&mut self) -> Result<Statement, CalcError> {
+        if let [first, second, ..] = &self.tokens[self.pos..]
+            && let TokenKind::Ident(name) = &first.kind
+        {
+            // "name =" starts an assignment
+            if second.kind == TokenKind::Equals {
+                self.pos += 2;
+                let value = self.expr()?;
+                return Ok(Statement::Assign {
+                    name: name.clone(),
+                    name_span: first.span,
+                    value,
+                });
+            }
+            // "name(...) =" starts a function definition
+            if second.kind == TokenKind::LParen && self.is_definition() {
+                self.pos += 2;
+                let params = self.params()?;
+                self.expect(&TokenKind::Equals)?;
+                let body = self.expr()?;
+                return Ok(Statement::Define {
+                    name: name.clone(),
+                    name_span: first.span,
+                    params,
+                    body,
+                });
+            }
+        }
+        Ok(Statement::Expr(self.expr()?))
+    }
+
+    // True when the parentheses after the name are followed by '='.
+    // Nothing is consumed, so a plain call like "f(2) + 1" still parses as an expression.
+    fn is_definition(&self) -> bool {
+        let mut depth = 0;
+        for (i, token) in self.tokens.iter().enumerate().skip(self.pos + 1) {
+            match token.kind {
+                TokenKind::LParen => depth += 1,
+                TokenKind::RParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return self
+                            .tokens
+                            .get(i + 1)
+                            .is_some_and(|t| t.kind == TokenKind::Equals);
+                    }
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    // Parses "x, y)" after the opening parenthesis of a definition
+    fn params(&mut self) -> Result<Vec<String>, CalcError> {
+        let mut params: Vec<String> = Vec::new();
+        if self.eat(&TokenKind::RParen).is_some() {
+            return Ok(params);
+        }
+        loop {
+            match self.next() {
+                Some(Token {
+                    kind: TokenKind::Ident(name),
+                    span,
+                }) => {
+                    if params.contains(name) {
+                        return Err(CalcError::DuplicateParam {
+                            name: name.clone(),
+                            span: *span,
+                        });
+                    }
+                    params.push(name.clone());
+                }
+                Some(token) => {
+                    return Err(CalcError::UnexpectedToken {
+                        found: describe(&token.kind),
+                        expected: Some("a parameter name".to_string()),
+                        span: token.span,
+                    });
+                }
+                None => return Err(self.unexpected_end("a parameter name")),
+            }
+            if self.eat(&TokenKind::RParen).is_some() {
+                return Ok(params);
+            }
+            self.expect(&TokenKind::Comma)?;
+        }
+    }
+
+    // Parses "1, 2 + 3)" after the opening parenthesis of a call,
+    // returning the arguments and the span of the closing ')'
+    fn args(&mut self) -> Result<(Vec<Expr>, Span), CalcError> {
+        let mut args = Vec::new();
+        if let Some(close) = self.eat(&TokenKind::RParen) {
+            return Ok((args, close.span));
+        }
+        loop {
+            args.push(self.arg()?);
+            if let Some(close) = self.eat(&TokenKind::RParen) {
+                return Ok((args, close.span));
+            }
+            self.expect(&TokenKind::Comma)?;
+        }
+    }
+
+    // One call argument, which may be an equation like "2x + y = 3"
+    fn arg(&mut self) -> Result<Expr, CalcError> {
+        let lhs = self.expr()?;
+        if self.eat(&TokenKind::Equals).is_none() {
+            return Ok(lhs);
+        }
+        let rhs = self.expr()?;
+        let span = lhs.span.to(rhs.span);
+        Ok(Expr::new(
+            ExprKind::Equation(Box::new(lhs), Box::new(rhs)),
+            span,
+        ))
+    }
+
+    fn expr(&mut self) -> Result<Expr, CalcError> {
+        let mut lhs = self.bit_or()?;
+        while let Some(Token {
+            kind: TokenKind::Ident(word),
+            ..
+        }) = self.peek()
+            && (word == "in" || word == "to")
+        {
+            self.pos += 1;
+            let (unit, unit_span) = self.unit()?;
+            let span = lhs.span.to(unit_span);
+            lhs = Expr::new(ExprKind::Convert(Box::new(lhs), unit), span);
+        }
+        Ok(lhs)
+    }
+
+    fn bit_or(&mut self) -> Result<Expr, CalcError> {
+        let mut lhs = self.bit_xor()?;
+        while self.eat(&TokenKind::Pipe).is_some() {
+            let rhs = self.bit_xor()?;
+            lhs = Expr::bitwise(BitOp::Or, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, CalcError> {
+        let mut lhs = self.bit_and()?;
+        while self.eat(&TokenKind::Ident("xor".to_string())).is_some() {
+            let rhs = self.bit_and()?;
+            lhs = Expr::bitwise(BitOp::Xor, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, CalcError> {
+        let mut lhs = self.shift()?;
+        while self.eat(&TokenKind::Amp).is_some() {
+            let rhs = self.shift()?;
+            lhs = Expr::bitwise(BitOp::And, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn shift(&mut self) -> Result<Expr, CalcError> {
+        let mut lhs = self.sum()?;
+        loop {
+            let op = if self.eat(&TokenKind::ShiftLeft).is_some() {
+                BitOp::ShiftLeft
+            } else if self.eat(&TokenKind::ShiftRight).is_some() {
+                BitOp::ShiftRight
+            } else {
+                break;
+            };
+            let rhs = self.sum()?;
+            lhs = Expr::bitwise(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn sum(&mut self) -> Result<Expr, CalcError> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = if self.eat(&TokenKind::Plus).is_some() {
+                BinOp::Add
+            } else if self.eat(&TokenKind::Minus).is_some() {
+                BinOp::Sub
+            } else {
+                break;
+            };
+            let rhs = self.term()?;
+            lhs = Expr::binary(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expr, CalcError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let elementwise = if self.eat(&TokenKind::DotStar).is_some() {
+                Some(BinOp::Mul)
+            } else if self.eat(&TokenKind::DotSlash).is_some() {
+                Some(BinOp::Div)
+            } else {
+                None
+            };
+            if let Some(op) = elementwise {
+                let rhs = self.unary()?;
+                lhs = Expr::elementwise(op, lhs, rhs);
+                continue;
+            }
+            let op = if self.eat(&TokenKind::Star).is_some() {
+                BinOp::Mul
+            } else if self.eat(&TokenKind::Slash).is_some() {
+                BinOp::Div
+            } else if self.eat(&TokenKind::SlashSlash).is_some() {
+                BinOp::FloorDiv
+            } else if self.eat(&TokenKind::Percent).is_some() {
+                BinOp::Mod
+            } else {
+                break;
+            };
+            let mut rhs = self.unary()?;
+            // 1/3 m is a third of a metre, as on paper, not 1/(3 m)
+            let fraction = op == BinOp::Div
+                && matches!(lhs.kind, ExprKind::Number(_))
+                && matches!(&rhs.kind, ExprKind::Quantity(number, _)
+                    if matches!(number.kind, ExprKind::Number(_)));
+            if fraction {
+                let span = lhs.span.to(rhs.span);
+                let placeholder = ExprKind::HistoryRef(0);
+                let ExprKind::Quantity(denominator, unit) =
+                    std::mem::replace(&mut rhs.kind, placeholder)
+                else {
+                    unreachable!("checked above");
+                };
+                let number = Expr::binary(BinOp::Div, lhs, *denominator);
+                lhs = Expr::new(ExprKind::Quantity(Box::new(number), unit), span);
+                continue;
+            }
+            lhs = Expr::binary(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, CalcError> {
+        if let Some(minus) = self.eat(&TokenKind::Minus) {
+            let operand = self.nested(minus.span, Self::unary)?;
+            let span = minus.span.to(operand.span);
+            return Ok(Expr::new(ExprKind::Neg(Box::new(operand)), span));
+        }
+        if let Some(tilde) = self.eat(&TokenKind::Tilde) {
+            let operand = self.nested(tilde.span, Self::unary)?;
+            let span = tilde.span.to(operand.span);
+            return Ok(Expr::new(ExprKind::BitNot(Box::new(operand)), span));
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Expr, CalcError> {
+        let base = self.postfix()?;
+        // 2x is 2 * x, and 2x^2 is 2 * x^2 as on paper
+        if matches!(base.kind, ExprKind::Number(_)) && self.name_attached(base.span) {
+            let factor = self.power()?;
+            return Ok(Expr::binary(BinOp::Mul, base, factor));
+        }
+        if let Some(caret) = self.eat(&TokenKind::Caret) {
+            // Recursing into `unary` (not `power`) allows "2 ^ -1"
+            // and makes the operator right-associative.
+            let exponent = self.nested(caret.span, Self::unary)?;
+            return Ok(Expr::binary(BinOp::Pow, base, exponent));
+        }
+        if let Some(caret) = self.eat(&TokenKind::DotCaret) {
+            let exponent = self.nested(caret.span, Self::unary)?;
+            return Ok(Expr::elementwise(BinOp::Pow, base, exponent));
+        }
+        Ok(base)
+    }
+
+    fn postfix(&mut self) -> Result<Expr, CalcError> {
+        let mut operand = self.primary()?;
+        while let Some(bang) = self.eat(&TokenKind::Bang) {
+            let span = operand.span.to(bang.span);
+            operand = Expr::new(ExprKind::Factorial(Box::new(operand)), span);
+        }
+        if !self.unit_ahead(0) {
+            return Ok(operand);
+        }
+        let (unit, unit_span) = self.unit()?;
+        let span = operand.span.to(unit_span);
+        Ok(Expr::new(ExprKind::Quantity(Box::new(operand), unit), span))
+    }
+
+    // Whether a name follows `span` with no space in between, like the x
+    // of 2x. Units never get here: `primary` has made 5km a quantity.
+    fn name_attached(&self, span: Span) -> bool {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Ident(name),
+                span: next,
+            }) => next.start == span.end && !matches!(name.as_str(), "in" | "to" | "xor"),
+            _ => false,
+        }
+    }
+
+    // Whether the token `offset` places ahead names a unit, and isn't a
+    // call of a function with the same name like min(...)
+    fn unit_ahead(&self, offset: usize) -> bool {
+        let at = |i: usize| self.tokens.get(self.pos + i).map(|t| &t.kind);
+        match at(offset) {
+            Some(TokenKind::Ident(name)) => {
+                units::lookup(name).is_some() && at(offset + 1) != Some(&TokenKind::LParen)
+            }
+            _ => false,
+        }
+    }
+
+    // An angle unit standing alone after a number, unless it is being called
+    fn angle_unit_ahead(&self) -> Option<(AngleMode, Span)> {
+        let token = self.peek()?;
+        let TokenKind::Ident(ref name) = token.kind else {
+            return None;
+        };
+        let called = self.tokens.get(self.pos + 1).map(|t| &t.kind) == Some(&TokenKind::LParen);
+        AngleMode::parse(name)
+            .filter(|_| !called)
+            .map(|unit| (unit, token.span))
+    }
+
+    // Parses a unit like "km", "m/s^2" or "kg*m^2/s^2". An operator only
+    // continues the unit when a unit follows it, so `5 m / 2` divides by 2.
+    fn unit(&mut self) -> Result<(UnitSpec, Span), CalcError> {
+        let mut terms = Vec::new();
+        let mut sign = 1;
+        let mut span: Option<Span> = None;
+        loop {
+            let Some(token) = self.next() else {
+                return Err(self.unexpected_end("a unit"));
+            };
+            let TokenKind::Ident(ref name) = token.kind else {
+                return Err(CalcError::UnexpectedToken {
+                    found: describe(&token.kind),
+                    expected: Some("a unit".to_string()),
+                    span: token.span,
+                });
+            };
+            if units::lookup(name).is_none() {
+                return Err(CalcError::UnknownUnit {
+                    name: name.clone(),
+                    span: token.span,
+                });
+            }
+            let mut end = token.span;
+            let mut power = 1;
+            if self.eat(&TokenKind::Caret).is_some() {
+                let negative = self.eat(&TokenKind::Minus).is_some();
+                let Some(exponent) = self.next() else {
+                    return Err(self.unexpected_end("a whole-number power"));
+                };
+                power = match &exponent.kind {
+                    TokenKind::Number(text) => match text.parse::<i32>() {
+                        Ok(n) if n <= i8::MAX as i32 => n,
+                        _ => {
+                            return Err(CalcError::InvalidNumber {
+                                text: text.clone(),
+                                span: exponent.span,
+                            });
+                        }
+                    },
+                    kind => {
+                        return Err(CalcError::UnexpectedToken {
+                            found: describe(kind),
+                            expected: Some("a whole-number power".to_string()),
+                            span: exponent.span,
+                        });
+                    }
+                };
+                if negative {
+                    power = -power;
+                }
+                end = exponent.span;
+            }
+            terms.push((name.clone(), sign * power));
+            let term_span = token.span.to(end);
+            span = Some(span.map_or(term_span, |span| span.to(term_span)));
+
+            sign = match self.peek().map(|t| &t.kind) {
+                Some(TokenKind::Star) if self.unit_ahead(1) => 1,
+                Some(TokenKind::Slash) if self.unit_ahead(1) => -1,
+                _ => break,
+            };
+            self.pos += 1;
+        }
+        Ok((terms, span.unwrap_or_default()))
+    }
+
+    fn primary(&mut self) -> Result<Expr, CalcError> {
+        let Some(token) = self.next() else {
+            return Err(self.unexpected_end("a number, '(' or '['"));
+        };
+        let kind = match token.kind {
+            TokenKind::Number(ref text) => {
+                // "30 deg" means the same as "30deg"
+                if let Some((unit, end)) = self.angle_unit_ahead() {
+                    self.pos += 1;
+                    let angle = ExprKind::Angle(text.clone(), unit);
+                    return Ok(Expr::new(angle, token.span.to(end)));
+                }
+                ExprKind::Number(text.clone())
+            }
+            TokenKind::Angle(ref text, unit) => ExprKind::Angle(text.clone(), unit),
+            TokenKind::Imaginary(ref text) => ExprKind::Imaginary(text.clone()),
+            TokenKind::Ident(ref name) => {
+                if let Some(open) = self.eat(&TokenKind::LParen) {
+                    let (args, close) = self.nested(open.span, Self::args)?;
+                    let call = ExprKind::Call(name.clone(), args);
+                    return Ok(Expr::new(call, token.span.to(close)));
+                }
+                ExprKind::Ident(name.clone())
+            }
+            TokenKind::HistoryRef(index) => ExprKind::HistoryRef(index),
+            TokenKind::LParen => {
+                let mut inner = self.nested(token.span, Self::expr)?;
+                let Some(close) = self.eat(&TokenKind::RParen) else {
+                    return Err(match self.peek() {
+                        Some(next) => CalcError::UnexpectedToken {
+                            found: describe(&next.kind),
+                            expected: Some("')'".to_string()),
+                            span: next.span,
+                        },
+                        None => CalcError::UnclosedParen { span: token.span },
+                    });
+                };
+                // Widen the span to include the parentheses themselves
+                inner.span = token.span.to(close.span);
+                return Ok(inner);
+            }
+            TokenKind::LBracket => {
+                let (elements, close) = self.nested(token.span, |parser| {
+                    let mut elements = vec![parser.expr()?];
+                    while parser.eat(&TokenKind::Comma).is_some() {
+                        elements.push(parser.expr()?);
+                    }
+                    Ok((elements, parser.expect(&TokenKind::RBracket)?))
+                })?;
+                return Ok(Expr::new(
+                    ExprKind::Matrix(elements),
+                    token.span.to(close.span),
+                ));
+            }
+            ref kind => {
+                return Err(CalcError::UnexpectedToken {
+                    found: describe(kind),
+                    expected: None,
+                    span: token.span,
+                });
+            }
+        };
+        Ok(Expr::new(kind, token.span))
+    }
+}
+
+fn describe(kind: &TokenKind) -> String {
+    match kind {
+        TokenKind::Number(n) => format!("number {}", n),
+        TokenKind::Angle(n, unit) => format!("angle {}{}", n, unit),
+        TokenKind::Imaginary(n) => format!("imaginary number {}i", n),
+        TokenKind::Ident(name) => format!("name '{}'", name),
+        TokenKind::HistoryRef(index) => format!("'${}'", index),
+        TokenKind::Plus => "'+'".to_string(),
+        TokenKind::Minus => "'-'".to_string(),
+        TokenKind::Star => "'*'".to_string(),
+        TokenKind::Slash => "'/'".to_string(),
+        TokenKind::SlashSlash => "'//'".to_string(),
+        TokenKind::Percent => "'%'".to_string(),
+        TokenKind::Caret => "'^'".to_string(),
+        TokenKind::Bang => "'!'".to_string(),
+        TokenKind::Amp => "'&'".to_string(),
+        TokenKind::Pipe => "'|'".to_string(),
+        TokenKind::Tilde => "'~'".to_string(),
+        TokenKind::ShiftLeft => "'<<'".to_string(),
+        TokenKind::ShiftRight => "'>>'".to_string(),
+        TokenKind::DotStar => "'.*'".to_string(),
+        TokenKind::DotSlash => "'./'".to_string(),
+        TokenKind::DotCaret => "'.^'".to_string(),
+        TokenKind::LParen => "'('".to_string(),
+        TokenKind::RParen => "')'".to_string(),
+        TokenKind::LBracket => "'['".to_string(),
+        TokenKind::RBracket => "']'".to_string(),
+        TokenKind::Equals => "'='".to_string(),
+        TokenKind::Comma => "','".to_string(),
+    }
+}
+
+impl BinOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::FloorDiv => "//",
+            BinOp::Mod => "%",
+            BinOp::Pow => "^",
+        }
+    }
+
+    // Higher binds tighter; matches the grammar at the top of this file
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Add | BinOp::Sub => 5,
+            BinOp::Mul | BinOp::Div | BinOp::FloorDiv | BinOp::Mod => 6,
+            BinOp::Pow => 8,
+        }
+    }
+}
+
+impl BitOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BitOp::And => "&",
+            BitOp::Or => "|",
+            BitOp::Xor => "xor",
+            BitOp::ShiftLeft => "<<",
+            BitOp::ShiftRight => ">>",
+        }
+    }
+
+    // Below + and -, as in C and Python
+    fn precedence(self) -> u8 {
+        match self {
+            BitOp::Or => 1,
+            BitOp::Xor => 2,
+            BitOp::And => 3,
+            BitOp::ShiftLeft | BitOp::ShiftRight => 4,
+        }
+    }
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
+
+    fn binary(op: BinOp, lhs: Expr, rhs: Expr) -> Self {
+        let span = lhs.span.to(rhs.span);
+        Expr::new(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), span)
+    }
+
+    fn bitwise(op: BitOp, lhs: Expr, rhs: Expr) -> Self {
+        let span = lhs.span.to(rhs.span);
+        Expr::new(ExprKind::Bitwise(op, Box::new(lhs), Box::new(rhs)), span)
+    }
+
+    fn elementwise(op: BinOp, lhs: Expr, rhs: Expr) -> Self {
+        let span = lhs.span.to(rhs.span);
+        Expr::new(
+            ExprKind::Elementwise(op, Box::new(lhs), Box::new(rhs)),
+            span,
+        )
+    }
+
+    // The left operand when this node continues a chain like 1 + 2 + 3 or
+    // 5 km in m in ft
+    fn chain_lhs(&self) -> Option<&Expr> {
+        match &self.kind {
+            ExprKind::Binary(_, lhs, _)
+            | ExprKind::Bitwise(_, lhs, _)
+            | ExprKind::Elementwise(_, lhs, _)
+            | ExprKind::Convert(lhs, _) => Some(lhs),
+            _ => None,
+        }
+    }
+
+    // This node and the links below it down the left side, top first, and
+    // the operand the chain starts with. A chain's tree is as deep as the
+    // chain is long, so code that walks trees goes through this with a loop
+    // instead of recursing into left operands.
+    pub fn chain(&self) -> (Vec<&Expr>, &Expr) {
+        let mut links = Vec::new();
+        let mut first = self;
+        while let Some(lhs) = first.chain_lhs() {
+            links.push(first);
+            first = lhs;
+        }
+        (links, first)
+    }
+
+    // The plain names the expression uses, in order of first use. Function
+    // names don't count, but bare units like the km of `2 * km` do.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        self.visit(|expr| {
+            if let ExprKind::Ident(name) = &expr.kind
+                && !names.contains(name)
+            {
+                names.push(name.clone());
+            }
+        });
+        names
+    }
+
+    // The units written right after numbers, like the s of `2s`
+    pub fn quantity_units(&self) -> Vec<String> {
+        let mut units: Vec<String> = Vec::new();
+        self.visit(|expr| {
+            if let ExprKind::Quantity(_, spec) = &expr.kind {
+                for (name, _) in spec {
+                    if !units.contains(name) {
+                        units.push(name.clone());
+                    }
+                }
+            }
+        });
+        units
+    }
+
+    // Both of the above, in the order they first appear: x, s and t for
+    // `x + 2s = 3t`
+    pub fn names_and_units(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        self.visit(|expr| {
+            let found: Vec<&String> = match &expr.kind {
+                ExprKind::Ident(name) => vec![name],
+                ExprKind::Quantity(_, spec) => spec.iter().map(|(name, _)| name).collect(),
+                _ => Vec::new(),
+            };
+            for name in found {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        });
+        names
+    }
+
+    // Calls `visit` on every node, left operands before right ones
+    fn visit(&self, mut visit: impl FnMut(&Expr)) {
+        // Left operands go on top, so they are visited first
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            visit(expr);
+            match &expr.kind {
+                ExprKind::Call(_, exprs) | ExprKind::Matrix(exprs) => {
+                    pending.extend(exprs.iter().rev());
+                }
+                ExprKind::Quantity(operand, _)
+                | ExprKind::Convert(operand, _)
+                | ExprKind::Neg(operand)
+                | ExprKind::BitNot(operand)
+                | ExprKind::Factorial(operand) => pending.push(operand),
+                ExprKind::Binary(_, lhs, rhs)
+                | ExprKind::Bitwise(_, lhs, rhs)
+                | ExprKind::Elementwise(_, lhs, rhs)
+                | ExprKind::Equation(lhs, rhs) => {
+                    pending.push(rhs);
+                    pending.push(lhs);
+                }
+                ExprKind::Ident(_)
+                | ExprKind::Number(_)
+                | ExprKind::Angle(..)
+                | ExprKind::Imaginary(_)
+                | ExprKind::HistoryRef(_) => {}
+            }
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match &self.kind {
+            ExprKind::Binary(op, _, _) | ExprKind::Elementwise(op, _, _) => op.precedence(),
+            ExprKind::Bitwise(op, _, _) => op.precedence(),
+            ExprKind::Convert(..) | ExprKind::Equation(..) => 0,
+            ExprKind::Neg(_) | ExprKind::BitNot(_) | ExprKind::Quantity(..) => 7,
+            _ => 9,
+        }
+    }
+}
+
+impl Clone for Expr {
+    fn clone(&self) -> Self {
+        let (links, first) = self.chain();
+        let mut copy = Expr::new(first.kind.clone(), first.span);
+        for link in links.into_iter().rev() {
+            let lhs = Box::new(copy);
+            let kind = match &link.kind {
+                ExprKind::Binary(op, _, rhs) => ExprKind::Binary(*op, lhs, rhs.clone()),
+                ExprKind::Bitwise(op, _, rhs) => ExprKind::Bitwise(*op, lhs, rhs.clone()),
+                ExprKind::Elementwise(op, _, rhs) => ExprKind::Elementwise(*op, lhs, rhs.clone()),
+                ExprKind::Convert(_, unit) => ExprKind::Convert(lhs, unit.clone()),
+                _ => unreachable!("only chain links have a left operand"),
+            };
+            copy = Expr::new(kind, link.span);
+        }
+        copy
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        let (mut a, mut b) = (self, other);
+        loop {
+            if a.span != b.span {
+                return false;
+            }
+            let (next_a, next_b) = match (&a.kind, &b.kind) {
+                (
+                    ExprKind::Binary(op, lhs, rhs),
+                    ExprKind::Binary(other_op, other_lhs, other_rhs),
+                )
+                | (
+                    ExprKind::Elementwise(op, lhs, rhs),
+                    ExprKind::Elementwise(other_op, other_lhs, other_rhs),
+                ) => {
+                    if op != other_op || rhs != other_rhs {
+                        return false;
+                    }
+                    (lhs, other_lhs)
+                }
+                (
+                    ExprKind::Bitwise(op, lhs, rhs),
+                    ExprKind::Bitwise(other_op, other_lhs, other_rhs),
+                ) => {
+                    if op != other_op || rhs != other_rhs {
+                        return false;
+                    }
+                    (lhs, other_lhs)
+                }
+                (ExprKind::Convert(lhs, unit), ExprKind::Convert(other_lhs, other_unit)) => {
+                    if unit != other_unit {
+                        return false;
+                    }
+                    (lhs, other_lhs)
+                }
+                (kind, other_kind) => return kind == other_kind,
+            };
+            a = next_a;
+            b = next_b;
+        }
+    }
+}
+
+impl Drop for Expr {
+    // The boxes would otherwise be dropped recursively, one level per link
+    // of a chain. Each node taken off `pending` has its children moved out
+    // first, so dropping it never goes further down.
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        let mut kind = std::mem::replace(&mut self.kind, ExprKind::HistoryRef(0));
+        loop {
+            match kind {
+                ExprKind::Binary(_, lhs, rhs)
+                | ExprKind::Bitwise(_, lhs, rhs)
+                | ExprKind::Elementwise(_, lhs, rhs)
+                | ExprKind::Equation(lhs, rhs) => pending.extend([*lhs, *rhs]),
+                ExprKind::Quantity(operand, _)
+                | ExprKind::Convert(operand, _)
+                | ExprKind::Neg(operand)
+                | ExprKind::BitNot(operand)
+                | ExprKind::Factorial(operand) => pending.push(*operand),
+                ExprKind::Call(_, exprs) | ExprKind::Matrix(exprs) => pending.extend(exprs),
+                _ => {}
+            }
+            let Some(mut next) = pending.pop() else {
+                break;
+            };
+            kind = std::mem::replace(&mut next.kind, ExprKind::HistoryRef(0));
+        }
+    }
+}
+
+// Printing an Expr gives back source text, adding parentheses only where needed.
+// Used by `funcs` to show what a function was defined as.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // A chain is written from its first operand up, with the '(' of
+        // every link whose left side needs them all at the start
+        let (links, first) = self.chain();
+        for _ in links.iter().filter(|link| link.wraps_lhs()) {
+            write!(f, "(")?;
+        }
+        first.write_node(f)?;
+        for link in links.into_iter().rev() {
+            if link.wraps_lhs() {
+                write!(f, ")")?;
+            }
+            link.write_node(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Expr {
+    // Whether a chain link's left operand binds looser than the link, so
+    // it needs parentheses
+    fn wraps_lhs(&self) -> bool {
+        match &self.kind {
+            // '^' is right-associative, so it's the left side of 2^3 that
+            // needs them at equal precedence: (2^3)^2
+            ExprKind::Binary(BinOp::Pow, lhs, _) | ExprKind::Elementwise(BinOp::Pow, lhs, _) => {
+                lhs.precedence() <= BinOp::Pow.precedence()
+            }
+            ExprKind::Binary(op, lhs, _) | ExprKind::Elementwise(op, lhs, _) => {
+                lhs.precedence() < op.precedence()
+            }
+            ExprKind::Bitwise(op, lhs, _) => lhs.precedence() < op.precedence(),
+            ExprKind::Convert(lhs, _) => lhs.precedence() < 1,
+            _ => false,
+        }
+    }
+
+    // Writes one node; for a chain link, only what comes after its left
+    // operand, since `fmt` has written that already
+    fn write_node(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ExprKind::Number(n) => write!(f, "{}", n),
+            ExprKind::Angle(n, unit) => write!(f, "{}{}", n, unit),
+            ExprKind::Imaginary(n) => write!(f, "{}i", n),
+            ExprKind::Ident(name) => write!(f, "{}", name),
+            ExprKind::HistoryRef(index) => write!(f, "${}", index),
+            ExprKind::Call(name, args) => {
+                write!(f, "{}(", name)?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+            ExprKind::Matrix(elements) => {
+                write!(f, "[")?;
+                write_list(f, elements)?;
+                write!(f, "]")
+            }
+            ExprKind::Equation(lhs, rhs) => write!(f, "{} = {}", lhs, rhs),
+            ExprKind::Quantity(number, unit) => {
+                write_operand(f, "", number, 9)?;
+                write!(f, " {}", units::format_terms(unit))
+            }
+            ExprKind::Convert(_, unit) => write!(f, " in {}", units::format_terms(unit)),
+            ExprKind::Neg(operand) => write_operand(f, "-", operand, 7),
+            ExprKind::BitNot(operand) => write_operand(f, "~", operand, 7),
+            ExprKind::Factorial(operand) => {
+                write_operand(f, "", operand, 9)?;
+                write!(f, "!")
+            }
+            ExprKind::Binary(op, _, rhs) | ExprKind::Elementwise(op, _, rhs) => {
+                // Left-associative ops need parens on the right for equal
+                // precedence ("a - (b - c)"); '^' is right-associative
+                let prec = op.precedence();
+                let right_min = if *op == BinOp::Pow { prec } else { prec + 1 };
+                let dot = if matches!(self.kind, ExprKind::Elementwise(..)) {
+                    "."
+                } else {
+                    ""
+                };
+                write_operand(f, &format!(" {}{} ", dot, op.symbol()), rhs, right_min)
+            }
+            ExprKind::Bitwise(op, _, rhs) => {
+                write_operand(f, &format!(" {} ", op.symbol()), rhs, op.precedence() + 1)
+            }
+        }
+    }
+}
+
+// Writes "a, b, c", for call arguments and matrix elements
+fn write_list(f: &mut fmt::Formatter, exprs: &[Expr]) -> fmt::Result {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", expr)?;
+    }
+    Ok(())
+}
+
+// Writes `prefix` then `expr`, wrapped in parentheses if it binds looser than `min`
+fn write_operand(f: &mut fmt::Formatter, prefix: &str, expr: &Expr, min: u8) -> fmt::Result {
+    if expr.precedence() < min {
+        write!(f, "{}({})", prefix, expr)
+    } else {
+        write!(f, "{}{}", prefix, expr)
+    }
+}
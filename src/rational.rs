@@ -0,0 +1,436 @@
+// Exact fractions, so 1/3 + 1/6 is exactly 1/2 instead of 0.5000000000000001.
+// Whole-number literals start out as fractions over 1 and stay exact through
+// + - * / % and integer powers; anything inexact (sqrt(2), sin, 0.5)
+// falls back to f64.
+//
+// A Rational is always kept in lowest terms with a positive denominator,
+// so two equal values have identical numerators and denominators.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::bigint::BigInt;
+use crate::decimal::{Decimal, DecimalContext};
+
+// Exact powers with more digits than this fall back to f64, so a typo like
+// 7^123456789 doesn't hang the calculator
+const MAX_POW_DIGITS: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rational {
+    num: BigInt,
+    den: BigInt,
+}
+
+impl Rational {
+    // num / den in lowest terms; None if `den` is zero
+    pub fn new(num: BigInt, den: BigInt) -> Option<Self> {
+        if den.is_zero() {
+            return None;
+        }
+        let gcd = num.gcd(&den);
+        let (mut num, _) = num.div_rem(&gcd);
+        let (mut den, _) = den.div_rem(&gcd);
+        if den.is_negative() {
+            num = -&num;
+            den = -&den;
+        }
+        Some(Rational { num, den })
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        &self.num
+    }
+
+    pub fn denom(&self) -> &BigInt {
+        &self.den
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.num.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.num.is_negative()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.den == BigInt::one()
+    }
+
+    // 0, 1 or -1, whose powers never grow
+    fn is_unit(&self) -> bool {
+        self.is_integer() && self.num.abs() <= BigInt::one()
+    }
+
+    pub fn abs(&self) -> Rational {
+        Rational {
+            num: self.num.abs(),
+            den: self.den.clone(),
+        }
+    }
+
+    // 1 / self; None for zero
+    pub fn recip(&self) -> Option<Rational> {
+        Rational::new(self.den.clone(), self.num.clone())
+    }
+
+    // None when dividing by zero
+    pub fn checked_div(&self, other: &Rational) -> Option<Rational> {
+        Rational::new(&self.num * &other.den, &self.den * &other.num)
+    }
+
+    // floor(self / other), like `//` in Python. None when `other` is zero.
+    pub fn div_floor(&self, other: &Rational) -> Option<Rational> {
+        Some(self.checked_div(other)?.floor())
+    }
+
+    // The remainder of floored division, with the sign of `other`, for both
+    // % and mod(): 7/2 mod 1 = 1/2, -7 mod 3 = 2. None when `other` is zero.
+    pub fn modulo(&self, other: &Rational) -> Option<Rational> {
+        let quotient = self.div_floor(other)?;
+        Some(self - &(other * &quotient))
+    }
+
+    // self^exp for an integer exponent. None for 0 to a negative power,
+    // or when the result would be unreasonably large.
+    pub fn pow(&self, exp: i64) -> Option<Rational> {
+        // Each bit of the base adds log10(2) digits per power, so 2^200000
+        // (60206 digits) is fine and 7^1000000 is not
+        let bits = self.num.bits().max(self.den.bits());
+        let digits = bits * exp.unsigned_abs() as f64 * std::f64::consts::LOG10_2;
+        if digits > MAX_POW_DIGITS as f64 && !self.is_unit() {
+            return None;
+        }
+        let n = u32::try_from(exp.unsigned_abs()).ok()?;
+        let result = Rational {
+            num: self.num.pow(n),
+            den: self.den.pow(n),
+        };
+        if exp < 0 {
+            result.recip()
+        } else {
+            Some(result)
+        }
+    }
+
+    // The exact square root, if both parts are perfect squares
+    pub fn sqrt(&self) -> Option<Rational> {
+        if self.is_negative() {
+            return None;
+        }
+        let (num, den) = (self.num.sqrt(), self.den.sqrt());
+        if &num * &num == self.num && &den * &den == self.den {
+            Rational::new(num, den)
+        } else {
+            None
+        }
+    }
+
+    pub fn floor(&self) -> Rational {
+        Rational::from(self.num.div_floor(&self.den))
+    }
+
+    pub fn ceil(&self) -> Rational {
+        -&(-self).floor()
+    }
+
+    pub fn trunc(&self) -> Rational {
+        let (q, _) = self.num.div_rem(&self.den);
+        Rational::from(q)
+    }
+
+    // Halves round away from zero, like f64::round
+    pub fn round(&self) -> Rational {
+        let half = Rational {
+            num: BigInt::one(),
+            den: BigInt::from(2u64),
+        };
+        if self.is_negative() {
+            -&(&self.abs() + &half).floor()
+        } else {
+            (self + &half).floor()
+        }
+    }
+
+    pub fn to_decimal(&self, ctx: DecimalContext) -> Decimal {
+        Decimal::from(self.num.clone()).div(&Decimal::from(self.den.clone()), ctx)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // Dividing as decimals keeps huge numerators and denominators from
+        // turning into infinity / infinity
+        let ctx = DecimalContext {
+            precision: 20,
+            ..DecimalContext::default()
+        };
+        self.to_decimal(ctx).to_f64()
+    }
+
+    // The simplest fraction that rounds to `x` as an f64, so 0.1 is 1/10
+    // and 0.3333333333333333 is 1/3. None for NaN and infinity.
+    pub fn approximate(x: f64) -> Option<Rational> {
+        if !x.is_finite() {
+            return None;
+        }
+        // x = mantissa * 2^exp exactly; every number within half a unit in
+        // the last place of the mantissa rounds to x
+        let bits = x.abs().to_bits();
+        let exp_bits = (bits >> 52) as i64;
+        let fraction = bits & ((1 << 52) - 1);
+        let (mantissa, exp) = if exp_bits == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | (1 << 52), exp_bits - 1075)
+        };
+        // Whole numbers (including huge ones like 1e20) are taken as they are
+        if x.fract() == 0.0 {
+            let exact = times_power_of_two(mantissa, exp);
+            return Some(if x < 0.0 { -&exact } else { exact });
+        }
+        // (2m - 1) / 2 * 2^exp and (2m + 1) / 2 * 2^exp
+        let lo = times_power_of_two(2 * mantissa - 1, exp - 1);
+        let hi = times_power_of_two(2 * mantissa + 1, exp - 1);
+        let result = simplest_between(&lo, &hi);
+        Some(if x < 0.0 { -&result } else { result })
+    }
+
+    // A whole part and a proper fraction: 7/2 is "3 1/2", -7/2 is "-3 1/2"
+    pub fn to_mixed_string(&self) -> String {
+        let whole = self.trunc();
+        if whole.is_zero() || self.is_integer() {
+            return self.to_string();
+        }
+        let fraction = (self - &whole).abs();
+        format!("{} {}", whole, fraction)
+    }
+}
+
+// n * 2^power, exactly
+fn times_power_of_two(n: u64, power: i64) -> Rational {
+    let two_power = BigInt::from(2u64).pow(power.unsigned_abs() as u32);
+    if power >= 0 {
+        Rational::from(&BigInt::from(n) * &two_power)
+    } else {
+        Rational::new(BigInt::from(n), two_power).unwrap()
+    }
+}
+
+// The fraction with the smallest denominator in [lo, hi], for 0 <= lo <= hi,
+// found by walking the continued fraction expansion of both ends
+fn simplest_between(lo: &Rational, hi: &Rational) -> Rational {
+    let ceil = lo.ceil();
+    if &ceil <= hi {
+        return ceil;
+    }
+    // Both ends share the same whole part: x = whole + 1/y for some y between
+    // 1/(hi - whole) and 1/(lo - whole), and the simplest y gives the simplest x
+    let whole = lo.floor();
+    let lo_frac = lo - &whole;
+    let hi_frac = hi - &whole;
+    // lo isn't a whole number here, so neither fraction is zero, and y >= 1
+    let y = simplest_between(
+        &hi_frac.recip().expect("nonzero fraction"),
+        &lo_frac.recip().expect("nonzero fraction"),
+    );
+    &whole + &y.recip().expect("y >= 1")
+}
+
+impl From<BigInt> for Rational {
+    fn from(n: BigInt) -> Self {
+        Rational {
+            num: n,
+            den: BigInt::one(),
+        }
+    }
+}
+
+// Every Decimal is a fraction with a power of ten below: 0.125 = 125/1000 = 1/8
+impl From<&Decimal> for Rational {
+    fn from(d: &Decimal) -> Self {
+        let exp = d.exponent();
+        let magnitude = BigInt::pow10(exp.unsigned_abs() as usize);
+        if exp >= 0 {
+            Rational::from(d.coefficient() * &magnitude)
+        } else {
+            Rational::new(d.coefficient().clone(), magnitude).unwrap()
+        }
+    }
+}
+
+impl Add for &Rational {
+    type Output = Rational;
+
+    fn add(self, other: &Rational) -> Rational {
+        let num = &(&self.num * &other.den) + &(&other.num * &self.den);
+        Rational::new(num, &self.den * &other.den).unwrap()
+    }
+}
+
+impl Sub for &Rational {
+    type Output = Rational;
+
+    fn sub(self, other: &Rational) -> Rational {
+        self + &-other
+    }
+}
+
+impl Mul for &Rational {
+    type Output = Rational;
+
+    fn mul(self, other: &Rational) -> Rational {
+        Rational::new(&self.num * &other.num, &self.den * &other.den).unwrap()
+    }
+}
+
+impl Neg for &Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Rational {
+            num: -&self.num,
+            den: self.den.clone(),
+        }
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Denominators are positive, so cross-multiplying keeps the order
+        (&self.num * &other.den).cmp(&(&other.num * &self.den))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// "1/2", "-7/3", or just "5" for whole numbers
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_integer() {
+            f.pad(&self.num.to_string())
+        } else {
+            f.pad(&format!("{}/{}", self.num, self.den))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r(num: i64, den: i64) -> Rational {
+        Rational::new(BigInt::from(num), BigInt::from(den)).unwrap()
+    }
+
+    #[test]
+    fn new_reduces_and_moves_the_sign_up() {
+        let half = r(-3, -6);
+        assert_eq!(
+            (half.numer(), half.denom()),
+            (&BigInt::one(), &BigInt::from(2i64))
+        );
+        assert_eq!(r(4, -6).to_string(), "-2/3");
+        assert_eq!(r(0, -5), r(0, 1));
+        assert_eq!(Rational::new(BigInt::one(), BigInt::zero()), None);
+    }
+
+    #[test]
+    fn arithmetic_stays_exact() {
+        assert_eq!(&r(1, 3) + &r(1, 6), r(1, 2));
+        assert_eq!(&r(1, 3) - &r(1, 2), r(-1, 6));
+        assert_eq!(&r(2, 3) * &r(9, 4), r(3, 2));
+        assert_eq!(r(1, 3).checked_div(&r(2, 9)), Some(r(3, 2)));
+        assert_eq!(r(1, 3).checked_div(&r(0, 1)), None);
+        assert!(r(1, 3) < r(1, 2) && r(-1, 2) < r(-1, 3));
+    }
+
+    // Remainders take the sign of the divisor
+    #[test]
+    fn remainders() {
+        assert_eq!(r(-7, 1).modulo(&r(3, 1)), Some(r(2, 1)));
+        assert_eq!(r(7, 1).modulo(&r(-3, 1)), Some(r(-2, 1)));
+        assert_eq!(r(-7, 1).div_floor(&r(2, 1)), Some(r(-4, 1)));
+        assert_eq!(r(7, 2).modulo(&r(1, 1)), Some(r(1, 2)));
+        assert_eq!(r(1, 1).modulo(&r(0, 1)), None);
+    }
+
+    #[test]
+    fn powers() {
+        assert_eq!(r(2, 3).pow(3), Some(r(8, 27)));
+        assert_eq!(r(2, 3).pow(-2), Some(r(9, 4)));
+        assert_eq!(r(-1, 2).pow(0), Some(r(1, 1)));
+        assert_eq!(r(0, 1).pow(-1), None);
+        // 7^1000000 would have 845,099 digits
+        assert_eq!(r(7, 1).pow(1_000_000), None);
+        // The limit goes by the size of the result, not of the exponent
+        let power = r(2, 1).pow(200_000).expect("2^200000 has 60206 digits");
+        assert_eq!(power.numer().num_digits(), 60_206);
+        assert_eq!(r(1, 2).pow(-200_000), Some(power));
+        assert_eq!(r(10, 1).pow(100_001), None);
+        // ...but 1, 0 and -1 never grow
+        assert_eq!(r(-1, 1).pow(4_000_000_001), Some(r(-1, 1)));
+        assert_eq!(r(1, 1).pow(-4_000_000_000), Some(r(1, 1)));
+    }
+
+    #[test]
+    fn square_roots() {
+        assert_eq!(r(9, 4).sqrt(), Some(r(3, 2)));
+        assert_eq!(r(2, 1).sqrt(), None);
+        assert_eq!(r(1, 2).sqrt(), None);
+        assert_eq!(r(-4, 1).sqrt(), None);
+    }
+
+    #[test]
+    fn rounding_to_whole_numbers() {
+        // floor, ceil, trunc, round
+        let cases = [
+            (r(7, 2), 3, 4, 3, 4),
+            (r(-7, 2), -4, -3, -3, -4),
+            (r(5, 3), 1, 2, 1, 2),
+            (r(-5, 3), -2, -1, -1, -2),
+            (r(4, 1), 4, 4, 4, 4),
+        ];
+        for (x, floor, ceil, trunc, round) in cases {
+            let whole = |n: i64| Rational::from(BigInt::from(n));
+            assert_eq!(x.floor(), whole(floor), "floor({})", x);
+            assert_eq!(x.ceil(), whole(ceil), "ceil({})", x);
+            assert_eq!(x.trunc(), whole(trunc), "trunc({})", x);
+            assert_eq!(x.round(), whole(round), "round({})", x);
+        }
+    }
+
+    #[test]
+    fn approximate_finds_the_simplest_fraction() {
+        assert_eq!(Rational::approximate(0.1), Some(r(1, 10)));
+        assert_eq!(Rational::approximate(1.0 / 3.0), Some(r(1, 3)));
+        assert_eq!(Rational::approximate(-0.75), Some(r(-3, 4)));
+        assert_eq!(Rational::approximate(0.0), Some(r(0, 1)));
+        let big = Rational::approximate(1e20).unwrap();
+        assert_eq!(big.to_string(), "100000000000000000000");
+        assert_eq!(Rational::approximate(f64::NAN), None);
+        assert_eq!(Rational::approximate(f64::NEG_INFINITY), None);
+    }
+
+    #[test]
+    fn conversions() {
+        let ctx = DecimalContext {
+            precision: 10,
+            ..DecimalContext::default()
+        };
+        assert_eq!(r(1, 3).to_decimal(ctx).to_string(), "0.3333333333");
+        assert_eq!(Rational::from(&Decimal::parse("0.125").unwrap()), r(1, 8));
+        assert_eq!(
+            Rational::from(&Decimal::parse("1.5e3").unwrap()),
+            r(1500, 1)
+        );
+        assert_eq!(r(2, 3).to_f64(), 2.0 / 3.0);
+        assert_eq!(r(7, 2).to_mixed_string(), "3 1/2");
+        assert_eq!(r(-7, 2).to_mixed_string(), "-3 1/2");
+        assert_eq!(r(1, 2).to_mixed_string(), "1/2");
+    }
+}
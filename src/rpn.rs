@@ -0,0 +1,319 @@
+// Reverse Polish Notation, the way HP calculators do it: numbers go onto a
+// stack and operators take their operands off it, so (3 + 4) * 2 is typed
+// as `3 4 + 2 *` and never needs parentheses.
+//
+// A word that isn't an operator, a function or a stack word is read as a
+// small infix expression and pushed, so 0xff, 30deg, 2i, pi, $1, variables
+// and units like km all mean what they mean in infix mode, and so do
+// vectors and matrices written without spaces, like [[1,2],[3,4]]. Operators and
+// functions go through the same Context methods as the infix evaluator.
+// A unit word like km or m/s gives its unit to the value on level 1, so
+// `5 km 3 m +` is 5.003 km. If that value already has a unit, or the
+// stack is empty, the word is pushed as one of the unit instead, which is
+// what `in` converts to: `5 km mi in`. A variable named like a unit is
+// still the variable.
+// Assignments and definitions stay infix; the front end runs any line
+// with an '=' as it would outside RPN mode.
+//
+// Level 1 is the top of the stack, the value pushed last.
+
+use crate::builtins;
+use crate::error::{CalcError, Span};
+use crate::eval::{self, Context};
+use crate::lexer;
+use crate::matrix;
+use crate::parser::{self, BinOp, BitOp, ExprKind, UnitSpec};
+use crate::value::Value;
+
+// Words that rearrange the stack instead of computing anything
+pub const STACK_WORDS: &[&str] = &["dup", "swap", "drop", "roll", "clear"];
+
+// Runs one line against `ctx.stack`. The whole line works or the stack is
+// left as it was, so a typo halfway through doesn't lose the other values.
+// Returns whether the line produced a value, as opposed to only moving
+// values around, so the front end knows whether to record it.
+pub fn run(ctx: &mut Context, input: &str) -> Result<bool, CalcError> {
+    let mut stack = ctx.stack.clone();
+    let mut produced = false;
+    for (word, span) in words(input) {
+        produced |= step(ctx, &mut stack, word, span)?;
+    }
+    ctx.stack = stack;
+    Ok(produced)
+}
+
+// The whitespace-separated words of a line with their columns, up to a '#' comment
+fn words(input: &str) -> Vec<(&str, Span)> {
+    let input = input.split('#').next().unwrap_or_default();
+    let mut words = Vec::new();
+    let mut start = None;
+    // (column, byte offset) pairs, plus the end of the line
+    let ends = input
+        .char_indices()
+        .enumerate()
+        .map(|(column, (at, c))| (column, at, c.is_whitespace()))
+        .chain([(input.chars().count(), input.len(), true)]);
+    for (column, at, space) in ends {
+        match (start, space) {
+            (None, false) => start = Some((column, at)),
+            (Some((first, from)), true) => {
+                words.push((&input[from..at], Span::new(first, column)));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+fn step(ctx: &Context, stack: &mut Vec<Value>, word: &str, span: Span) -> Result<bool, CalcError> {
+    match word {
+        "dup" => {
+            let [x] = take(stack, word, span)?;
+            stack.extend([x.clone(), x]);
+            return Ok(false);
+        }
+        "swap" => {
+            let [y, x] = take(stack, word, span)?;
+            stack.extend([x, y]);
+            return Ok(false);
+        }
+        "drop" => {
+            take::<1>(stack, word, span)?;
+            return Ok(false);
+        }
+        // Roll down, like R↓ on an HP: level 1 moves to the bottom and
+        // everything else moves up one level
+        "roll" => {
+            let [x] = take(stack, word, span)?;
+            stack.insert(0, x);
+            return Ok(false);
+        }
+        "clear" => {
+            stack.clear();
+            return Ok(false);
+        }
+        _ => {}
+    }
+
+    let result = if let Some(op) = binary_op(word) {
+        let [a, b] = take(stack, word, span)?;
+        ctx.operate(op, a, b)
+    } else if let Some(op) = elementwise_op(word) {
+        let [a, b] = take(stack, word, span)?;
+        matrix::elementwise(ctx, op, a, b)
+    } else if let Some(op) = bitwise_op(word) {
+        let [a, b] = take(stack, word, span)?;
+        ctx.bitwise(op, &a, &b)
+    } else if let Some(arity) = arity(ctx, word) {
+        // Functions that take any number of arguments, like max, use the whole stack
+        let count = arity.unwrap_or(stack.len().max(1));
+        if stack.len() < count {
+            return Err(CalcError::StackUnderflow {
+                word: word.to_string(),
+                needed: count,
+                found: stack.len(),
+                span,
+            });
+        }
+        let args = stack.split_off(stack.len() - count);
+        ctx.call_values(word, &args, span)
+    } else if let Some(spec) = unit_for_top(ctx, stack, word) {
+        // `5 km`: the unit goes on the value below it
+        take(stack, word, span).and_then(|[x]| ctx.apply_unit(x, &spec, span))
+    } else {
+        match word {
+            "neg" | "chs" => take(stack, word, span).map(|[x]| eval::negate(x)),
+            "~" | "not" => take(stack, word, span).and_then(|[x]| ctx.bit_not(&x)),
+            "!" => take(stack, word, span).and_then(|[x]| ctx.factorial(&x)),
+            // `5 km mi in`: the unit comes off level 1 and level 2 is converted to it
+            "in" | "to" => take(stack, word, span).and_then(|[x, target]| match target {
+                Value::Quantity(target) => ctx.convert(x, &target.unit),
+                other => Err(CalcError::Domain {
+                    message: format!(
+                        "'{}' converts to the unit on level 1, like 5 km mi in (got {})",
+                        word, other
+                    ),
+                    span,
+                }),
+            }),
+            // Anything else is a value written the infix way
+            _ => ctx.evaluate(word).map_err(|error| {
+                let inner = error.span();
+                error.at(Span::new(span.start + inner.start, span.start + inner.end))
+            }),
+        }
+    };
+    let value = result.and_then(|value| ctx.fit(value)).map_err(|error| {
+        // Errors from the engine have no position yet; point them at the word
+        if error.span() == Span::default() {
+            error.at(span)
+        } else {
+            error
+        }
+    })?;
+    stack.push(value);
+    Ok(true)
+}
+
+// The unit a word like km or m/s^2 names, if that's all the word is and
+// level 1 has no unit of its own yet
+fn unit_for_top(ctx: &Context, stack: &[Value], word: &str) -> Option<UnitSpec> {
+    if matches!(stack.last(), None | Some(Value::Quantity(_))) || ctx.vars.contains_key(word) {
+        return None;
+    }
+    let tokens = lexer::tokenize(&format!("1 {}", word)).ok()?;
+    match parser::parse_expression(&tokens).ok()?.kind {
+        ExprKind::Quantity(ref number, ref spec) if matches!(number.kind, ExprKind::Number(_)) => {
+            Some(spec.clone())
+        }
+        _ => None,
+    }
+}
+
+// The top `N` values, deepest first, so `3 4 -` gives a = 3 and b = 4
+fn take<const N: usize>(
+    stack: &mut Vec<Value>,
+    word: &str,
+    span: Span,
+) -> Result<[Value; N], CalcError> {
+    let found = stack.len();
+    if found < N {
+        return Err(CalcError::StackUnderflow {
+            word: word.to_string(),
+            needed: N,
+            found,
+            span,
+        });
+    }
+    Ok(stack
+        .split_off(found - N)
+        .try_into()
+        .expect("exactly N values"))
+}
+
+fn binary_op(word: &str) -> Option<BinOp> {
+    match word {
+        "+" => Some(BinOp::Add),
+        "-" => Some(BinOp::Sub),
+        "*" => Some(BinOp::Mul),
+        "/" => Some(BinOp::Div),
+        "//" => Some(BinOp::FloorDiv),
+        "%" => Some(BinOp::Mod),
+        "^" => Some(BinOp::Pow),
+        _ => None,
+    }
+}
+
+// .*, ./ and .^ on matching elements of vectors and matrices
+fn elementwise_op(word: &str) -> Option<BinOp> {
+    match word {
+        ".*" => Some(BinOp::Mul),
+        "./" => Some(BinOp::Div),
+        ".^" => Some(BinOp::Pow),
+        _ => None,
+    }
+}
+
+fn bitwise_op(word: &str) -> Option<BitOp> {
+    match word {
+        "&" => Some(BitOp::And),
+        "|" => Some(BitOp::Or),
+        "xor" => Some(BitOp::Xor),
+        "<<" => Some(BitOp::ShiftLeft),
+        ">>" => Some(BitOp::ShiftRight),
+        _ => None,
+    }
+}
+
+// How many values a function takes off the stack: Some(n) for a fixed
+// count, None for one like max that takes them all, and no answer at all
+// if `word` isn't a function
+fn arity(ctx: &Context, word: &str) -> Option<Option<usize>> {
+    if let Some(builtin) = builtins::lookup(word) {
+        return Some(match builtin.arity() {
+            (min, Some(max)) if min == max => Some(min),
+            _ => None,
+        });
+    }
+    ctx.funcs
+        .get(word)
+        .map(|function| Some(function.params.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs each line in turn and shows the stack from the bottom up
+    fn stack(lines: &[&str]) -> Result<Vec<String>, String> {
+        let mut ctx = Context::new();
+        for line in lines {
+            run(&mut ctx, line).map_err(|err| err.to_string())?;
+        }
+        Ok(ctx.stack.iter().map(|value| value.to_string()).collect())
+    }
+
+    #[test]
+    fn operators_take_their_operands_off_the_stack() {
+        assert_eq!(stack(&["3 4 + 2 *"]), Ok(vec!["14".to_string()]));
+        assert_eq!(stack(&["3 4 -"]), Ok(vec!["-1".to_string()]));
+        assert_eq!(stack(&["2", "3 ^"]), Ok(vec!["8".to_string()]));
+        assert_eq!(stack(&["1 2 3 max"]), Ok(vec!["3".to_string()]));
+        assert_eq!(
+            stack(&["5 neg 3 !"]),
+            Ok(vec!["-5".to_string(), "6".to_string()])
+        );
+    }
+
+    #[test]
+    fn stack_words() {
+        let values = |list: &[&str]| Ok(list.iter().map(|v| v.to_string()).collect());
+        assert_eq!(stack(&["1 2 dup"]), values(&["1", "2", "2"]));
+        assert_eq!(stack(&["1 2 swap"]), values(&["2", "1"]));
+        assert_eq!(stack(&["1 2 drop"]), values(&["1"]));
+        assert_eq!(stack(&["1 2 3 roll"]), values(&["3", "1", "2"]));
+        assert_eq!(stack(&["1 2 clear 4"]), values(&["4"]));
+    }
+
+    #[test]
+    fn words_are_read_as_infix_values() {
+        let mut ctx = Context::new();
+        run(&mut ctx, "0xff pi 5km mi in # comment").unwrap();
+        assert_eq!(ctx.stack.len(), 3);
+        assert_eq!(ctx.stack[0].to_string(), "255");
+        assert!(ctx.stack[2].to_string().ends_with(" mi"));
+    }
+
+    #[test]
+    fn unit_words_go_on_the_value_below() {
+        let values = |list: &[&str]| Ok(list.iter().map(|v| v.to_string()).collect());
+        assert_eq!(stack(&["5 km 3 m +"]), values(&["5003/1000 km"]));
+        assert_eq!(stack(&["5 km"]), stack(&["5km"]));
+        assert_eq!(stack(&["60 km/h 2 h *"]), values(&["120 km"]));
+        assert_eq!(stack(&["1 2 3 km"]), values(&["1", "2", "3 km"]));
+        assert_eq!(stack(&["km"]), values(&["1 km"]));
+        assert_eq!(stack(&["25 degC degF in"]), values(&["77 degF"]));
+        let mut ctx = Context::new();
+        run(&mut ctx, "8 km mi in").unwrap();
+        assert_eq!(ctx.stack.len(), 1);
+        assert!(ctx.stack[0].to_string().ends_with(" mi"));
+        let five = ctx.evaluate("5").unwrap();
+        ctx.vars.insert("m".to_string(), five);
+        run(&mut ctx, "clear 3 m +").unwrap();
+        assert_eq!(ctx.stack[0].to_string(), "8");
+    }
+
+    #[test]
+    fn a_failed_line_leaves_the_stack_alone() {
+        let mut ctx = Context::new();
+        run(&mut ctx, "1 2").unwrap();
+        let error = run(&mut ctx, "3 + + +").unwrap_err();
+        assert_eq!(error.span(), Span::new(6, 7));
+        assert_eq!(ctx.stack.len(), 2);
+        assert_eq!(
+            stack(&["1 /"]),
+            Err("'/' needs 2 value(s) on the stack but there are 1".to_string())
+        );
+    }
+}
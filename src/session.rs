@@ -0,0 +1,467 @@
+// Saving a session to disk and reading it back: variables, user functions,
+// settings, history and the RPN stack, as JSON with a version number so a
+// later release can tell which layout a file has.
+//
+//     {"version": 1,
+//      "settings": {"angle": "deg", "numbers": "float", "precision": 34, ...},
+//      "variables": {"rate": {"fraction": "3/40"}},
+//      "functions": ["f(x) = x ^ 2"],
+//      "history": [{"input": "2 + 2", "value": {"fraction": "4"}}],
+//      "stack": []}
+//
+// Numbers are written exactly, as text, so 1/3 comes back as 1/3 and a
+// float as the very same f64. Exchange rates aren't part of a session;
+// they belong to their own file.
+
+use std::fs;
+use std::path::Path;
+
+use crate::angle::AngleMode;
+use crate::bigint::BigInt;
+use crate::complex::{Complex, ComplexForm};
+use crate::decimal::{Decimal, RoundingMode};
+use crate::eval::{Context, Entry, Settings};
+use crate::format::{DecimalMark, Notation};
+use crate::integer::Radix;
+use crate::json::Json;
+use crate::matrix::Matrix;
+use crate::parser::Statement;
+use crate::rational::Rational;
+use crate::units::{Quantity, UnitExpr};
+use crate::value::{NumberMode, Value};
+
+// The layout written by this version; files from newer versions are refused
+// rather than half-read
+pub const VERSION: u64 = 1;
+
+// Writes the session to `path`, creating its directory if needed. The file
+// is written next to the old one and then moved over it, so a crash
+// halfway through never leaves a truncated session behind.
+pub fn save(ctx: &Context, path: &Path) -> Result<(), String> {
+    let source = path.display();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    }
+    let partial = path.with_extension("json.partial");
+    fs::write(&partial, format!("{}\n", to_json(ctx)))
+        .and_then(|_| fs::rename(&partial, path))
+        .map_err(|e| format!("cannot write {}: {}", source, e))
+}
+
+// Reads a saved session into a fresh context, which has no exchange rates
+pub fn load(path: &Path) -> Result<Context, String> {
+    let source = path.display();
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", source, e))?;
+    let json = Json::parse(&text).map_err(|message| format!("{}: {}", source, message))?;
+    from_json(&json).map_err(|message| format!("{}: {}", source, message))
+}
+
+pub fn to_json(ctx: &Context) -> Json {
+    let variables = ctx
+        .vars
+        .iter()
+        .map(|(name, value)| (name.clone(), value_to_json(value)))
+        .collect();
+    let functions = ctx
+        .funcs
+        .values()
+        .map(|function| Json::String(function.to_string()))
+        .collect();
+    let history = ctx
+        .history
+        .iter()
+        .map(|entry| {
+            Json::Object(vec![
+                ("input".to_string(), Json::String(entry.input.clone())),
+                ("value".to_string(), value_to_json(&entry.value)),
+            ])
+        })
+        .collect();
+    Json::Object(vec![
+        ("version".to_string(), Json::Number(VERSION.to_string())),
+        ("settings".to_string(), settings_to_json(&ctx.settings)),
+        ("variables".to_string(), Json::Object(variables)),
+        ("functions".to_string(), Json::Array(functions)),
+        ("history".to_string(), Json::Array(history)),
+        (
+            "stack".to_string(),
+            Json::Array(ctx.stack.iter().map(value_to_json).collect()),
+        ),
+    ])
+}
+
+// Missing sections are left empty, so a hand-written file only needs the
+// parts it cares about
+pub fn from_json(json: &Json) -> Result<Context, String> {
+    let version = match json.get("version") {
+        Some(Json::Number(text)) => text.parse::<u64>().ok(),
+        _ => None,
+    };
+    match version {
+        Some(VERSION) => {}
+        Some(version) if version > VERSION => {
+            return Err(format!(
+                "this session was saved by a newer calculator (version {}; this one reads {})",
+                version, VERSION
+            ));
+        }
+        _ => return Err("not a calculator session (no version number)".to_string()),
+    }
+
+    let mut ctx = Context::new();
+    if let Some(settings) = json.get("settings") {
+        ctx.settings = settings_from_json(settings)?;
+    }
+    if let Some(Json::Object(variables)) = json.get("variables") {
+        for (name, value) in variables {
+            let value = value_from_json(value).map_err(|e| format!("variable {}: {}", name, e))?;
+            ctx.vars.insert(name.clone(), value);
+        }
+    }
+    for definition in array(json, "functions") {
+        let Json::String(text) = definition else {
+            return Err("functions must be written as text, like \"f(x) = x ^ 2\"".to_string());
+        };
+        let statement = crate::parse_statement(text);
+        match statement {
+            Ok(statement @ Statement::Define { .. }) => {
+                ctx.execute(&statement)
+                    .map_err(|e| format!("function '{}': {}", text, e))?;
+            }
+            _ => return Err(format!("'{}' is not a function definition", text)),
+        }
+    }
+    for entry in array(json, "history") {
+        let input = match entry.get("input") {
+            Some(Json::String(input)) => input.clone(),
+            _ => return Err("a history entry has no input".to_string()),
+        };
+        let value = entry
+            .get("value")
+            .ok_or("a history entry has no value".to_string())
+            .and_then(value_from_json)
+            .map_err(|e| format!("history entry '{}': {}", input, e))?;
+        ctx.history.push(Entry { input, value });
+    }
+    for value in array(json, "stack") {
+        ctx.stack.push(value_from_json(value)?);
+    }
+    Ok(ctx)
+}
+
+// The items of an array field, or none if it's missing
+fn array<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    match json.get(key) {
+        Some(Json::Array(items)) => items,
+        _ => &[],
+    }
+}
+
+fn settings_to_json(settings: &Settings) -> Json {
+    let text = |value: String| Json::String(value);
+    let notation = match settings.output.notation {
+        Notation::Auto => "auto".to_string(),
+        Notation::Fixed(n) => format!("fix {}", n),
+        Notation::Significant(n) => format!("sig {}", n),
+        Notation::Scientific(n) => format!("sci {}", n),
+        Notation::Engineering(n) => format!("eng {}", n),
+    };
+    let mark = match settings.output.mark {
+        DecimalMark::Point => ".",
+        DecimalMark::Comma => ",",
+    };
+    Json::Object(vec![
+        ("angle".to_string(), text(settings.angle.to_string())),
+        ("numbers".to_string(), text(settings.numbers.to_string())),
+        (
+            "precision".to_string(),
+            Json::Number(settings.decimal.precision.to_string()),
+        ),
+        (
+            "rounding".to_string(),
+            text(settings.decimal.rounding.to_string()),
+        ),
+        (
+            "mixed_fractions".to_string(),
+            Json::Bool(settings.mixed_fractions),
+        ),
+        ("complex".to_string(), text(settings.complex.to_string())),
+        ("wrap".to_string(), Json::Bool(settings.wrap)),
+        (
+            "base".to_string(),
+            Json::Number(settings.radix.radix().to_string()),
+        ),
+        ("rpn".to_string(), Json::Bool(settings.rpn)),
+        ("format".to_string(), text(notation)),
+        ("group".to_string(), Json::Bool(settings.output.group)),
+        ("mark".to_string(), text(mark.to_string())),
+    ])
+}
+
+// Settings the file doesn't mention keep their defaults
+fn settings_from_json(json: &Json) -> Result<Settings, String> {
+    let mut settings = Settings::default();
+    let Json::Object(entries) = json else {
+        return Err("settings must be an object".to_string());
+    };
+    for (key, value) in entries {
+        let text = match value {
+            Json::String(text) | Json::Number(text) => text.as_str(),
+            Json::Bool(true) => "true",
+            Json::Bool(false) => "false",
+            _ => "",
+        };
+        let flag = match text {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        };
+        let known = match key.as_str() {
+            "angle" => AngleMode::parse(text).map(|angle| settings.angle = angle),
+            "numbers" => NumberMode::parse(text).map(|numbers| settings.numbers = numbers),
+            "precision" => text
+                .parse()
+                .ok()
+                .filter(|digits| (1..=crate::decimal::MAX_PRECISION).contains(digits))
+                .map(|digits| settings.decimal.precision = digits),
+            "rounding" => {
+                RoundingMode::parse(text).map(|rounding| settings.decimal.rounding = rounding)
+            }
+            "mixed_fractions" => flag.map(|mixed| settings.mixed_fractions = mixed),
+            "complex" => ComplexForm::parse(text).map(|form| settings.complex = form),
+            "wrap" => flag.map(|wrap| settings.wrap = wrap),
+            "base" => Radix::parse(text).map(|radix| settings.radix = radix),
+            "rpn" => flag.map(|rpn| settings.rpn = rpn),
+            "format" => Notation::parse(text).map(|notation| settings.output.notation = notation),
+            "group" => flag.map(|group| settings.output.group = group),
+            "mark" => DecimalMark::parse(text).map(|mark| settings.output.mark = mark),
+            // Settings from a newer version are skipped rather than refused
+            _ => Some(()),
+        };
+        if known.is_none() {
+            return Err(format!("setting '{}' can't be '{}'", key, text));
+        }
+    }
+    Ok(settings)
+}
+
+// Each kind of value is an object with one key naming the kind:
+// {"float": "0.1"}, {"decimal": "10e-2"}, {"fraction": "1/3"},
+// {"complex": ["1", "2"]}, {"quantity": {"value": ..., "unit": [["km", 1]]}},
+// {"vector": [...]} or {"matrix": [[...], [...]]}
+fn value_to_json(value: &Value) -> Json {
+    let (kind, body) = match value {
+        Value::Real(x) => ("float", Json::String(x.to_string())),
+        // As coefficient and exponent, so trailing zeros like those of 1.50
+        // are kept
+        Value::Decimal(d) => (
+            "decimal",
+            Json::String(format!("{}e{}", d.coefficient(), d.exponent())),
+        ),
+        Value::Rational(r) => ("fraction", Json::String(r.to_string())),
+        Value::Complex(z) => (
+            "complex",
+            Json::Array(vec![
+                Json::String(z.re.to_string()),
+                Json::String(z.im.to_string()),
+            ]),
+        ),
+        Value::Quantity(q) => {
+            let unit = q
+                .unit
+                .spec()
+                .into_iter()
+                .map(|(name, power)| {
+                    Json::Array(vec![Json::String(name), Json::Number(power.to_string())])
+                })
+                .collect();
+            let body = Json::Object(vec![
+                ("value".to_string(), value_to_json(&q.value)),
+                ("unit".to_string(), Json::Array(unit)),
+            ]);
+            ("quantity", body)
+        }
+        Value::Matrix(m) if m.is_vector() => (
+            "vector",
+            Json::Array(m.elems().iter().map(value_to_json).collect()),
+        ),
+        Value::Matrix(m) => {
+            let rows = (0..m.rows())
+                .map(|row| Json::Array(m.row(row).iter().map(value_to_json).collect()))
+                .collect();
+            ("matrix", Json::Array(rows))
+        }
+    };
+    Json::Object(vec![(kind.to_string(), body)])
+}
+
+fn value_from_json(json: &Json) -> Result<Value, String> {
+    let invalid = || format!("not a value: {}", json);
+    let Json::Object(entries) = json else {
+        return Err(invalid());
+    };
+    let [(kind, body)] = entries.as_slice() else {
+        return Err(invalid());
+    };
+    let text = match body {
+        Json::String(text) => Some(text.as_str()),
+        _ => None,
+    };
+    let float = |text: &str| text.parse::<f64>().ok().filter(|x| x.is_finite());
+    let value = match kind.as_str() {
+        "float" => text.and_then(float).map(Value::Real),
+        "decimal" => text.and_then(Decimal::parse).map(Value::Decimal),
+        "fraction" => text.and_then(parse_fraction).map(Value::Rational),
+        "complex" => match body {
+            Json::Array(parts) => match parts.as_slice() {
+                [Json::String(re), Json::String(im)] => float(re)
+                    .zip(float(im))
+                    .map(|(re, im)| Value::complex(Complex::new(re, im))),
+                _ => None,
+            },
+            _ => None,
+        },
+        "quantity" => {
+            let value = body.get("value").ok_or_else(invalid)?;
+            let Some(Json::Array(terms)) = body.get("unit") else {
+                return Err(invalid());
+            };
+            let mut spec = Vec::new();
+            for term in terms {
+                let Json::Array(pair) = term else {
+                    return Err(invalid());
+                };
+                let [Json::String(name), Json::Number(power)] = pair.as_slice() else {
+                    return Err(invalid());
+                };
+                spec.push((name.clone(), power.parse().map_err(|_| invalid())?));
+            }
+            let unit =
+                UnitExpr::lookup(&spec).map_err(|name| format!("unknown unit '{}'", name))?;
+            Some(Value::Quantity(Box::new(Quantity {
+                value: value_from_json(value)?,
+                unit,
+            })))
+        }
+        "vector" => {
+            let elems = elements_from_json(body).ok_or_else(invalid)??;
+            (!elems.is_empty()).then(|| Matrix::vector(elems).into())
+        }
+        "matrix" => {
+            let Json::Array(rows) = body else {
+                return Err(invalid());
+            };
+            let mut elems = Vec::new();
+            let mut cols = None;
+            for row in rows {
+                let row = elements_from_json(row).ok_or_else(invalid)??;
+                if row.is_empty() || *cols.get_or_insert(row.len()) != row.len() {
+                    return Err(invalid());
+                }
+                elems.extend(row);
+            }
+            cols.map(|cols| Matrix::new(rows.len(), cols, elems).into())
+        }
+        _ => None,
+    };
+    value.ok_or_else(invalid)
+}
+
+// The numbers in a vector or a row of a matrix; None if it isn't a list
+fn elements_from_json(json: &Json) -> Option<Result<Vec<Value>, String>> {
+    let Json::Array(items) = json else {
+        return None;
+    };
+    Some(
+        items
+            .iter()
+            .map(|item| match value_from_json(item)? {
+                Value::Matrix(_) => Err(format!("not a number: {}", item)),
+                value => Ok(value),
+            })
+            .collect(),
+    )
+}
+
+// "1/3" or "-4"
+fn parse_fraction(text: &str) -> Option<Rational> {
+    match text.split_once('/') {
+        Some((num, den)) => Rational::new(BigInt::parse(num)?, BigInt::parse(den)?),
+        None => Some(Rational::from(BigInt::parse(text)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integer::IntType;
+
+    // A session with one of everything that gets saved
+    fn session() -> Context {
+        let mut ctx = Context::new();
+        ctx.settings.angle = AngleMode::Deg;
+        ctx.settings.mixed_fractions = true;
+        ctx.settings.output.notation = Notation::Scientific(6);
+        ctx.settings.output.mark = DecimalMark::Comma;
+        for line in [
+            "third = 1/3",
+            "real = 0.1 + sin(0)",
+            "z = 2 - 3i",
+            "d = 5.3 km",
+        ] {
+            ctx.run(line).unwrap();
+        }
+        ctx.run("f(x, y) = x ^ 2 + y").unwrap();
+        let value = ctx.evaluate("f(third, 1)").unwrap();
+        ctx.record("f(third, 1)", value);
+        ctx.stack.push(Value::Real(f64::MIN_POSITIVE));
+        ctx
+    }
+
+    #[test]
+    fn round_trip() {
+        let ctx = session();
+        let loaded = from_json(&Json::parse(&to_json(&ctx).to_string()).unwrap()).unwrap();
+        assert_eq!(loaded.settings, ctx.settings);
+        assert_eq!(loaded.vars, ctx.vars);
+        assert_eq!(loaded.stack, ctx.stack);
+        assert_eq!(loaded.history.len(), 1);
+        assert_eq!(loaded.history[0].input, "f(third, 1)");
+        assert_eq!(loaded.history[0].value, ctx.history[0].value);
+        assert_eq!(
+            loaded.evaluate("f(2, 1)"),
+            Ok(Value::from(Rational::from(BigInt::from(5u64))))
+        );
+    }
+
+    #[test]
+    fn files() {
+        let dir = std::env::temp_dir().join(format!("calculator-session-{}", std::process::id()));
+        let path = dir.join("nested").join("session.json");
+        let mut ctx = session();
+        ctx.settings.numbers = NumberMode::Integer(IntType::parse("u8").unwrap());
+        save(&ctx, &path).unwrap();
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.settings, ctx.settings);
+        assert_eq!(to_json(&loaded).to_string(), to_json(&ctx).to_string());
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(load(&path).unwrap_err().starts_with("cannot read "));
+    }
+
+    #[test]
+    fn versions() {
+        let newer = Json::parse(r#"{"version": 2}"#).unwrap();
+        assert_eq!(
+            from_json(&newer).unwrap_err(),
+            "this session was saved by a newer calculator (version 2; this one reads 1)"
+        );
+        let other = Json::parse(r#"{"name": "not a session"}"#).unwrap();
+        assert_eq!(
+            from_json(&other).unwrap_err(),
+            "not a calculator session (no version number)"
+        );
+        // Sections that aren't there are simply empty
+        let minimal = from_json(&Json::parse(r#"{"version": 1}"#).unwrap()).unwrap();
+        assert!(minimal.vars.is_empty() && minimal.history.is_empty());
+    }
+}
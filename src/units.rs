@@ -0,0 +1,634 @@
+// Physical units, so `5 km + 300 m in mi` and `60 mph * 2.5 h` work and
+// `3 m + 2 s` is an error instead of 5.
+//
+// A quantity keeps its number in the unit it was written in (5 km stays
+// 5 with unit km) and only converts when it has to: when adding to a
+// different unit of the same kind, or on `in`/`to`. Every unit knows its
+// size in SI base units as an exact fraction, so conversions don't add
+// rounding errors of their own: 60 mph * 2.5 h is exactly 150 mi.
+//
+// A unit name can follow any value: x km, (1/3) m, [1, 2] km. Right
+// after a value it always means the unit, so a variable named m doesn't
+// change what 3 m is; write 3 * m for that.
+//
+// Currencies are units too, but their sizes come from the session's rate
+// table (see currency.rs), so anything that needs a size takes the table.
+
+use std::fmt;
+
+use crate::bigint::BigInt;
+use crate::currency::{self, Rates};
+use crate::decimal::Decimal;
+use crate::rational::Rational;
+use crate::value::Value;
+
+// Powers of the seven SI base dimensions, in the order
+// length, mass, time, current, temperature, amount, luminous intensity,
+// followed by money. Speed (m/s) is [1, 0, -1, 0, 0, 0, 0, 0].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Dimension([i8; 8]);
+
+const fn dim(m: i8, kg: i8, s: i8, a: i8, k: i8, mol: i8, cd: i8) -> Dimension {
+    Dimension([m, kg, s, a, k, mol, cd, 0])
+}
+
+const NONE: Dimension = dim(0, 0, 0, 0, 0, 0, 0);
+const LENGTH: Dimension = dim(1, 0, 0, 0, 0, 0, 0);
+const MASS: Dimension = dim(0, 1, 0, 0, 0, 0, 0);
+const TIME: Dimension = dim(0, 0, 1, 0, 0, 0, 0);
+const CURRENT: Dimension = dim(0, 0, 0, 1, 0, 0, 0);
+const TEMPERATURE: Dimension = dim(0, 0, 0, 0, 1, 0, 0);
+const AMOUNT: Dimension = dim(0, 0, 0, 0, 0, 1, 0);
+const LUMINOSITY: Dimension = dim(0, 0, 0, 0, 0, 0, 1);
+const AREA: Dimension = dim(2, 0, 0, 0, 0, 0, 0);
+const VOLUME: Dimension = dim(3, 0, 0, 0, 0, 0, 0);
+const FREQUENCY: Dimension = dim(0, 0, -1, 0, 0, 0, 0);
+const FORCE: Dimension = dim(1, 1, -2, 0, 0, 0, 0);
+const ENERGY: Dimension = dim(2, 1, -2, 0, 0, 0, 0);
+const POWER: Dimension = dim(2, 1, -3, 0, 0, 0, 0);
+const PRESSURE: Dimension = dim(-1, 1, -2, 0, 0, 0, 0);
+const CHARGE: Dimension = dim(0, 0, 1, 1, 0, 0, 0);
+const VOLTAGE: Dimension = dim(2, 1, -3, -1, 0, 0, 0);
+const RESISTANCE: Dimension = dim(2, 1, -3, -2, 0, 0, 0);
+const MONEY: Dimension = Dimension([0, 0, 0, 0, 0, 0, 0, 1]);
+
+// What error messages call a dimension, e.g. "km (length)"
+const DIMENSION_NAMES: &[(Dimension, &str)] = &[
+    (LENGTH, "length"),
+    (MASS, "mass"),
+    (TIME, "time"),
+    (CURRENT, "current"),
+    (TEMPERATURE, "temperature"),
+    (AMOUNT, "amount of substance"),
+    (LUMINOSITY, "luminous intensity"),
+    (AREA, "area"),
+    (VOLUME, "volume"),
+    (dim(1, 0, -1, 0, 0, 0, 0), "speed"),
+    (dim(1, 0, -2, 0, 0, 0, 0), "acceleration"),
+    (FREQUENCY, "frequency"),
+    (FORCE, "force"),
+    (ENERGY, "energy"),
+    (POWER, "power"),
+    (PRESSURE, "pressure"),
+    (CHARGE, "charge"),
+    (VOLTAGE, "voltage"),
+    (RESISTANCE, "resistance"),
+    (MONEY, "currency"),
+];
+
+const BASE_UNITS: [&str; 8] = ["m", "kg", "s", "A", "K", "mol", "cd", "currency"];
+
+impl Dimension {
+    pub fn is_none(self) -> bool {
+        self == NONE
+    }
+
+    fn add(self, other: Dimension, times: i32) -> Dimension {
+        let mut result = self.0;
+        for (power, other) in result.iter_mut().zip(other.0) {
+            *power += (other as i32 * times) as i8;
+        }
+        Dimension(result)
+    }
+
+    // "length", or the SI base units for unnamed ones, like "m^2*kg/s"
+    pub fn describe(self) -> String {
+        if let Some((_, name)) = DIMENSION_NAMES.iter().find(|(d, _)| *d == self) {
+            return name.to_string();
+        }
+        let terms: Vec<(String, i32)> = BASE_UNITS
+            .iter()
+            .zip(self.0)
+            .filter(|(_, power)| *power != 0)
+            .map(|(name, power)| (name.to_string(), power as i32))
+            .collect();
+        format_terms(&terms)
+    }
+}
+
+// One entry of the unit table
+#[derive(Debug, PartialEq)]
+pub struct UnitDef {
+    pub name: &'static str,
+    dimension: Dimension,
+    // The size of one of this unit in SI base units, as exact decimal
+    // text or a fraction: a foot is "0.3048" m, a degree Fahrenheit "5/9" K
+    factor: &'static str,
+    // Added before scaling to SI: 0 degC is (0 + 273.15) * 1 K
+    offset: Option<&'static str>,
+    // Whether SI prefixes apply: km, ms, kWh, but not kmi
+    prefixes: bool,
+    // A shorthand for a combination of other units, like mph for mi/h.
+    // Shorthands are spelled out when multiplied, so mph * h can cancel to mi.
+    expands_to: &'static [(&'static str, i32)],
+}
+
+const fn unit(name: &'static str, dimension: Dimension, factor: &'static str) -> UnitDef {
+    UnitDef {
+        name,
+        dimension,
+        factor,
+        offset: None,
+        prefixes: false,
+        expands_to: &[],
+    }
+}
+
+const fn si(name: &'static str, dimension: Dimension, factor: &'static str) -> UnitDef {
+    UnitDef {
+        prefixes: true,
+        ..unit(name, dimension, factor)
+    }
+}
+
+const fn offset(name: &'static str, factor: &'static str, offset: &'static str) -> UnitDef {
+    UnitDef {
+        offset: Some(offset),
+        ..unit(name, TEMPERATURE, factor)
+    }
+}
+
+const fn shorthand(name: &'static str, expands_to: &'static [(&'static str, i32)]) -> UnitDef {
+    UnitDef {
+        expands_to,
+        ..unit(name, NONE, "1")
+    }
+}
+
+pub const UNITS: &[UnitDef] = &[
+    // SI base units; the gram rather than the kilogram takes the prefixes
+    si("m", LENGTH, "1"),
+    si("g", MASS, "0.001"),
+    si("s", TIME, "1"),
+    si("A", CURRENT, "1"),
+    si("K", TEMPERATURE, "1"),
+    si("mol", AMOUNT, "1"),
+    si("cd", LUMINOSITY, "1"),
+    // Named SI units
+    si("L", VOLUME, "0.001"),
+    si("Hz", FREQUENCY, "1"),
+    si("N", FORCE, "1"),
+    si("J", ENERGY, "1"),
+    si("W", POWER, "1"),
+    si("Pa", PRESSURE, "1"),
+    si("C", CHARGE, "1"),
+    si("V", VOLTAGE, "1"),
+    si("ohm", RESISTANCE, "1"),
+    si("Wh", ENERGY, "3600"),
+    si("eV", ENERGY, "1.602176634e-19"),
+    si("cal", ENERGY, "4.184"),
+    si("bar", PRESSURE, "100000"),
+    // Everyday units
+    unit("min", TIME, "60"),
+    unit("h", TIME, "3600"),
+    unit("day", TIME, "86400"),
+    unit("week", TIME, "604800"),
+    unit("year", TIME, "31557600"),
+    unit("t", MASS, "1000"),
+    unit("ha", AREA, "10000"),
+    unit("atm", PRESSURE, "101325"),
+    // Imperial and US customary units
+    unit("inch", LENGTH, "0.0254"),
+    unit("ft", LENGTH, "0.3048"),
+    unit("yd", LENGTH, "0.9144"),
+    unit("mi", LENGTH, "1609.344"),
+    unit("nmi", LENGTH, "1852"),
+    unit("acre", AREA, "4046.8564224"),
+    unit("gal", VOLUME, "0.003785411784"),
+    unit("oz", MASS, "0.028349523125"),
+    unit("lb", MASS, "0.45359237"),
+    unit("lbf", FORCE, "4.4482216152605"),
+    unit("psi", PRESSURE, "6894.757293168361"),
+    unit("hp", POWER, "745.69987158227022"),
+    shorthand("mph", &[("mi", 1), ("h", -1)]),
+    shorthand("kph", &[("km", 1), ("h", -1)]),
+    shorthand("knot", &[("nmi", 1), ("h", -1)]),
+    // Temperatures on scales that don't start at absolute zero
+    offset("degC", "1", "273.15"),
+    offset("degF", "5/9", "459.67"),
+];
+
+// What every currency points at; its size comes from the rate table
+static CURRENCY: UnitDef = unit("currency", MONEY, "1");
+
+// SI prefixes as powers of ten; "da" comes first so "dam" isn't read as deci-am
+const PREFIXES: &[(&str, i32)] = &[
+    ("da", 1),
+    ("h", 2),
+    ("k", 3),
+    ("M", 6),
+    ("G", 9),
+    ("T", 12),
+    ("P", 15),
+    ("E", 18),
+    ("d", -1),
+    ("c", -2),
+    ("m", -3),
+    ("u", -6),
+    ("µ", -6),
+    ("n", -9),
+    ("p", -12),
+    ("f", -15),
+    ("a", -18),
+];
+
+// A unit from the table, possibly with a prefix: km is ("k", 3) and m.
+// Currencies all share one table entry and are told apart by their code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnitRef {
+    prefix: Option<(&'static str, i32)>,
+    def: &'static UnitDef,
+    code: Option<[u8; 3]>,
+}
+
+// Finds "km", "degC", "mph" and so on; names without a prefix win,
+// so "min" is minutes rather than milli-"in". Any currency code is
+// accepted here; whether the rate table knows it is checked on use.
+pub fn lookup(name: &str) -> Option<UnitRef> {
+    if let Some(def) = UNITS.iter().find(|def| def.name == name) {
+        return Some(UnitRef {
+            prefix: None,
+            def,
+            code: None,
+        });
+    }
+    if currency::is_code(name) {
+        let code = name.as_bytes().try_into().ok()?;
+        return Some(UnitRef {
+            prefix: None,
+            def: &CURRENCY,
+            code: Some(code),
+        });
+    }
+    PREFIXES.iter().find_map(|&(symbol, power)| {
+        let rest = name.strip_prefix(symbol)?;
+        let def = UNITS.iter().find(|def| def.name == rest && def.prefixes)?;
+        Some(UnitRef {
+            prefix: Some((symbol, power)),
+            def,
+            code: None,
+        })
+    })
+}
+
+impl UnitRef {
+    pub fn name(&self) -> String {
+        match (self.code, self.prefix) {
+            (Some(code), _) => String::from_utf8_lossy(&code).into_owned(),
+            (None, Some((symbol, _))) => format!("{}{}", symbol, self.def.name),
+            (None, None) => self.def.name.to_string(),
+        }
+    }
+
+    fn dimension(&self) -> Dimension {
+        self.expanded()
+            .iter()
+            .fold(self.def.dimension, |total, (unit, power)| {
+                total.add(unit.dimension(), *power)
+            })
+    }
+
+    // Err holds the currency code when the rate table doesn't have it
+    fn factor(&self, rates: &Rates) -> Result<Rational, String> {
+        if self.code.is_some() {
+            let code = self.name();
+            return rates.value_of(&code).ok_or(code);
+        }
+        let mut factor = parse_exact(self.def.factor);
+        if let Some((_, power)) = self.prefix {
+            let scale = BigInt::pow10(power.unsigned_abs() as usize);
+            factor = if power < 0 {
+                Rational::new(factor.numer().clone(), factor.denom() * &scale)
+            } else {
+                Rational::new(factor.numer() * &scale, factor.denom().clone())
+            }
+            .expect("nonzero denominator");
+        }
+        for (unit, power) in self.expanded() {
+            factor = &factor * &powi(&unit.factor(rates)?, power);
+        }
+        Ok(factor)
+    }
+
+    // What a shorthand like mph stands for; empty for ordinary units
+    fn expanded(&self) -> Vec<(UnitRef, i32)> {
+        self.def
+            .expands_to
+            .iter()
+            .filter_map(|&(name, power)| Some((lookup(name)?, power)))
+            .collect()
+    }
+}
+
+// Unit factors are short and powers small, so this never hits the size limit
+fn powi(factor: &Rational, power: i32) -> Rational {
+    factor
+        .pow(power as i64)
+        .expect("small power of a nonzero factor")
+}
+
+// "0.3048" or "5/9" as an exact fraction; the unit table only holds valid text
+fn parse_exact(text: &str) -> Rational {
+    let parse = |text: &str| Rational::from(&Decimal::parse(text).expect("valid unit factor"));
+    match text.split_once('/') {
+        Some((num, den)) => parse(num)
+            .checked_div(&parse(den))
+            .expect("nonzero unit factor"),
+        None => parse(text),
+    }
+}
+
+// A product of units with powers, like km/h ([km^1, h^-1]) or m/s^2
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UnitExpr {
+    terms: Vec<(UnitRef, i32)>,
+}
+
+impl UnitExpr {
+    pub fn new(terms: Vec<(UnitRef, i32)>) -> Self {
+        UnitExpr { terms }
+    }
+
+    // A unit as the parser wrote it down, or the first name that isn't a unit
+    pub fn lookup(spec: &[(String, i32)]) -> Result<UnitExpr, String> {
+        let terms = spec
+            .iter()
+            .map(|(name, power)| lookup(name).map(|unit| (unit, *power)).ok_or(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(UnitExpr { terms })
+    }
+
+    // The unit as names with powers, the form `lookup` takes back
+    pub fn spec(&self) -> Vec<(String, i32)> {
+        self.terms
+            .iter()
+            .map(|(unit, power)| (unit.name(), *power))
+            .collect()
+    }
+
+    pub fn kelvin() -> UnitExpr {
+        UnitExpr::lookup(&[("K".to_string(), 1)]).expect("kelvin is in the table")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        self.terms.iter().fold(NONE, |total, (unit, power)| {
+            total.add(unit.dimension(), *power)
+        })
+    }
+
+    // The size of one of this unit in SI base units (and the base currency).
+    // Err holds the first currency code the rate table doesn't have.
+    pub fn factor(&self, rates: &Rates) -> Result<Rational, String> {
+        let mut total = Rational::from(BigInt::one());
+        for (unit, power) in &self.terms {
+            total = &total * &powi(&unit.factor(rates)?, *power);
+        }
+        Ok(total)
+    }
+
+    // The codes of the currencies in this unit, like ["EUR"] for EUR/kg
+    pub fn currencies(&self) -> Vec<String> {
+        self.terms
+            .iter()
+            .filter(|(unit, _)| unit.code.is_some())
+            .map(|(unit, _)| unit.name())
+            .collect()
+    }
+
+    // The offset of a lone temperature unit like degC; in anything more
+    // complicated (degC/s) a temperature can only mean a difference
+    pub fn offset(&self) -> Option<Rational> {
+        match self.terms.as_slice() {
+            [(unit, 1)] if unit.prefix.is_none() => unit.def.offset.map(parse_exact),
+            _ => None,
+        }
+    }
+
+    // The unit of a product (`times` = 1) or quotient (`times` = -1).
+    // Units of the same kind are merged into the first one, so km * m is
+    // km^2 and h / min cancels out completely.
+    pub fn combine(&self, other: &UnitExpr, times: i32) -> UnitExpr {
+        if self.is_empty() || other.is_empty() {
+            let mut terms = self.terms.clone();
+            terms.extend(
+                other
+                    .terms
+                    .iter()
+                    .map(|&(unit, power)| (unit, power * times)),
+            );
+            return UnitExpr { terms };
+        }
+        let mut terms: Vec<(UnitRef, i32)> = Vec::new();
+        let incoming = self.spelled_out().into_iter().chain(
+            other
+                .spelled_out()
+                .into_iter()
+                .map(|(unit, power)| (unit, power * times)),
+        );
+        for (unit, power) in incoming {
+            match terms
+                .iter_mut()
+                .find(|(existing, _)| existing.dimension() == unit.dimension())
+            {
+                Some((_, existing_power)) => *existing_power += power,
+                None => terms.push((unit, power)),
+            }
+        }
+        terms.retain(|(_, power)| *power != 0);
+        UnitExpr { terms }
+    }
+
+    // Every power multiplied by `exp`, so (3 m)^2 is in m^2 and
+    // (4 m^2)^0.5 in m. None if a power would stop being a whole number.
+    pub fn powf(&self, exp: f64) -> Option<UnitExpr> {
+        let terms = self
+            .terms
+            .iter()
+            .map(|&(unit, power)| {
+                let scaled = power as f64 * exp;
+                (scaled.fract() == 0.0 && scaled.abs() <= i8::MAX as f64)
+                    .then_some((unit, scaled as i32))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(UnitExpr { terms })
+    }
+
+    // Shorthands replaced by what they stand for
+    fn spelled_out(&self) -> Vec<(UnitRef, i32)> {
+        let mut terms = Vec::new();
+        for &(unit, power) in &self.terms {
+            let expanded = unit.expanded();
+            if expanded.is_empty() {
+                terms.push((unit, power));
+            } else {
+                terms.extend(
+                    expanded
+                        .into_iter()
+                        .map(|(unit, inner)| (unit, inner * power)),
+                );
+            }
+        }
+        terms
+    }
+
+    // "km (length)" for error messages
+    pub fn describe(&self) -> String {
+        if self.is_empty() {
+            "a plain number".to_string()
+        } else {
+            format!("{} ({})", self, self.dimension().describe())
+        }
+    }
+}
+
+// "km/h", "kg*m^2/s^2", or "s^-1" when there is nothing on top.
+// This is also how units are written in input, so results can be pasted back.
+pub fn format_terms(terms: &[(String, i32)]) -> String {
+    let term = |name: &str, power: i32| {
+        if power == 1 {
+            name.to_string()
+        } else {
+            format!("{}^{}", name, power)
+        }
+    };
+    let above: Vec<String> = terms
+        .iter()
+        .filter(|(_, power)| *power > 0)
+        .map(|(name, power)| term(name, *power))
+        .collect();
+    if above.is_empty() {
+        let below: Vec<String> = terms
+            .iter()
+            .map(|(name, power)| term(name, *power))
+            .collect();
+        return below.join("*");
+    }
+    let mut text = above.join("*");
+    for (name, power) in terms.iter().filter(|(_, power)| *power < 0) {
+        text.push('/');
+        text.push_str(&term(name, -power));
+    }
+    text
+}
+
+impl fmt::Display for UnitExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let terms: Vec<(String, i32)> = self
+            .terms
+            .iter()
+            .map(|(unit, power)| (unit.name(), *power))
+            .collect();
+        write!(f, "{}", format_terms(&terms))
+    }
+}
+
+// A number with a unit, like 5 km. The number is never itself a quantity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: Value,
+    pub unit: UnitExpr,
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            // (2 + 3i) km, since 2 + 3i km reads as 2 + 3i kilometres
+            Value::Complex(_) => write!(f, "({}) {}", self.value, self.unit),
+            value => write!(f, "{} {}", value, self.unit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc;
+
+    fn unit(names: &[(&str, i32)]) -> UnitExpr {
+        let spec: Vec<(String, i32)> = names.iter().map(|&(n, p)| (n.to_string(), p)).collect();
+        UnitExpr::lookup(&spec).unwrap()
+    }
+
+    #[test]
+    fn lookup_prefers_plain_names() {
+        assert_eq!(lookup("km").unwrap().name(), "km");
+        assert_eq!(lookup("min").unwrap().def.name, "min");
+        assert!(lookup("kmph").is_none());
+        assert!(lookup("xyz").is_none());
+        assert_eq!(
+            UnitExpr::lookup(&[("furlong".to_string(), 1)]),
+            Err("furlong".to_string())
+        );
+    }
+
+    #[test]
+    fn dimensions_and_factors() {
+        let none = Rates::default();
+        assert_eq!(
+            unit(&[("km", 1), ("h", -1)]).dimension().describe(),
+            "speed"
+        );
+        assert_eq!(
+            unit(&[("m", 2), ("kg", 1), ("s", -1)])
+                .dimension()
+                .describe(),
+            "m^2*kg/s"
+        );
+        assert_eq!(
+            unit(&[("mph", 1)]).dimension(),
+            unit(&[("m", 1), ("s", -1)]).dimension()
+        );
+        assert_eq!(unit(&[("km", 1)]).factor(&none), Ok(parse_exact("1000")));
+        assert_eq!(unit(&[("degF", 1)]).factor(&none), Ok(parse_exact("5/9")));
+    }
+
+    #[test]
+    fn conversions_are_exact() {
+        assert_eq!(calc("60 mph * 2.5 h"), "150 mi");
+        assert_eq!(calc("1 mi to m"), "1609.344 m");
+        assert_eq!(calc("1 kWh in J"), "3600000 J");
+        assert_eq!(calc("2 km * 500 m"), "1 km^2");
+    }
+
+    #[test]
+    fn temperatures_use_offsets() {
+        assert_eq!(calc("100 degC in degF"), "212 degF");
+        assert_eq!(calc("20 degC + 5 K"), "25 degC");
+    }
+
+    #[test]
+    fn incompatible_units_are_errors() {
+        assert_eq!(
+            calc("3 m + 2 s"),
+            "Incompatible units: m (length) and s (time)"
+        );
+        assert_eq!(
+            calc("5 km in s"),
+            "Incompatible units: km (length) and s (time)"
+        );
+    }
+
+    #[test]
+    fn units_after_any_operand() {
+        assert_eq!(calc("(1/3) m * 3 in cm"), "100 cm");
+        assert_eq!(calc("1/3 m * 3 in cm"), "100 cm");
+        // Only a number over a number is a fraction
+        assert_eq!(calc("60 km / 2 h"), "30 km/h");
+        assert_eq!(calc("sqrt(16) m"), "4 m");
+        assert_eq!(calc("3! s"), "6 s");
+        assert_eq!(calc("[1, 2] km"), "[1 km, 2 km]");
+        assert_eq!(calc("(2 + 3i) km"), "(2 + 3i) km");
+        assert_eq!(calc("pi m"), "3.141592653589793 m");
+        let mut ctx = crate::Context::new();
+        ctx.run("x = 4").unwrap();
+        assert_eq!(ctx.evaluate("x km").unwrap().to_string(), "4 km");
+        // A variable named like a unit doesn't change what follows a number
+        ctx.run("m = 5").unwrap();
+        assert_eq!(ctx.evaluate("3 m").unwrap().to_string(), "3 m");
+        assert_eq!(ctx.evaluate("3 * m").unwrap().to_string(), "15");
+    }
+}
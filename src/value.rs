@@ -0,0 +1,209 @@
+// What an expression evaluates to. Which kind of number a calculation uses
+// depends on the session's number mode: exact fractions where possible and
+// `f64` otherwise by default, decimals after `mode decimal`, or whole
+// numbers of a fixed width after e.g. `mode u8`. Anything involving `i` is
+// complex in the first two modes.
+
+use std::fmt;
+
+use crate::bigint::BigInt;
+use crate::complex::Complex;
+use crate::decimal::{Decimal, DecimalContext};
+use crate::error::{CalcError, Span};
+use crate::integer::IntType;
+use crate::matrix::Matrix;
+use crate::rational::Rational;
+use crate::units::Quantity;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Real(f64),
+    Decimal(Decimal),
+    // An exact whole number or fraction, like 12 or 1/3
+    Rational(Rational),
+    // Always has a nonzero imaginary part; see `Value::complex`
+    Complex(Complex),
+    // A number with a unit, like 5 km
+    Quantity(Box<Quantity>),
+    // A vector or matrix of any of the above
+    Matrix(Box<Matrix>),
+}
+
+// Which kind of number literals and results are
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NumberMode {
+    // Whole numbers and fractions stay exact (1/3 + 1/6 is 1/2), everything
+    // else is binary floating point (0.1 + 0.2 is 0.30000000000000004)
+    #[default]
+    Float,
+    // Decimal with a chosen number of significant digits; 0.1 + 0.2 is 0.3
+    Decimal,
+    // Whole numbers of a fixed width, like u8 or i64; 7 / 2 is 3
+    Integer(IntType),
+}
+
+impl NumberMode {
+    pub fn parse(name: &str) -> Option<NumberMode> {
+        match name {
+            "float" => Some(NumberMode::Float),
+            "decimal" => Some(NumberMode::Decimal),
+            _ => IntType::parse(name).map(NumberMode::Integer),
+        }
+    }
+}
+
+impl fmt::Display for NumberMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NumberMode::Float => write!(f, "float"),
+            NumberMode::Decimal => write!(f, "decimal"),
+            NumberMode::Integer(int) => write!(f, "{}", int),
+        }
+    }
+}
+
+impl Value {
+    // A complex result that turned out to be real, like i * i, becomes an
+    // ordinary number again
+    pub fn complex(z: Complex) -> Value {
+        if z.im == 0.0 {
+            Value::Real(z.re)
+        } else {
+            Value::Complex(z)
+        }
+    }
+
+    // NaN for complex values, quantities and matrices, which have no plain
+    // real value to give
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Value::Real(x) => *x,
+            Value::Decimal(d) => d.to_f64(),
+            Value::Rational(r) => r.to_f64(),
+            Value::Complex(_) | Value::Quantity(_) | Value::Matrix(_) => f64::NAN,
+        }
+    }
+
+    // `to_f64` for a number that has to become an f64 to go on, like the
+    // 10^400 of 10^400 * 1.5 or ln(10^400). Past the largest f64 it is an
+    // overflow and too close to 0 an underflow, instead of inf or 0.
+    // The span is filled in by the caller.
+    pub fn to_float(&self) -> Result<f64, CalcError> {
+        let span = Span::default();
+        let x = self.to_f64();
+        let nonzero = match self {
+            Value::Rational(r) => !r.is_zero(),
+            Value::Decimal(d) => !d.is_zero(),
+            _ => x != 0.0,
+        };
+        if x.is_infinite() {
+            Err(CalcError::Overflow { span })
+        } else if x == 0.0 && nonzero {
+            Err(CalcError::Underflow { span })
+        } else {
+            Ok(x)
+        }
+    }
+
+    pub fn to_complex(&self) -> Complex {
+        match self {
+            Value::Complex(z) => *z,
+            real => Complex::from(real.to_f64()),
+        }
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, Value::Complex(_))
+    }
+
+    pub fn as_quantity(&self) -> Option<&Quantity> {
+        match self {
+            Value::Quantity(q) => Some(q),
+            _ => None,
+        }
+    }
+
+    // An amount of money, which is only as good as the exchange rates
+    pub fn is_money(&self) -> bool {
+        self.as_quantity()
+            .is_some_and(|q| !q.unit.currencies().is_empty())
+    }
+
+    // Values computed in float mode carry over into decimal mode as the
+    // number they print as, so a stored 0.1 stays 0.1. Complex values have
+    // no decimal form, and neither do quantities or matrices; callers deal
+    // with those before converting.
+    pub fn to_decimal(&self, ctx: DecimalContext) -> Decimal {
+        match self {
+            // Literals too big for an f64 and operations that overflow are
+            // errors, so evaluation never produces NaN or infinity and this
+            // always converts
+            Value::Real(x) => Decimal::from_f64(*x).unwrap_or_default().round(ctx),
+            Value::Decimal(d) => d.round(ctx),
+            Value::Rational(r) => r.to_decimal(ctx),
+            Value::Complex(z) => Decimal::from_f64(z.re).unwrap_or_default().round(ctx),
+            Value::Quantity(q) => q.value.to_decimal(ctx),
+            Value::Matrix(_) => Decimal::zero(),
+        }
+    }
+
+    // The exact fraction, if this value is one
+    pub fn as_rational(&self) -> Option<&Rational> {
+        match self {
+            Value::Rational(r) => Some(r),
+            _ => None,
+        }
+    }
+
+    // The whole number this value is, whatever kind of number holds it:
+    // 12, 12.0 and 24/2 all give 12
+    pub fn as_whole(&self) -> Option<BigInt> {
+        let exact = match self {
+            Value::Rational(r) => r.clone(),
+            Value::Decimal(d) => Rational::from(d),
+            // Formatting without decimals writes out every digit of a whole f64
+            Value::Real(x) if x.is_finite() && x.fract() == 0.0 => {
+                return BigInt::parse(&format!("{:.0}", x));
+            }
+            _ => return None,
+        };
+        exact.is_integer().then(|| exact.numer().clone())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Real(x)
+    }
+}
+
+impl From<Decimal> for Value {
+    fn from(d: Decimal) -> Self {
+        Value::Decimal(d)
+    }
+}
+
+impl From<Complex> for Value {
+    fn from(z: Complex) -> Self {
+        Value::complex(z)
+    }
+}
+
+impl From<Rational> for Value {
+    fn from(r: Rational) -> Self {
+        Value::Rational(r)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Real(x) => write!(f, "{}", x),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Rational(r) => write!(f, "{}", r),
+            Value::Complex(z) => write!(f, "{}", z),
+            Value::Quantity(q) => write!(f, "{}", q),
+            Value::Matrix(m) => write!(f, "{}", m),
+        }
+    }
+}